@@ -0,0 +1,146 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+//! Benchmarks of calling PL/Rust functions, compared to calling the same function written in Rust
+//! as a C function, and to calling it without the call site's cache.
+//!
+//! Each reports what it measured in a NOTICE, and fails if PL/Rust is slower than it should be.
+//! The bounds are ratios of timings taken in the same backend, so they hold on any machine, and in
+//! the debug builds the tests run against.
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use pgrx::prelude::*;
+
+    const ROWS: i64 = 1_000_000;
+
+    /// Fewer rows for calls that aren't cached, which are slow, and trace what they look up
+    const UNCACHED_ROWS: i64 = 100_000;
+
+    /// How many times the per-row call overhead of [`add_one_c()`] a PL/Rust function's may be.
+    /// Both are called through `pgrx`'s function wrapper, so the difference is what PL/Rust adds
+    /// on top:  finding the function cached at the call site, and calling into its library
+    const MAX_CALL_OVERHEAD: f64 = 4.0;
+
+    /// How much less per-row call overhead a PL/Rust function must have when its call site caches
+    /// it than when every call looks it up
+    const MIN_CACHING_SPEEDUP: f64 = 1.25;
+
+    /// How many times longer a query calling a PL/Rust function per row may take with
+    /// `plrust.track_functions` on
+    const MAX_TRACKING_OVERHEAD: f64 = 1.5;
+
+    /// The least per-row overhead, in nanoseconds, a C function is taken to have.  Its overhead
+    /// can be lost in the noise of the baseline, which would make any overhead PL/Rust has too much
+    const MIN_C_CALL: f64 = 5.0;
+
+    /// The function `add_one` is, written as a C function
+    #[pg_extern(immutable, strict)]
+    fn add_one_c(i: i64) -> i64 {
+        i + 1
+    }
+
+    fn create_add_one() -> spi::Result<()> {
+        Spi::run(
+            "CREATE FUNCTION add_one(i bigint) RETURNS bigint STRICT IMMUTABLE LANGUAGE plrust AS $$
+                Ok(Some(i + 1))
+            $$",
+        )?;
+        // the first call loads the function, which we don't want to measure
+        Spi::run("SELECT add_one(1)")
+    }
+
+    /// How long `query` took, at best, over a few runs
+    fn time(query: &str) -> spi::Result<Duration> {
+        let mut best = Duration::MAX;
+        for _ in 0..3 {
+            let start = Instant::now();
+            Spi::run(query)?;
+            best = best.min(start.elapsed());
+        }
+        Ok(best)
+    }
+
+    /// The per-row overhead, in nanoseconds, of calling `call` (with `i` for its argument) over
+    /// `rows` rows
+    fn call_overhead(call: &str, rows: i64) -> spi::Result<f64> {
+        let baseline = time(&format!("SELECT sum(i) FROM generate_series(1, {rows}) i"))?;
+        let calls = time(&format!(
+            "SELECT sum({call}) FROM generate_series(1, {rows}) i"
+        ))?;
+        Ok(calls.saturating_sub(baseline).as_nanos() as f64 / rows as f64)
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn benchmark_per_row_calls() -> spi::Result<()> {
+        create_add_one()?;
+
+        let builtin_call = call_overhead("int8pl(i, 1)", ROWS)?;
+        let c_call = call_overhead("tests.add_one_c(i)", ROWS)?;
+        let plrust_call = call_overhead("add_one(i)", ROWS)?;
+
+        let overhead = plrust_call / c_call.max(MIN_C_CALL);
+        notice!(
+            "per-row call overhead:  int8pl() {builtin_call:.1}ns, C {c_call:.1}ns, PL/Rust {plrust_call:.1}ns ({overhead:.1}x C)"
+        );
+        assert!(
+            overhead <= MAX_CALL_OVERHEAD,
+            "calling PL/Rust has {overhead:.1}x the overhead of calling a C function, more than {MAX_CALL_OVERHEAD}x"
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn benchmark_per_row_calls_uncached() -> spi::Result<()> {
+        create_add_one()?;
+
+        Spi::run("SET plrust.debug_uncached_calls = on")?;
+        let uncached = call_overhead("add_one(i)", UNCACHED_ROWS)?;
+        Spi::run("SET plrust.debug_uncached_calls = off")?;
+        let cached = call_overhead("add_one(i)", UNCACHED_ROWS)?;
+
+        let speedup = uncached / cached.max(MIN_C_CALL);
+        notice!(
+            "per-row PL/Rust call overhead:  {cached:.1}ns cached, {uncached:.1}ns uncached ({speedup:.1}x)"
+        );
+        assert!(
+            speedup >= MIN_CACHING_SPEEDUP,
+            "caching calls made them only {speedup:.2}x faster, less than {MIN_CACHING_SPEEDUP}x"
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn benchmark_per_row_calls_tracked() -> spi::Result<()> {
+        create_add_one()?;
+
+        let query = format!("SELECT sum(add_one(i)) FROM generate_series(1, {ROWS}) i");
+        Spi::run("SET plrust.track_functions = off")?;
+        let untracked = time(&query)?;
+        Spi::run("SET plrust.track_functions = on")?;
+        let tracked = time(&query)?;
+
+        notice!(
+            "per-row PL/Rust call:  {:.1}ns, {:.1}ns with `plrust.track_functions`",
+            untracked.as_nanos() as f64 / ROWS as f64,
+            tracked.as_nanos() as f64 / ROWS as f64
+        );
+        let overhead = tracked.as_secs_f64() / untracked.as_secs_f64();
+        assert!(
+            overhead <= MAX_TRACKING_OVERHEAD,
+            "`plrust.track_functions` made calls take {overhead:.2}x as long, more than {MAX_TRACKING_OVERHEAD}x"
+        );
+        Ok(())
+    }
+}
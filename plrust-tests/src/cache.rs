@@ -0,0 +1,27 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_clear_shared_library_cache() -> spi::Result<()> {
+        Spi::run("CREATE FUNCTION uncached() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$")?;
+        assert_eq!(Ok(Some(1)), Spi::get_one::<i32>("SELECT uncached()"));
+
+        // the tests don't turn on `plrust.shared_library_cache`, so there's nothing to clear
+        assert_eq!(
+            Ok(Some(0)),
+            Spi::get_one::<i64>("SELECT plrust.clear_shared_library_cache()")
+        );
+        Ok(())
+    }
+}
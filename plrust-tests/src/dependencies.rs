@@ -79,6 +79,91 @@ mod tests {
         Ok(())
     }
 
+    #[pg_test]
+    #[cfg(not(feature = "sandboxed"))]
+    #[search_path(@extschema@)]
+    fn plrust_deps_feature_subset() -> spi::Result<()> {
+        let definition = r#"
+                CREATE FUNCTION tokio_rt() RETURNS TEXT
+                IMMUTABLE STRICT
+                LANGUAGE PLRUST AS
+            $$
+            [dependencies]
+                tokio = { version = "1", features = ["rt"] }
+            [code]
+                let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+                Ok(Some(rt.block_on(async { "hello".to_string() })))
+            $$;
+        "#;
+        Spi::run(definition)?;
+
+        let retval = Spi::get_one::<String>("SELECT tokio_rt();");
+        assert_eq!(retval, Ok(Some("hello".to_string())));
+        Ok(())
+    }
+
+    #[pg_test]
+    #[cfg(not(feature = "sandboxed"))]
+    #[search_path(@extschema@)]
+    #[should_panic(expected = "Feature `fs` of `tokio` is not permitted")]
+    fn plrust_deps_feature_not_permitted() {
+        let definition = r#"
+                CREATE FUNCTION tokio_fs() RETURNS TEXT
+                IMMUTABLE STRICT
+                LANGUAGE PLRUST AS
+            $$
+            [dependencies]
+                tokio = { version = "1", features = ["rt", "fs"] }
+            [code]
+                Ok(Some("hello".to_string()))
+            $$;
+        "#;
+        Spi::run(definition).unwrap();
+    }
+
+    #[pg_test]
+    #[cfg(not(feature = "sandboxed"))]
+    #[search_path(@extschema@)]
+    fn plrust_deps_recorded() -> spi::Result<()> {
+        let definition = r#"
+                CREATE FUNCTION colorize(input TEXT) RETURNS TEXT
+                IMMUTABLE STRICT
+                LANGUAGE PLRUST AS
+            $$
+            [dependencies]
+                owo-colors = "3"
+            [code]
+                use owo_colors::OwoColorize;
+                Ok(Some(input.purple().to_string()))
+            $$;
+        "#;
+        Spi::run(definition)?;
+
+        let recorded = Spi::get_one::<String>(
+            "SELECT string_agg(format('%s %s', name, version), ', ')
+               FROM plrust.function_dependencies
+              WHERE function = 'colorize(text)'::regprocedure AND direct",
+        )?;
+        assert_eq!(recorded, Some("owo-colors 3.5.0".to_string()));
+
+        // removing the crate, or narrowing it to another version, breaks the function
+        let broken = Spi::get_one::<String>(
+            "SELECT string_agg(function::text, ', ') FROM plrust.dependency_impact('owo-colors')",
+        )?;
+        assert_eq!(broken, Some("colorize(text)".to_string()));
+
+        let broken = Spi::get_one::<String>(
+            "SELECT string_agg(function::text, ', ')
+               FROM plrust.dependency_impact('owo-colors', '=3.5.0', narrowed_to => '=3.4.0')",
+        )?;
+        assert_eq!(broken, Some("colorize(text)".to_string()));
+
+        // but other crates don't
+        let broken = Spi::get_one::<i64>("SELECT count(*) FROM plrust.dependency_impact('tokio')")?;
+        assert_eq!(broken, Some(0));
+        Ok(())
+    }
+
     #[pg_test]
     #[cfg(not(feature = "sandboxed"))]
     #[search_path(@extschema@)]
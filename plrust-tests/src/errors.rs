@@ -0,0 +1,125 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_pg_error() -> spi::Result<()> {
+        Spi::run(
+            r#"CREATE FUNCTION check_quantity(quantity int) RETURNS int STRICT LANGUAGE plrust AS $$
+                if quantity <= 0 {
+                    return Err(PgError::new(PgSqlErrorCode::ERRCODE_CHECK_VIOLATION, "quantity must be positive")
+                        .set_detail(format!("quantity was {quantity}"))
+                        .set_hint("Use a refund to return items.")
+                        .into());
+                }
+                Ok(Some(quantity))
+            $$"#,
+        )?;
+        Spi::run(
+            "CREATE FUNCTION caught(quantity int) RETURNS text LANGUAGE plpgsql AS $$
+            DECLARE
+                detail text;
+                hint text;
+            BEGIN
+                PERFORM check_quantity(quantity);
+                RETURN 'ok';
+            EXCEPTION WHEN check_violation THEN
+                GET STACKED DIAGNOSTICS detail = PG_EXCEPTION_DETAIL, hint = PG_EXCEPTION_HINT;
+                RETURN SQLERRM || ' / ' || detail || ' / ' || hint;
+            END;
+            $$",
+        )?;
+
+        assert_eq!(Ok(Some("ok")), Spi::get_one::<&str>("SELECT caught(1)"));
+        assert_eq!(
+            Ok(Some(
+                "quantity must be positive / quantity was -1 / Use a refund to return items."
+            )),
+            Spi::get_one::<&str>("SELECT caught(-1)")
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_pg_error_fields() -> spi::Result<()> {
+        Spi::run(
+            r#"CREATE FUNCTION check_stock(stock int) RETURNS int STRICT LANGUAGE plrust AS $$
+                if stock < 0 {
+                    return Err(PgError::new(PgSqlErrorCode::ERRCODE_CHECK_VIOLATION, "stock can't be negative")
+                        .set_column("stock")
+                        .set_constraint("stock_not_negative")
+                        .set_table("inventory")
+                        .into());
+                }
+                if stock > 1000 {
+                    return Err(PgError::new(PgSqlErrorCode::ERRCODE_CHECK_VIOLATION, "too much stock").into());
+                }
+                Ok(Some(stock))
+            $$"#,
+        )?;
+        Spi::run(
+            "CREATE FUNCTION caught(stock int) RETURNS text LANGUAGE plpgsql AS $$
+            DECLARE
+                col text;
+                con text;
+                tab text;
+            BEGIN
+                PERFORM check_stock(stock);
+                RETURN 'ok';
+            EXCEPTION WHEN check_violation THEN
+                GET STACKED DIAGNOSTICS col = COLUMN_NAME, con = CONSTRAINT_NAME, tab = TABLE_NAME;
+                RETURN col || ' / ' || con || ' / ' || tab;
+            END;
+            $$",
+        )?;
+
+        assert_eq!(
+            Ok(Some("stock / stock_not_negative / inventory")),
+            Spi::get_one::<&str>("SELECT caught(-1)")
+        );
+
+        // and they don't linger for the next error
+        assert_eq!(
+            Ok(Some(" /  / ")),
+            Spi::get_one::<&str>("SELECT caught(1001)")
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_other_errors_are_data_exceptions() -> spi::Result<()> {
+        Spi::run(
+            r#"CREATE FUNCTION parse(s text) RETURNS int STRICT LANGUAGE plrust AS $$
+                Ok(Some(s.parse()?))
+            $$"#,
+        )?;
+        Spi::run(
+            "CREATE FUNCTION caught(s text) RETURNS text LANGUAGE plpgsql AS $$
+            BEGIN
+                PERFORM parse(s);
+                RETURN 'ok';
+            EXCEPTION WHEN data_exception THEN
+                RETURN SQLERRM;
+            END;
+            $$",
+        )?;
+
+        assert_eq!(
+            Ok(Some("invalid digit found in string")),
+            Spi::get_one::<&str>("SELECT caught('one')")
+        );
+        Ok(())
+    }
+}
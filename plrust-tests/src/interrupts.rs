@@ -0,0 +1,46 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "canceling statement due to statement timeout"]
+    fn plrust_infinite_loop_statement_timeout() -> spi::Result<()> {
+        Spi::run("CREATE FUNCTION spin() RETURNS int LANGUAGE plrust AS $$ loop {} $$")?;
+        Spi::run("SET statement_timeout = '500ms'")?;
+        Spi::run("SELECT spin()")
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_explicit_check_for_interrupts() -> spi::Result<()> {
+        Spi::run(
+            "CREATE FUNCTION sum_to(n bigint) RETURNS bigint STRICT LANGUAGE plrust AS $$
+                Ok(Some((0..n).inspect(|_| check_for_interrupts()).sum()))
+            $$",
+        )?;
+        assert_eq!(Ok(Some(55)), Spi::get_one::<i64>("SELECT sum_to(11)"));
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_generated_crate_checks_for_interrupts() -> spi::Result<()> {
+        let lib_rs = Spi::get_one::<String>(
+            "SELECT lib_rs FROM plrust.generated_crate('for _ in 0..10 {} Ok(None)', return_type => 'int')",
+        )?
+        .expect("no generated lib.rs");
+        // once at the start of the function and once in the loop, in each of `opened` and `forbidden`
+        assert_eq!(lib_rs.matches("::pgrx::check_for_interrupts();").count(), 4);
+        Ok(())
+    }
+}
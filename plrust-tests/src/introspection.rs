@@ -0,0 +1,142 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_function_info() -> spi::Result<()> {
+        Spi::run(
+            "CREATE FUNCTION introspect_me(a int) RETURNS int LANGUAGE plrust AS $$ Ok(a) $$",
+        )?;
+
+        Spi::connect(|client| {
+            let table = client.select(
+                "SELECT * FROM plrust.function_info('introspect_me(int)')",
+                None,
+                None,
+            )?;
+
+            // we don't cross compile during tests, so there's only this host's target
+            assert_eq!(table.len(), 1);
+            let row = table.first();
+            assert_eq!(
+                row.get_by_name::<String, _>("source")?
+                    .as_deref()
+                    .map(str::trim),
+                Some("Ok(a)")
+            );
+            assert_eq!(
+                row.get_by_name::<String, _>("dependencies")?,
+                Some("".into())
+            );
+            assert!(row
+                .get_by_name::<String, _>("symbol")?
+                .unwrap()
+                .starts_with("plrust_fn_oid_"));
+            assert!(row.get_by_name::<i64, _>("binary_size")?.unwrap() > 0);
+            assert!(row.get_by_name::<i64, _>("stored_size")?.unwrap() > 0);
+            assert!(!row
+                .get_by_name::<Vec<String>, _>("lints")?
+                .unwrap()
+                .is_empty());
+            Ok::<_, spi::Error>(())
+        })?;
+
+        let in_view = Spi::get_one::<bool>(
+            "SELECT EXISTS (SELECT 1 FROM plrust.functions WHERE function = 'introspect_me(int)'::regprocedure)",
+        )?;
+        assert_eq!(in_view, Some(true));
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_functions_view_unreadable() -> spi::Result<()> {
+        Spi::run("CREATE FUNCTION readable() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$")?;
+        Spi::run("CREATE FUNCTION unreadable() RETURNS int LANGUAGE plrust AS $$ Ok(Some(2)) $$")?;
+        Spi::run(
+            "UPDATE pg_catalog.pg_proc SET prosrc = '{\"src\": \"Ok(Some(2))\"' WHERE oid = 'unreadable()'::regprocedure",
+        )?;
+
+        assert_eq!(
+            Ok(Some(true)),
+            Spi::get_one::<bool>(
+                "SELECT source IS NULL AND error IS NOT NULL FROM plrust.functions WHERE function = 'unreadable()'::regprocedure"
+            )
+        );
+        assert_eq!(
+            Ok(Some(true)),
+            Spi::get_one::<bool>(
+                "SELECT binary_size > 0 AND error IS NULL FROM plrust.functions WHERE function = 'readable()'::regprocedure"
+            )
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_generated_crate() -> spi::Result<()> {
+        Spi::run(
+            "CREATE FUNCTION generate_me(a int) RETURNS int STRICT LANGUAGE plrust AS $$ Ok(Some(a)) $$",
+        )?;
+
+        let lib_rs = Spi::get_one::<String>(
+            "SELECT lib_rs FROM plrust.generated_crate('generate_me(int)')",
+        )?
+        .unwrap();
+        assert!(lib_rs.contains("#[pg_extern]"));
+        assert!(lib_rs.contains("Ok(Some(a))"));
+
+        let cargo_toml = Spi::get_one::<String>(
+            "SELECT cargo_toml FROM plrust.generated_crate('generate_me(int)')",
+        )?
+        .unwrap();
+        assert!(cargo_toml.contains("plrust-trusted-pgrx"));
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_generated_crate_hypothetical() -> spi::Result<()> {
+        let lib_rs = Spi::get_one::<String>(
+            "SELECT lib_rs FROM plrust.generated_crate(
+                'Ok(Some(a + b))',
+                argument_names => ARRAY['a', 'b'],
+                argument_types => ARRAY['int', 'int']::regtype[],
+                return_type => 'int',
+                strict => true
+            )",
+        )?
+        .unwrap();
+        assert!(lib_rs.contains("a: i32"));
+        assert!(lib_rs.contains("b: i32"));
+        assert!(lib_rs.contains("Ok(Some(a + b))"));
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "argument names were given for"]
+    fn plrust_generated_crate_hypothetical_mismatched_arguments() -> spi::Result<()> {
+        Spi::run("SELECT * FROM plrust.generated_crate('Ok(None)', argument_names => ARRAY['a'])")
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "is not a PL/Rust function"]
+    fn plrust_function_info_not_plrust() -> spi::Result<()> {
+        Spi::run(
+            "CREATE FUNCTION not_introspectable() RETURNS int LANGUAGE sql AS $$ SELECT 1 $$",
+        )?;
+        Spi::run("SELECT * FROM plrust.function_info('not_introspectable()')")
+    }
+}
@@ -10,17 +10,29 @@ Use of this source code is governed by the PostgreSQL license that can be found
 mod alter;
 mod argument;
 mod basic;
+mod benchmarks;
 mod blocked_code;
 mod borrow_mut_error;
+mod cache;
 mod ddl;
 mod dependencies;
+mod errors;
 mod fn_call;
+mod interrupts;
+mod introspection;
+mod libraries;
+mod loading;
 mod matches;
+mod memory;
 mod panics;
+mod profile;
 mod range;
 mod recursion;
+mod restore;
 mod return_values;
 mod round_trip;
+mod stats;
+mod targets;
 mod time_and_dates;
 mod trusted;
 mod user_defined_types;
@@ -0,0 +1,92 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_library() -> spi::Result<()> {
+        Spi::run(
+            r#"SELECT plrust.register_library('helpers', $lib$
+                /// Parse a `key=value` pair
+                pub fn parse_pair(input: &str) -> Option<(&str, &str)> {
+                    input.split_once('=').map(|(key, value)| (key.trim(), value.trim()))
+                }
+            $lib$, '1.2.0');"#,
+        )?;
+
+        let definition = r#"
+                CREATE FUNCTION pair_value(input TEXT) RETURNS TEXT
+                IMMUTABLE STRICT
+                LANGUAGE PLRUST AS
+            $$
+            [dependencies]
+                helpers = "1"
+            [code]
+                Ok(helpers::parse_pair(input).map(|(_, value)| value.to_string()))
+            $$;
+        "#;
+        Spi::run(definition)?;
+
+        let retval = Spi::get_one::<String>("SELECT pair_value('answer = 42');");
+        assert_eq!(retval, Ok(Some("42".to_string())));
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic(expected = "is a PL/Rust library at version `1.2.0`, which doesn't match")]
+    fn plrust_library_wrong_version() {
+        Spi::run(
+            "SELECT plrust.register_library('helpers', 'pub fn one() -> i32 { 1 }', '1.2.0');",
+        )
+        .unwrap();
+
+        let definition = r#"
+                CREATE FUNCTION one() RETURNS INT
+                IMMUTABLE STRICT
+                LANGUAGE PLRUST AS
+            $$
+            [dependencies]
+                helpers = "2"
+            [code]
+                Ok(Some(helpers::one()))
+            $$;
+        "#;
+        Spi::run(definition).unwrap();
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic(expected = "error: usage of an `unsafe` block")]
+    fn plrust_library_is_linted() {
+        // libraries are held to the same lints as the functions that use them
+        Spi::run(
+            r#"SELECT plrust.register_library('helpers', $lib$
+                pub fn read(ptr: *const i32) -> i32 { unsafe { *ptr } }
+            $lib$);"#,
+        )
+        .unwrap();
+
+        let definition = r#"
+                CREATE FUNCTION zero() RETURNS INT
+                IMMUTABLE STRICT
+                LANGUAGE PLRUST AS
+            $$
+            [dependencies]
+                helpers = "*"
+            [code]
+                Ok(Some(helpers::read(&0)))
+            $$;
+        "#;
+        Spi::run(definition).unwrap();
+    }
+}
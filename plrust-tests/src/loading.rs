@@ -0,0 +1,149 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_loaded_functions() -> spi::Result<()> {
+        Spi::run("CREATE FUNCTION load_me() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$")?;
+        Spi::run("SELECT load_me() FROM generate_series(1, 3)")?;
+        assert_eq!(
+            Ok(Some(3)),
+            Spi::get_one::<i64>(
+                "SELECT calls FROM plrust.loaded_functions() WHERE function = 'load_me()'::regprocedure"
+            )
+        );
+
+        assert_eq!(
+            Ok(Some(1)),
+            Spi::get_one::<i64>("SELECT plrust.unload_functions('load_me()')")
+        );
+        assert_eq!(
+            Ok(Some(0)),
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM plrust.loaded_functions() WHERE function = 'load_me()'::regprocedure"
+            )
+        );
+
+        // it's loaded again when it's next called
+        assert_eq!(Ok(Some(1)), Spi::get_one::<i32>("SELECT load_me()"));
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_max_loaded_functions() -> spi::Result<()> {
+        Spi::run("CREATE FUNCTION first() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$")?;
+        Spi::run("CREATE FUNCTION second() RETURNS int LANGUAGE plrust AS $$ Ok(Some(2)) $$")?;
+        Spi::run("SELECT plrust.unload_functions()")?;
+        Spi::run("SET plrust.max_loaded_functions = 1")?;
+
+        assert_eq!(Ok(Some(1)), Spi::get_one::<i32>("SELECT first()"));
+        assert_eq!(Ok(Some(2)), Spi::get_one::<i32>("SELECT second()"));
+        assert_eq!(
+            Ok(Some("second()".to_string())),
+            Spi::get_one::<String>("SELECT function::text FROM plrust.loaded_functions()")
+        );
+        assert_eq!(Ok(Some(1)), Spi::get_one::<i32>("SELECT first()"));
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_dropped_function_is_unloaded() -> spi::Result<()> {
+        Spi::run("CREATE FUNCTION doomed() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$")?;
+        Spi::run("CREATE FUNCTION survivor() RETURNS int LANGUAGE plrust AS $$ Ok(Some(2)) $$")?;
+        assert_eq!(Ok(Some(1)), Spi::get_one::<i32>("SELECT doomed()"));
+        let doomed = Spi::get_one::<pg_sys::Oid>("SELECT 'doomed()'::regprocedure::oid")?;
+
+        Spi::run("DROP FUNCTION doomed()")?;
+        assert_eq!(Ok(Some(2)), Spi::get_one::<i32>("SELECT survivor()"));
+        assert_eq!(
+            Ok(Some(0)),
+            Spi::get_one_with_args::<i64>(
+                "SELECT count(*) FROM plrust.loaded_functions() WHERE function::oid = $1",
+                vec![(PgBuiltInOids::OIDOID.oid(), doomed.into_datum())]
+            )
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_replaced_function_is_reloaded() -> spi::Result<()> {
+        Spi::run("CREATE FUNCTION replaced() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$")?;
+        assert_eq!(Ok(Some(1)), Spi::get_one::<i32>("SELECT replaced()"));
+        Spi::run(
+            "CREATE OR REPLACE FUNCTION replaced() RETURNS int LANGUAGE plrust AS $$ Ok(Some(2)) $$",
+        )?;
+        assert_eq!(Ok(Some(2)), Spi::get_one::<i32>("SELECT replaced()"));
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_preload_functions() -> spi::Result<()> {
+        Spi::run("CREATE SCHEMA preloaded")?;
+        Spi::run(
+            "CREATE FUNCTION preloaded.one() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$",
+        )?;
+        Spi::run("CREATE FUNCTION preloaded.two(a int, b int) RETURNS int STRICT LANGUAGE plrust AS $$ Ok(Some(a + b)) $$")?;
+        Spi::run("SELECT plrust.unload_functions()")?;
+
+        assert_eq!(
+            Ok(Some(1)),
+            Spi::get_one::<i64>("SELECT plrust.preload_functions('preloaded.two(int, int)')")
+        );
+        Spi::run("SELECT plrust.unload_functions()")?;
+
+        Spi::run("SET plrust.preload_functions = 'preloaded.*'")?;
+        assert_eq!(
+            Ok(Some(2)),
+            Spi::get_one::<i64>("SELECT plrust.preload_functions()")
+        );
+        // they're already loaded
+        assert_eq!(
+            Ok(Some(0)),
+            Spi::get_one::<i64>("SELECT plrust.preload_functions()")
+        );
+        assert_eq!(
+            Ok(Some(0)),
+            Spi::get_one::<i64>(
+                "SELECT sum(calls)::bigint FROM plrust.loaded_functions() WHERE function::text LIKE 'preloaded.%'"
+            )
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "invalid value for parameter \"plrust.preload_functions\""]
+    fn plrust_preload_functions_malformed() -> spi::Result<()> {
+        Spi::run("SET plrust.preload_functions = 'public.one(int'")
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "permission denied for function secret"]
+    fn plrust_preload_functions_requires_execute() -> spi::Result<()> {
+        Spi::run("CREATE SCHEMA guarded")?;
+        Spi::run(
+            "CREATE FUNCTION guarded.secret() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$",
+        )?;
+        Spi::run("REVOKE EXECUTE ON FUNCTION guarded.secret() FROM PUBLIC")?;
+        Spi::run("CREATE ROLE outsider")?;
+        Spi::run("GRANT USAGE ON SCHEMA guarded, plrust TO outsider")?;
+
+        Spi::run("SET ROLE outsider")?;
+        Spi::run("SELECT plrust.preload_functions('guarded.secret()')")
+    }
+}
@@ -0,0 +1,68 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "PL/Rust function allocate(bigint) exceeded `plrust.max_call_memory` of 1024kB"]
+    fn plrust_max_call_memory() -> spi::Result<()> {
+        Spi::run(
+            "CREATE FUNCTION allocate(bytes bigint) RETURNS bigint STRICT LANGUAGE plrust AS $$
+                let allocated = vec![1u8; bytes as usize];
+                Ok(Some(allocated.iter().map(|b| *b as i64).sum()))
+            $$",
+        )?;
+        Spi::run("SET plrust.max_call_memory = '1MB'")?;
+        assert_eq!(Ok(Some(1024)), Spi::get_one::<i64>("SELECT allocate(1024)"));
+        Spi::run("SELECT allocate(16 * 1024 * 1024)")
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "PL/Rust function allocate_small(bigint) exceeded `plrust.max_call_memory` of 1024kB"]
+    fn plrust_max_call_memory_small_allocations() -> spi::Result<()> {
+        // each node is its own small allocation, so the limit is reached with only a few bytes to
+        // spare, which the panic then needs to unwind
+        Spi::run(
+            "CREATE FUNCTION allocate_small(count bigint) RETURNS bigint STRICT LANGUAGE plrust AS $$
+                let mut allocated = std::collections::LinkedList::new();
+                for i in 0..count {
+                    allocated.push_back([i as u8; 24]);
+                }
+                Ok(Some(allocated.len() as i64))
+            $$",
+        )?;
+        Spi::run("SET plrust.max_call_memory = '1MB'")?;
+        assert_eq!(
+            Ok(Some(1000)),
+            Spi::get_one::<i64>("SELECT allocate_small(1000)")
+        );
+        Spi::run("SELECT allocate_small(1000000)")
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_max_call_memory_unlimited() -> spi::Result<()> {
+        Spi::run(
+            "CREATE FUNCTION allocate(bytes bigint) RETURNS bigint STRICT LANGUAGE plrust AS $$
+                let allocated = vec![1u8; bytes as usize];
+                Ok(Some(allocated.len() as i64))
+            $$",
+        )?;
+        Spi::run("SET plrust.max_call_memory = 0")?;
+        assert_eq!(
+            Ok(Some(16 * 1024 * 1024)),
+            Spi::get_one::<i64>("SELECT allocate(16 * 1024 * 1024)")
+        );
+        Ok(())
+    }
+}
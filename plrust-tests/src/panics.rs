@@ -37,6 +37,26 @@ mod tests {
         assert_eq!(retval, Ok(None));
         Ok(())
     }
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "PL/Rust function explode_on_line_three() panicked at line 3: boom"]
+    fn plrust_panic_reports_user_line() -> spi::Result<()> {
+        let definition = r#"
+            CREATE FUNCTION explode_on_line_three()
+            RETURNS int AS
+            $$
+                let values = vec![1, 2, 3];
+                for value in values {
+                    if value > 2 { panic!("boom"); }
+                }
+                Ok(None)
+            $$ LANGUAGE plrust;
+        "#;
+
+        Spi::run(definition)?;
+        Spi::run("SELECT explode_on_line_three()")
+    }
+
     #[pg_test]
     #[search_path(@extschema@)]
     #[should_panic = "xxx"]
@@ -0,0 +1,45 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "`opt-level` may not be overridden by a function"]
+    fn plrust_profile_override_not_allowed() -> spi::Result<()> {
+        let definition = r#"
+            CREATE FUNCTION small_please() RETURNS int LANGUAGE plrust AS $$
+            [profile]
+            opt-level = "z"
+
+            [code]
+            Ok(Some(1))
+            $$;
+        "#;
+        Spi::run(definition)
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "`panic` is not a supported `[profile]` setting"]
+    fn plrust_profile_cant_abort() -> spi::Result<()> {
+        let definition = r#"
+            CREATE FUNCTION abort_please() RETURNS int LANGUAGE plrust AS $$
+            [profile]
+            panic = "abort"
+
+            [code]
+            Ok(Some(1))
+            $$;
+        "#;
+        Spi::run(definition)
+    }
+}
@@ -0,0 +1,66 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_reuse_compiled_function() -> spi::Result<()> {
+        Spi::run("CREATE FUNCTION reuse_me() RETURNS int LANGUAGE plrust AS $$ Ok(Some(42)) $$")?;
+        let prosrc = Spi::get_one::<String>(
+            "SELECT prosrc FROM pg_catalog.pg_proc WHERE oid = 'reuse_me'::regproc",
+        )?;
+        assert!(prosrc.is_some());
+
+        // this is essentially what `pg_restore` does with a PL/Rust function
+        Spi::run("SET plrust.reuse_compiled_functions TO on")?;
+        Spi::run(&format!(
+            "CREATE OR REPLACE FUNCTION reuse_me() RETURNS int LANGUAGE plrust AS $${}$$",
+            prosrc.as_ref().unwrap()
+        ))?;
+
+        // had the function been compiled again, its `prosrc` would contain a different artifact
+        let reused = Spi::get_one::<String>(
+            "SELECT prosrc FROM pg_catalog.pg_proc WHERE oid = 'reuse_me'::regproc",
+        )?;
+        assert_eq!(prosrc, reused);
+        assert_eq!(Ok(Some(42)), Spi::get_one::<i32>("SELECT reuse_me()"));
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_reuse_compiled_function_not_superuser() -> spi::Result<()> {
+        Spi::run("CREATE FUNCTION forge_me() RETURNS int LANGUAGE plrust AS $$ Ok(Some(42)) $$")?;
+        let prosrc = Spi::get_one::<String>(
+            "SELECT prosrc FROM pg_catalog.pg_proc WHERE oid = 'forge_me'::regproc",
+        )?
+        .unwrap();
+
+        // an ordinary role could just as well have written this entry, and its library, itself
+        Spi::run("CREATE ROLE forger")?;
+        Spi::run("CREATE SCHEMA forgery AUTHORIZATION forger")?;
+        Spi::run("SET plrust.reuse_compiled_functions TO on")?;
+        Spi::run("SET ROLE forger")?;
+        Spi::run(&format!(
+            "CREATE FUNCTION forgery.forged() RETURNS int LANGUAGE plrust AS $${prosrc}$$"
+        ))?;
+        Spi::run("RESET ROLE")?;
+
+        // it was compiled again, from the entry's source
+        let forged = Spi::get_one::<String>(
+            "SELECT prosrc FROM pg_catalog.pg_proc WHERE oid = 'forgery.forged'::regproc",
+        )?;
+        assert_ne!(Some(prosrc), forged);
+        assert_eq!(Ok(Some(42)), Spi::get_one::<i32>("SELECT forgery.forged()"));
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+    use pgrx::PgBuiltInOids;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_stat_functions() -> spi::Result<()> {
+        Spi::run("SET plrust.track_functions = on")?;
+        Spi::run("CREATE FUNCTION tracked(a int) RETURNS int STRICT LANGUAGE plrust AS $$ Ok(Some(a)) $$")?;
+        Spi::run("SELECT plrust.stat_functions_reset()")?;
+
+        Spi::run("SELECT tracked(x) FROM generate_series(1, 3) x")?;
+        assert_eq!(
+            Ok(Some(3)),
+            Spi::get_one::<i64>(
+                "SELECT calls FROM plrust.stat_functions WHERE funcname = 'tracked'"
+            )
+        );
+        assert_eq!(
+            Ok(Some(0)),
+            Spi::get_one::<i64>(
+                "SELECT errors FROM plrust.stat_functions WHERE funcname = 'tracked'"
+            )
+        );
+        assert_eq!(
+            Ok(Some(true)),
+            Spi::get_one::<bool>(
+                "SELECT max_time <= total_time FROM plrust.stat_functions WHERE funcname = 'tracked'"
+            )
+        );
+
+        Spi::run("SELECT plrust.stat_functions_reset('tracked(int)')")?;
+        assert_eq!(
+            Ok(Some(0)),
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM plrust.stat_functions WHERE funcname = 'tracked'"
+            )
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_stat_functions_dropped() -> spi::Result<()> {
+        Spi::run("SET plrust.track_functions = on")?;
+        Spi::run("CREATE FUNCTION dropped() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$")?;
+        Spi::run("CREATE FUNCTION survivor() RETURNS int LANGUAGE plrust AS $$ Ok(Some(2)) $$")?;
+        Spi::run("SELECT dropped(), survivor()")?;
+        let funcid = Spi::get_one::<pg_sys::Oid>("SELECT 'dropped()'::regprocedure::oid")?;
+
+        // the next call notices it was dropped
+        Spi::run("DROP FUNCTION dropped()")?;
+        Spi::run("SELECT survivor()")?;
+        assert_eq!(
+            Ok(Some(0)),
+            Spi::get_one_with_args::<i64>(
+                "SELECT count(*) FROM plrust.function_stats() WHERE funcid = $1",
+                vec![(PgBuiltInOids::OIDOID.oid(), funcid.into_datum())]
+            )
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_stat_functions_untracked() -> spi::Result<()> {
+        Spi::run("SET plrust.track_functions = off")?;
+        Spi::run("CREATE FUNCTION untracked() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$")?;
+        Spi::run("SELECT untracked()")?;
+        assert_eq!(
+            Ok(Some(0)),
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM plrust.stat_functions WHERE funcname = 'untracked'"
+            )
+        );
+        Ok(())
+    }
+}
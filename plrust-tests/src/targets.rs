@@ -0,0 +1,49 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    fn plrust_compile_missing_targets_nothing_missing() -> spi::Result<()> {
+        Spi::run(
+            "CREATE FUNCTION nothing_missing() RETURNS int LANGUAGE plrust AS $$ Ok(Some(1)) $$",
+        )?;
+        let prosrc = Spi::get_one::<String>(
+            "SELECT prosrc FROM pg_catalog.pg_proc WHERE oid = 'nothing_missing'::regproc",
+        )?;
+
+        // the tests don't configure `plrust.compilation_targets`, so there's nothing to compile
+        let compiled = Spi::get_one::<i64>(
+            "SELECT count(*) FROM plrust.compile_missing_targets('nothing_missing()'::regprocedure)",
+        )?;
+        assert_eq!(Some(0), compiled);
+
+        let compiled =
+            Spi::get_one::<i64>("SELECT count(*) FROM plrust.compile_missing_targets()")?;
+        assert_eq!(Some(0), compiled);
+
+        // and the function itself is untouched
+        let after = Spi::get_one::<String>(
+            "SELECT prosrc FROM pg_catalog.pg_proc WHERE oid = 'nothing_missing'::regproc",
+        )?;
+        assert_eq!(prosrc, after);
+        Ok(())
+    }
+
+    #[pg_test]
+    #[search_path(@extschema@)]
+    #[should_panic = "is not a PL/Rust function"]
+    fn plrust_compile_missing_targets_not_plrust() -> spi::Result<()> {
+        Spi::run("CREATE FUNCTION not_plrust() RETURNS int LANGUAGE sql AS $$ SELECT 1 $$")?;
+        Spi::run("SELECT * FROM plrust.compile_missing_targets('not_plrust()'::regprocedure)")
+    }
+}
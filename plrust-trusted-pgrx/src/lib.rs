@@ -162,6 +162,407 @@ pub mod spi {
     };
 }
 
+pub use error_report::PgError;
+
+/// Return errors Postgres reports with a particular SQLSTATE, DETAIL, HINT, and the column,
+/// constraint, or table they concern.
+pub mod error_report {
+    use ::pgrx::pg_sys::elog::PgLogLevel;
+    use ::pgrx::pg_sys::errcodes::PgSqlErrorCode;
+    use ::pgrx::pg_sys::panic::ErrorReport;
+
+    extern "C" {
+        fn plrust_user_error_fields(
+            sqlstate: i32,
+            column: *const u8,
+            column_len: usize,
+            constraint: *const u8,
+            constraint_len: usize,
+            table: *const u8,
+            table_len: usize,
+        );
+    }
+
+    /// An error for a user function to return when it wants Postgres to report more than just a
+    /// message.
+    ///
+    /// Other errors a function returns are reported with the SQLSTATE `22000` (`data_exception`)
+    /// and their [`Display`](std::fmt::Display) form as the message.  A `PgError` is reported with
+    /// the SQLSTATE, message, DETAIL, and HINT it was given, along with any column, constraint, or
+    /// table name, which clients see as the error's `COLUMN_NAME`, `CONSTRAINT_NAME`, and
+    /// `TABLE_NAME` fields:
+    ///
+    /// ```rust,ignore
+    /// return Err(PgError::new(PgSqlErrorCode::ERRCODE_CHECK_VIOLATION, "quantity must be positive")
+    ///     .set_detail(format!("quantity was {quantity}"))
+    ///     .set_hint("Use a refund to return items.")
+    ///     .set_column("quantity")
+    ///     .into());
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct PgError {
+        sqlstate: PgSqlErrorCode,
+        message: String,
+        detail: Option<String>,
+        hint: Option<String>,
+        column: Option<String>,
+        constraint: Option<String>,
+        table: Option<String>,
+    }
+
+    impl PgError {
+        pub fn new(sqlstate: PgSqlErrorCode, message: impl Into<String>) -> Self {
+            Self {
+                sqlstate,
+                message: message.into(),
+                detail: None,
+                hint: None,
+                column: None,
+                constraint: None,
+                table: None,
+            }
+        }
+
+        pub fn set_detail(mut self, detail: impl Into<String>) -> Self {
+            self.detail = Some(detail.into());
+            self
+        }
+
+        pub fn set_hint(mut self, hint: impl Into<String>) -> Self {
+            self.hint = Some(hint.into());
+            self
+        }
+
+        pub fn set_column(mut self, column: impl Into<String>) -> Self {
+            self.column = Some(column.into());
+            self
+        }
+
+        pub fn set_constraint(mut self, constraint: impl Into<String>) -> Self {
+            self.constraint = Some(constraint.into());
+            self
+        }
+
+        pub fn set_table(mut self, table: impl Into<String>) -> Self {
+            self.table = Some(table.into());
+            self
+        }
+
+        pub fn sqlstate(&self) -> PgSqlErrorCode {
+            self.sqlstate
+        }
+
+        pub fn message(&self) -> &str {
+            &self.message
+        }
+
+        pub fn detail(&self) -> Option<&str> {
+            self.detail.as_deref()
+        }
+
+        pub fn hint(&self) -> Option<&str> {
+            self.hint.as_deref()
+        }
+
+        pub fn column(&self) -> Option<&str> {
+            self.column.as_deref()
+        }
+
+        pub fn constraint(&self) -> Option<&str> {
+            self.constraint.as_deref()
+        }
+
+        pub fn table(&self) -> Option<&str> {
+            self.table.as_deref()
+        }
+
+        fn report(&self) -> ! {
+            let mut report = ErrorReport::new(self.sqlstate, &self.message, "plrust user function");
+            if let Some(detail) = &self.detail {
+                report = report.set_detail(detail);
+            }
+            if let Some(hint) = &self.hint {
+                report = report.set_hint(hint);
+            }
+
+            // `ErrorReport` can't carry these, so `plrust` adds them when the ERROR is raised
+            if self.column.is_some() || self.constraint.is_some() || self.table.is_some() {
+                let field = |field: &Option<String>| match field {
+                    Some(field) => (field.as_ptr(), field.len()),
+                    None => (std::ptr::null(), 0),
+                };
+                let (column, column_len) = field(&self.column);
+                let (constraint, constraint_len) = field(&self.constraint);
+                let (table, table_len) = field(&self.table);
+
+                // SAFETY:  `plrust` defines this, and copies the strings before it returns
+                unsafe {
+                    plrust_user_error_fields(
+                        self.sqlstate as i32,
+                        column,
+                        column_len,
+                        constraint,
+                        constraint_len,
+                        table,
+                        table_len,
+                    );
+                }
+            }
+            report.report(PgLogLevel::ERROR);
+            unreachable!("ERROR-level reports don't return")
+        }
+    }
+
+    impl std::fmt::Display for PgError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(&self.message)
+        }
+    }
+
+    impl std::error::Error for PgError {}
+
+    /// The error types user functions return, which might be a [`PgError`].
+    #[doc(hidden)]
+    pub trait MaybePgError {
+        fn as_pg_error(&self) -> Option<&PgError>;
+    }
+
+    impl MaybePgError for Box<dyn std::error::Error> {
+        fn as_pg_error(&self) -> Option<&PgError> {
+            self.downcast_ref()
+        }
+    }
+
+    impl MaybePgError for Box<dyn std::error::Error + Send + Sync> {
+        fn as_pg_error(&self) -> Option<&PgError> {
+            self.downcast_ref()
+        }
+    }
+
+    /// Called by `plrust` with what a user function returned.  A [`PgError`] is reported here, with
+    /// all its fields, and anything else is passed along for `pgrx` to report.
+    #[doc(hidden)]
+    pub fn report<T, E: MaybePgError>(result: Result<T, E>) -> Result<T, E> {
+        if let Err(e) = &result {
+            if let Some(pg_error) = e.as_pg_error() {
+                pg_error.report();
+            }
+        }
+        result
+    }
+}
+
+/// Tells `plrust` where in the user's code a user function panicked.
+///
+/// `plrust` generates a map from lines of the user crate's `lib.rs` to lines of the user's code,
+/// and has the user function install a panic hook that uses it.  The hook hands the panic message,
+/// the line, and a backtrace of the user's frames if `plrust.panic_backtraces` is on, to `plrust`,
+/// which is always loaded into the backend before any user function, to report.
+#[doc(hidden)]
+pub mod panics {
+    use std::backtrace::Backtrace;
+    use std::fmt::Write;
+    use std::sync::Once;
+
+    extern "C" {
+        fn plrust_capture_backtraces() -> bool;
+        fn plrust_user_panic(
+            message: *const u8,
+            message_len: usize,
+            line: u32,
+            backtrace: *const u8,
+            backtrace_len: usize,
+        );
+    }
+
+    /// The path `rustc` was given for the user crate's `lib.rs`.  Dependencies are given absolute
+    /// paths
+    const USER_LIB_RS: &str = "src/lib.rs";
+
+    static INSTALL: Once = Once::new();
+
+    /// Install the panic hook, which first calls the one installed before it, such as `pgrx`'s own.
+    /// `lines` are pairs of (`lib.rs` line, user code line), ordered by `lib.rs` line
+    pub fn locate_panics(lines: &'static [(u32, u32)]) {
+        INSTALL.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                previous(info);
+
+                // `pgrx` raises Postgres errors by panicking with its own payloads, which are
+                // reported just as they are.  And we mustn't allocate here, as this might be a
+                // panic because an allocation was refused
+                let payload = info.payload();
+                let message = match payload.downcast_ref::<&str>() {
+                    Some(message) => *message,
+                    None => match payload.downcast_ref::<String>() {
+                        Some(message) => message.as_str(),
+                        None => return,
+                    },
+                };
+                let line = info
+                    .location()
+                    .filter(|location| location.file() == USER_LIB_RS)
+                    .and_then(|location| user_line(lines, location.line()))
+                    .unwrap_or(0);
+
+                // SAFETY:  `plrust` defines this, and only says yes when we're allowed to allocate
+                let backtrace = if unsafe { plrust_capture_backtraces() } {
+                    user_frames(&Backtrace::force_capture().to_string(), lines)
+                } else {
+                    String::new()
+                };
+
+                // SAFETY:  `plrust` copies the strings before it returns
+                unsafe {
+                    plrust_user_panic(
+                        message.as_ptr(),
+                        message.len(),
+                        line,
+                        backtrace.as_ptr(),
+                        backtrace.len(),
+                    )
+                }
+            }))
+        });
+    }
+
+    /// The line of the user's code that `lib_rs_line` came from, if it came from the user's code
+    fn user_line(lines: &[(u32, u32)], lib_rs_line: u32) -> Option<u32> {
+        let (first, last) = (lines.first()?.0, lines.last()?.0);
+        if lib_rs_line < first || lib_rs_line > last {
+            return None;
+        }
+        match lines.binary_search_by_key(&lib_rs_line, |(generated, _)| *generated) {
+            Ok(i) => Some(lines[i].1),
+            // a line of generated code, such as an interrupt check, goes with the one before it
+            Err(i) => Some(lines[i - 1].1),
+        }
+    }
+
+    /// The frames of a backtrace that are in the user's code, one per line, most recent first
+    fn user_frames(backtrace: &str, lines: &[(u32, u32)]) -> String {
+        let mut frames = String::new();
+        let mut function = "";
+        for line in backtrace.lines().map(str::trim) {
+            if let Some(location) = line.strip_prefix("at ") {
+                let location = location.strip_prefix("./").unwrap_or(location);
+                let Some(position) = location
+                    .strip_prefix(USER_LIB_RS)
+                    .and_then(|l| l.strip_prefix(':'))
+                else {
+                    continue;
+                };
+                let lib_rs_line = position.split(':').next().and_then(|l| l.parse().ok());
+                if let Some(user_line) = lib_rs_line.and_then(|l| user_line(lines, l)) {
+                    // the user's function is `user_fn`, and anything within it is named for it
+                    let within = function
+                        .split_once("::user_fn")
+                        .map(|(_, within)| within.trim_start_matches("::"))
+                        .unwrap_or(function);
+                    let _ = match within {
+                        "" => writeln!(frames, "line {user_line}"),
+                        within => writeln!(frames, "line {user_line}, in `{within}`"),
+                    };
+                }
+            } else if let Some((_, symbol)) = line.split_once(": ") {
+                // symbols might end with a hash, such as `::h0123456789abcdef`
+                function = match symbol.rsplit_once("::h") {
+                    Some((function, hash))
+                        if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) =>
+                    {
+                        function
+                    }
+                    _ => symbol,
+                };
+            }
+        }
+        frames
+    }
+}
+
+pub use interrupts::check_for_interrupts;
+
+/// Respond to query cancellation, `statement_timeout`, and the like.
+pub mod interrupts {
+    /// Check if this backend has been asked to stop what it's doing, such as by
+    /// `pg_cancel_backend()` or `statement_timeout`, and if so, raise the appropriate ERROR.
+    ///
+    /// Unless `plrust.check_for_interrupts` is off, `plrust` inserts a call to this at the start of
+    /// each function and loop body in user code, so it's only necessary in long-running code that
+    /// doesn't loop, such as a lengthy iterator chain.
+    #[inline]
+    pub fn check_for_interrupts() {
+        ::pgrx::check_for_interrupts!();
+    }
+}
+
+/// The global allocator for user functions, which lets `plrust` limit how much memory they allocate.
+///
+/// It defers to the system allocator, but first asks `plrust`, which is always loaded into the
+/// backend before any user function, if the allocation is allowed.  `plrust` compiles user functions
+/// to panic, rather than abort, when an allocation is refused.
+#[doc(hidden)]
+pub mod allocator {
+    use std::alloc::{GlobalAlloc, Layout, System};
+
+    extern "C" {
+        fn plrust_memory_reserve(size: usize) -> bool;
+        fn plrust_memory_release(size: usize);
+    }
+
+    pub struct PlRustAllocator;
+
+    // SAFETY:  we only add bookkeeping around the system allocator
+    unsafe impl GlobalAlloc for PlRustAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            if !plrust_memory_reserve(layout.size()) {
+                return std::ptr::null_mut();
+            }
+            let ptr = System.alloc(layout);
+            if ptr.is_null() {
+                plrust_memory_release(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            if !plrust_memory_reserve(layout.size()) {
+                return std::ptr::null_mut();
+            }
+            let ptr = System.alloc_zeroed(layout);
+            if ptr.is_null() {
+                plrust_memory_release(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            plrust_memory_release(layout.size());
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let old_size = layout.size();
+            if new_size > old_size && !plrust_memory_reserve(new_size - old_size) {
+                return std::ptr::null_mut();
+            }
+            let new_ptr = System.realloc(ptr, layout, new_size);
+            if new_ptr.is_null() {
+                if new_size > old_size {
+                    plrust_memory_release(new_size - old_size);
+                }
+            } else if new_size < old_size {
+                plrust_memory_release(old_size - new_size);
+            }
+            new_ptr
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: PlRustAllocator = PlRustAllocator;
+}
+
 pub use trigger_support::*;
 
 /// Various types for use when a `plrust` function is a trigger function.
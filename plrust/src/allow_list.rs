@@ -5,15 +5,15 @@ All rights reserved.
 
 Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
 */
-use crate::gucs::PLRUST_ALLOWED_DEPENDENCIES;
-use semver::{BuildMetadata, Comparator, Op, Version, VersionReq};
+use crate::gucs;
+use pgrx::prelude::*;
+use pgrx::{spi, PgBuiltInOids};
+use semver::{BuildMetadata, Comparator, Op, Prerelease, Version, VersionReq};
 use std::cmp::Ordering;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Display, Formatter};
 use std::iter::once;
 use std::ops::Deref;
-use std::path::PathBuf;
-use std::str::FromStr;
 use toml::Value;
 
 #[derive(Debug, PartialEq)]
@@ -45,13 +45,21 @@ impl TryFrom<String> for OrderedVersionReq {
 impl TryFrom<&str> for OrderedVersionReq {
     type Error = Error;
 
-    /// only allow "*", exact, and bounded VersionReq values.  
+    /// only allow "*", exact, and bounded VersionReq values, along with caret (`^x.y.z`) and tilde
+    /// (`~x.y.z`) requirements, which are expanded to the bounded ranges they're equivalent to.
     ///
     /// Versions with "prerelease" parts are not supported
     fn try_from(version: &str) -> Result<Self, Self::Error> {
-        let vreq = VersionReq::parse(&version)
+        let mut vreq = VersionReq::parse(&version)
             .map_err(|e| Error::MalformedVersion(version.to_string(), e.to_string()))?;
 
+        // a bare `x.y.z` is also a caret requirement, but in the allow-list it's more likely meant to
+        // be exact, so we make the administrator say which
+        if version.trim_start().starts_with(['^', '~']) {
+            vreq = expand_caret_tilde(&vreq)
+                .ok_or_else(|| Error::UnsupportedVersionReq(version.to_string()))?;
+        }
+
         if validate_versionreq(&vreq, true) {
             Ok(OrderedVersionReq(vreq))
         } else {
@@ -60,6 +68,52 @@ impl TryFrom<&str> for OrderedVersionReq {
     }
 }
 
+/// Expand a lone caret or tilde comparator into the bounded range it's equivalent to, such as
+/// `^1.2` into `>=1.2.0, <2.0.0`, or `~1.2.3` into `>=1.2.3, <1.3.0`.  Anything else is returned
+/// as-is
+fn expand_caret_tilde(vreq: &VersionReq) -> Option<VersionReq> {
+    let [cmp] = vreq.comparators.as_slice() else {
+        return Some(vreq.clone());
+    };
+    if !cmp.pre.is_empty() {
+        return None;
+    }
+
+    let (major, minor, patch) = (cmp.major, cmp.minor, cmp.patch);
+    let upper = match cmp.op {
+        // the left-most non-zero part of the version can't change, or the left-most part given,
+        // when they're all zero
+        Op::Caret => match (major, minor, patch) {
+            (0, Some(0), Some(patch)) => (0, 0, patch + 1),
+            (0, Some(minor), _) => (0, minor + 1, 0),
+            (major, _, _) => (major + 1, 0, 0),
+        },
+        // only the patch can change, or the minor too when it isn't given
+        Op::Tilde => match minor {
+            Some(minor) => (major, minor + 1, 0),
+            None => (major + 1, 0, 0),
+        },
+        _ => return Some(vreq.clone()),
+    };
+
+    let bound = |op, (major, minor, patch)| Comparator {
+        op,
+        major,
+        minor: Some(minor),
+        patch: Some(patch),
+        pre: Prerelease::EMPTY,
+    };
+    Some(VersionReq {
+        comparators: vec![
+            bound(
+                Op::GreaterEq,
+                (major, minor.unwrap_or(0), patch.unwrap_or(0)),
+            ),
+            bound(Op::Less, upper),
+        ],
+    })
+}
+
 fn validate_versionreq(vreq: &VersionReq, require_exact: bool) -> bool {
     let has_prelrease = vreq.comparators.iter().any(|cmp| !cmp.pre.is_empty());
     if has_prelrease {
@@ -96,30 +150,17 @@ impl Deref for OrderedVersionReq {
 }
 
 impl Ord for OrderedVersionReq {
-    /// Orders [`VersionReq`] values from smallest to largest, with "*" considered the smallest
+    /// Orders [`VersionReq`] values from smallest to largest, by their lower bounds and then by
+    /// their upper bounds, with "*" considered the smallest
     fn cmp(&self, other: &Self) -> Ordering {
-        let self_vers = self.as_versions();
-        let other_vers = other.as_versions();
-
-        if self_vers.is_empty() {
+        match (self.bounds(), other.bounds()) {
+            (None, None) => Ordering::Equal,
             // '*' version is the smallest
-            return Ordering::Less;
-        } else if other_vers.is_empty() {
-            // '*' version is the smallest
-            return Ordering::Greater;
-        } else {
-            match self_vers[0].cmp(&other_vers[0]) {
-                // how do the upper bounds of each side compare?
-                Ordering::Equal if self_vers.len() > 1 && other_vers.len() > 1 => {
-                    self_vers[1].cmp(&other_vers[1])
-                }
-
-                // how does our value compare to the upper bound of the other?
-                Ordering::Equal if other_vers.len() > 1 => self_vers[0].cmp(&other_vers[1]),
-
-                // lower bounds compared unequal, so that's our final answer
-                ne => ne,
-            }
+            (None, Some(_)) => Ordering::Less,
+            (Some(_), None) => Ordering::Greater,
+            (Some((self_lower, self_upper)), Some((other_lower, other_upper))) => self_lower
+                .cmp(&other_lower)
+                .then_with(|| self_upper.cmp(&other_upper)),
         }
     }
 }
@@ -142,12 +183,17 @@ fn fake_version(cmp: &Comparator) -> Version {
     if cmp.op == Op::Greater {
         version.patch += 1;
     } else if cmp.op == Op::Less {
+        // the largest version that's less, such as `1.9.MAX` for `<2.0.0`, so that an exclusive
+        // upper bound doesn't appear to allow versions it doesn't
         if version.patch > 0 {
             version.patch -= 1;
         } else if version.minor > 0 {
             version.minor -= 1;
+            version.patch = u64::MAX;
         } else if version.major > 0 {
             version.major -= 1;
+            version.minor = u64::MAX;
+            version.patch = u64::MAX;
         }
     }
 
@@ -160,8 +206,14 @@ impl OrderedVersionReq {
     /// Our definition of "match" is that `other` needs to first pass [`validate_versionreq()`], and
     /// then we ensure that the lower (and possibly upper) bounds of each version match each other
     /// by pretending they're actually [`Version`]s.
+    ///
+    /// A caret or tilde `other` is first expanded to the bounded range it's equivalent to.
     #[rustfmt::skip]
     fn matches_versionreq(&self, other: &VersionReq) -> bool {
+        let Some(other) = expand_caret_tilde(other) else {
+            return false;
+        };
+        let other = &other;
         if !validate_versionreq(other, false) {
             return false;
         }
@@ -190,10 +242,25 @@ impl OrderedVersionReq {
         }
     }
 
-    /// Convert each part of the inner [`VersionReq`] into an exact [`Version`] the best we can.
-    /// For unknown fields, we assume zero.
-    fn as_versions(&self) -> Vec<Version> {
-        self.0.comparators.iter().map(fake_version).collect()
+    /// The lower and upper bounds of the inner [`VersionReq`], or `None` for "*".  Each is a
+    /// [`Version`], with unknown fields assumed to be zero, along with a number that orders an
+    /// exclusive bound after (`>`) or before (`<`) an inclusive bound on the same version
+    fn bounds(&self) -> Option<((Version, i8), (Version, i8))> {
+        let bound = |cmp: &Comparator| {
+            let version = Version::new(cmp.major, cmp.minor.unwrap_or(0), cmp.patch.unwrap_or(0));
+            let exclusive = match cmp.op {
+                Op::Greater => 1,
+                Op::Less => -1,
+                _ => 0,
+            };
+            (version, exclusive)
+        };
+
+        match self.0.comparators.as_slice() {
+            [] => None,
+            [only] => Some((bound(only), bound(only))),
+            [lower, upper, ..] => Some((bound(lower), bound(upper))),
+        }
     }
 }
 
@@ -209,13 +276,11 @@ pub enum Error {
     NotATomlFile,
     #[error("`plrust.allowed_dependencies` is not set in `postgresql.conf`")]
     NotConfigured,
-    #[error("The value of `plrust.allowed_dependencies` is not a valid path")]
-    InvalidPath,
     #[error("`{0}` is malformed: {1}")]
     MalformedVersion(String, String),
     #[error("`{0}` is not permitted by the allow-list")]
     VersionNotPermitted(String),
-    #[error("`{0}` is not a supported version requirement.  Use wildcard (`*`), exact (`=x.y.z`), or bounded ranges (`>=a.b.c, <=x.y.z`)")]
+    #[error("`{0}` is not a supported version requirement.  Use wildcard (`*`), exact (`=x.y.z`), caret (`^x.y.z`), tilde (`~x.y.z`), or bounded ranges (`>=a.b.c, <=x.y.z`)")]
     UnsupportedVersionReq(String),
 }
 
@@ -252,10 +317,12 @@ impl From<AllowedDependencies> for Vec<AllowedDependencyTuple> {
     }
 }
 
-/// Get all the allowed dependencies entries as a AllowedDependencies struct
+/// Get all the allowed dependencies entries the current role may use as a AllowedDependencies struct
 pub fn get_allowed_dependencies() -> AllowedDependencies {
+    // SAFETY:  we're in a transaction, so asking about our current user is fine
+    let role = unsafe { pg_sys::GetUserId() };
     let allowlist: BTreeMap<String, Dependency> =
-        load_allowlist().expect("Error loading dependency allow-list");
+        load_allowlist(role).expect("Error loading dependency allow-list");
     let mut allowed_dependencies: Vec<AllowedDependency> = vec![];
     for dependency in allowlist.values() {
         let entries = get_entries_for_single_allowed_dependency(dependency);
@@ -404,6 +471,11 @@ impl TryFrom<(&str, Value)> for Dependency {
 }
 
 impl Dependency {
+    /// The version requirements this dependency is allowed with
+    pub(crate) fn version_reqs(&self) -> impl Iterator<Item = &VersionReq> {
+        self.versions.keys().map(Deref::deref)
+    }
+
     /// Given some kind of version string, which could be a literal version such as `1.2.3`, or
     /// any [`semver::VersionReq`]-compatible version pattern, find the **largest** declared
     /// version entry that matches the specified `wanted_version`.
@@ -487,19 +559,425 @@ impl Dependency {
     }
 }
 
-/// Reads the "dependency allow-list" from disk, at the path specified by the
-/// `plrust.allowed_dependencies` GUC
-pub fn load_allowlist() -> eyre::Result<AllowList> {
-    let path = PathBuf::from_str(
-        &PLRUST_ALLOWED_DEPENDENCIES
-            .get()
-            .ok_or(Error::NotConfigured)?
-            .to_str()?,
-    )
-    .map_err(|_| Error::InvalidPath)?;
+/// Is a dependency allow-list configured at all?  If not, any dependency is allowed
+pub(crate) fn is_configured() -> bool {
+    gucs::allowed_dependencies_table() || is_file_configured()
+}
 
-    let contents = std::fs::read_to_string(path).map_err(|_| Error::CannotReadAllowList)?;
-    Ok(parse_allowlist(&contents)?)
+/// Is the dependency allow-list the file named by `plrust.allowed_dependencies`?
+pub(crate) fn is_file_configured() -> bool {
+    !gucs::allowed_dependencies_table() && gucs::allowed_dependencies().is_some()
+}
+
+/// Reads the "dependency allow-list" that applies to `role`, either from the
+/// `plrust.dependency_allowlist` table when `plrust.allowed_dependencies_table` is on, or otherwise
+/// from disk, at the path specified by the `plrust.allowed_dependencies` GUC.  The file applies to
+/// every role
+pub fn load_allowlist(role: pg_sys::Oid) -> eyre::Result<AllowList> {
+    if gucs::allowed_dependencies_table() {
+        return load_allowlist_table(role);
+    }
+    load_allowlist_file()
+}
+
+/// Parses the "dependency allow-list" file named by the `plrust.allowed_dependencies` GUC, as it
+/// was read when the setting was last accepted.  Editing the file has no effect until the
+/// configuration is reloaded, and then only if the edited file has no problems
+pub(crate) fn load_allowlist_file() -> eyre::Result<AllowList> {
+    gucs::allowed_dependencies().ok_or(Error::NotConfigured)?;
+    let contents = gucs::allowed_dependencies_contents().ok_or(Error::CannotReadAllowList)?;
+    Ok(parse_allowlist(contents)?)
+}
+
+/// Describes what's wrong with the `contents` of an allow-list file, if anything, for the
+/// `plrust.allowed_dependencies` check hook
+pub(crate) fn check_allowlist_file(contents: &str) -> Result<(), String> {
+    let problems = validate_entries(contents)
+        .into_iter()
+        .filter_map(|status| match (status.name, status.error) {
+            (Some(name), Some(error)) => Some(format!("`{name}`: {error}")),
+            (None, Some(error)) => Some(error),
+            (_, None) => None,
+        })
+        .collect::<Vec<_>>();
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+/// The status of one entry of the allow-list, as reported by `plrust.validate_allowlist()`.  An
+/// entry is a version of a dependency, so a dependency with an array of versions has an entry for
+/// each.  A problem with the allow-list as a whole is reported without a `name`
+#[derive(Debug, PartialEq)]
+pub(crate) struct EntryStatus {
+    pub(crate) name: Option<String>,
+    /// The entry, as TOML
+    pub(crate) entry: Option<String>,
+    /// The entry's version requirement, as PL/Rust understands it
+    pub(crate) version_req: Option<String>,
+    pub(crate) error: Option<String>,
+}
+
+impl EntryStatus {
+    fn problem(error: impl Display) -> Self {
+        Self {
+            name: None,
+            entry: None,
+            version_req: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Check each entry of the configured allow-list, without stopping at the first problem.  The file
+/// is read as it is now, so that it can be checked before the configuration is reloaded
+pub(crate) fn validate_allowlist() -> Vec<EntryStatus> {
+    if gucs::allowed_dependencies_table() {
+        return match validate_allowlist_table() {
+            Ok(statuses) => statuses,
+            Err(e) => vec![EntryStatus::problem(e)],
+        };
+    }
+
+    let Some(path) = gucs::allowed_dependencies() else {
+        return vec![EntryStatus::problem(Error::NotConfigured)];
+    };
+    match std::fs::read_to_string(&*path.to_string_lossy()) {
+        Ok(contents) => validate_entries(&contents),
+        Err(e) => vec![EntryStatus::problem(format!(
+            "{}: {e}",
+            Error::CannotReadAllowList
+        ))],
+    }
+}
+
+fn validate_allowlist_table() -> eyre::Result<Vec<EntryStatus>> {
+    let rows = Spi::connect(|client| {
+        client
+            .select(
+                "SELECT a.name, a.version_req, a.features, a.default_features,
+                        a.role::oid <> 0 AND NOT EXISTS (
+                            SELECT 1 FROM pg_catalog.pg_roles r WHERE r.oid = a.role
+                        )
+                   FROM plrust.dependency_allowlist a
+                  ORDER BY a.name, a.version_req, a.role::oid",
+                None,
+                None,
+            )?
+            .map(|row| {
+                Ok((
+                    row.get::<String>(1)?.unwrap_or_default(),
+                    row.get::<String>(2)?.unwrap_or_default(),
+                    row.get::<Vec<String>>(3)?.unwrap_or_default(),
+                    row.get::<bool>(4)?.unwrap_or(true),
+                    row.get::<bool>(5)?.unwrap_or_default(),
+                ))
+            })
+            .collect::<spi::Result<Vec<_>>>()
+    })?;
+    Ok(rows
+        .into_iter()
+        .map(
+            |(name, version, features, default_features, role_dropped)| {
+                let entry = Value::Table(dependency_table(version, features, default_features));
+                let mut status = validate_entry(name, entry);
+                if role_dropped && status.error.is_none() {
+                    status.error = Some("the role this entry is for no longer exists".to_string());
+                }
+                status
+            },
+        )
+        .collect())
+}
+
+/// Check each entry of an allow-list file's `contents`
+fn validate_entries(contents: &str) -> Vec<EntryStatus> {
+    let toml = match toml::from_str::<toml::value::Table>(contents) {
+        Ok(toml) => toml,
+        Err(e) => {
+            return vec![EntryStatus::problem(format!(
+                "{}: {e}",
+                Error::NotATomlFile
+            ))]
+        }
+    };
+
+    let mut statuses = Vec::new();
+    for (name, value) in toml {
+        match value {
+            Value::Array(entries) => {
+                for entry in entries {
+                    statuses.push(validate_entry(name.clone(), entry));
+                }
+            }
+            entry => statuses.push(validate_entry(name, entry)),
+        }
+    }
+    statuses
+}
+
+fn validate_entry(name: String, entry: Value) -> EntryStatus {
+    let text = entry.to_string();
+    let (version_req, error) = match Dependency::try_from((name.as_str(), entry)) {
+        Ok(dependency) => (
+            dependency.version_reqs().next().map(ToString::to_string),
+            None,
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    EntryStatus {
+        name: Some(name),
+        entry: Some(text),
+        version_req,
+        error,
+    }
+}
+
+/// Reads the rows of the `plrust.dependency_allowlist` table that apply to `role`, which are each
+/// like a table entry of the file.  Those for `public`, stored as `InvalidOid` like in an ACL, apply
+/// to every role, and the others to the members of the role they name.  Entries for a role that's
+/// since been dropped apply to no one
+pub(crate) fn load_allowlist_table(role: pg_sys::Oid) -> eyre::Result<AllowList> {
+    let rows = Spi::connect(|client| {
+        client
+            .select(
+                // a version allowed for one of the role's groups replaces that same version as
+                // allowed for `public`, as it comes later
+                "SELECT a.name, a.version_req, a.features, a.default_features
+                   FROM plrust.dependency_allowlist a
+                  WHERE a.role::oid = 0
+                     OR EXISTS (
+                            SELECT 1 FROM pg_catalog.pg_roles r
+                             WHERE r.oid = a.role
+                               AND pg_catalog.pg_has_role($1, r.oid, 'MEMBER')
+                        )
+                  ORDER BY a.role::oid = 0 DESC, a.role::text",
+                None,
+                Some(vec![(PgBuiltInOids::OIDOID.oid(), role.into_datum())]),
+            )?
+            .map(|row| {
+                Ok((
+                    row.get::<String>(1)?.unwrap_or_default(),
+                    row.get::<String>(2)?.unwrap_or_default(),
+                    row.get::<Vec<String>>(3)?.unwrap_or_default(),
+                    row.get::<bool>(4)?.unwrap_or(true),
+                ))
+            })
+            .collect::<spi::Result<Vec<_>>>()
+    })?;
+
+    let mut versions = BTreeMap::<String, Vec<Value>>::new();
+    for (name, version, features, default_features) in rows {
+        versions
+            .entry(name)
+            .or_default()
+            .push(Value::Table(dependency_table(
+                version,
+                features,
+                default_features,
+            )));
+    }
+
+    let mut allowed = AllowList::new();
+    for (depname, versions) in versions {
+        let dependency = Dependency::try_from((depname.as_str(), Value::Array(versions)))?;
+        allowed.insert(depname, dependency);
+    }
+    Ok(allowed)
+}
+
+/// A dependency entry as it would be written in the allow-list file, leaving out the properties
+/// that are their defaults so it compares equal to what a user function declares
+fn dependency_table(
+    version: String,
+    features: Vec<String>,
+    default_features: bool,
+) -> toml::value::Table {
+    let mut table = toml::value::Table::new();
+    table.insert("version".to_string(), Value::String(version));
+    if !features.is_empty() {
+        table.insert(
+            "features".to_string(),
+            Value::Array(features.into_iter().map(Value::String).collect()),
+        );
+    }
+    if !default_features {
+        table.insert("default-features".to_string(), Value::Boolean(false));
+    }
+    table
+}
+
+/// Add a version of a dependency, for the members of `role`, to the `plrust.dependency_allowlist`
+/// table, replacing the features of that same version for that role if it's already there
+pub(crate) fn allow_dependency(
+    name: &str,
+    version_req: &str,
+    features: Vec<String>,
+    default_features: bool,
+    role: &str,
+) -> eyre::Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(eyre::eyre!("a dependency's name must not be empty"));
+    }
+    let version_req = OrderedVersionReq::try_from(version_req.trim())?;
+    let role = role_oid(role)?;
+
+    Spi::run_with_args(
+        "INSERT INTO plrust.dependency_allowlist (name, version_req, features, default_features, role)
+         VALUES ($1, $2, $3, $4, $5::regrole)
+         ON CONFLICT (name, version_req, role) DO UPDATE
+            SET features = excluded.features, default_features = excluded.default_features",
+        Some(vec![
+            (PgBuiltInOids::TEXTOID.oid(), name.into_datum()),
+            (
+                PgBuiltInOids::TEXTOID.oid(),
+                version_req.to_string().into_datum(),
+            ),
+            (PgBuiltInOids::TEXTARRAYOID.oid(), features.into_datum()),
+            (PgBuiltInOids::BOOLOID.oid(), default_features.into_datum()),
+            (PgBuiltInOids::OIDOID.oid(), role.into_datum()),
+        ]),
+    )?;
+    Ok(())
+}
+
+/// Remove a version of a dependency, or every version when `version_req` is `None`, from the
+/// `plrust.dependency_allowlist` table, for `role` or for every role when it's `None`.  Returns how
+/// many entries were removed.  Functions already compiled against them are unaffected
+pub(crate) fn revoke_dependency(
+    name: &str,
+    version_req: Option<&str>,
+    role: Option<&str>,
+) -> eyre::Result<i64> {
+    let version_req = version_req
+        .map(|version_req| OrderedVersionReq::try_from(version_req.trim()))
+        .transpose()?
+        .map(|version_req| version_req.to_string());
+    let role = role.map(role_oid).transpose()?;
+
+    let removed = Spi::get_one_with_args::<i64>(
+        "WITH removed AS (
+            DELETE FROM plrust.dependency_allowlist
+             WHERE name = $1
+               AND ($2::text IS NULL OR version_req = $2)
+               AND ($3::oid IS NULL OR role = $3::regrole)
+            RETURNING 1
+         )
+         SELECT count(*) FROM removed",
+        vec![
+            (PgBuiltInOids::TEXTOID.oid(), name.trim().into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), version_req.into_datum()),
+            (PgBuiltInOids::OIDOID.oid(), role.into_datum()),
+        ],
+    )?;
+    Ok(removed.unwrap_or_default())
+}
+
+/// The Oid of the role named `role`, which is `InvalidOid` for `public`, as in an ACL.  Entries are
+/// kept by Oid so that they follow the role when it's renamed, and aren't inherited by a new role
+/// that happens to have the name of a dropped one
+fn role_oid(role: &str) -> eyre::Result<pg_sys::Oid> {
+    if role == "public" {
+        return Ok(pg_sys::InvalidOid);
+    }
+    Spi::get_one_with_args::<pg_sys::Oid>(
+        "SELECT (SELECT oid FROM pg_catalog.pg_roles WHERE rolname = $1)",
+        vec![(PgBuiltInOids::TEXTOID.oid(), role.into_datum())],
+    )?
+    .ok_or_else(|| eyre::eyre!("role `{role}` does not exist"))
+}
+
+/// A `LANGUAGE plrust` function that wouldn't compile if an allow-list entry were removed or
+/// narrowed, and the version of the dependency it was built against.  That's `None` when the
+/// function was compiled before PL/Rust recorded that
+pub(crate) struct Impact {
+    pub(crate) function: pg_sys::Oid,
+    pub(crate) owner: pg_sys::Oid,
+    pub(crate) version: Option<String>,
+}
+
+/// Determine which functions would no longer compile if the allow-list entry for `name` with the
+/// specified `version_req` were removed, or every entry for `name` if that's `None`.  When given,
+/// `narrowed_to` is the entry that would replace it.
+///
+/// Functions are considered against the allow-list that applies to their owner, and the entry is
+/// considered removed for every role
+pub(crate) fn dependency_impact(
+    name: &str,
+    version_req: Option<&str>,
+    narrowed_to: Option<&str>,
+) -> eyre::Result<Vec<Impact>> {
+    if !is_configured() {
+        return Err(Error::NotConfigured.into());
+    }
+    let name = name.trim();
+    let version_req = version_req
+        .map(|version_req| OrderedVersionReq::try_from(version_req.trim()))
+        .transpose()?;
+    let narrowed_to = narrowed_to
+        .map(|narrowed_to| OrderedVersionReq::try_from(narrowed_to.trim()))
+        .transpose()?;
+
+    let mut allowlists = HashMap::new();
+    let mut impacts = Vec::new();
+    for function in crate::plrust::plrust_function_oids()? {
+        // functions that haven't been compiled don't depend on anything yet
+        let Ok(info) = crate::prosrc::function_info(function) else {
+            continue;
+        };
+        let owner = crate::pgproc::PgProc::new(function)?.proowner();
+
+        let versions = match info.dependencies {
+            Some(dependencies) => dependencies
+                .into_iter()
+                .filter(|dependency| dependency.direct && dependency.name == name)
+                .map(|dependency| Some(dependency.version))
+                .collect::<Vec<_>>(),
+
+            // we can't know which version the function was built against, only that it asked for one
+            None => {
+                let declared = crate::user_crate::split_source_sections(&info.src).dependencies;
+                match toml::from_str::<toml::value::Table>(&declared) {
+                    Ok(declared) if declared.contains_key(name) => vec![None],
+                    _ => vec![],
+                }
+            }
+        };
+        if versions.is_empty() {
+            continue;
+        }
+
+        if !allowlists.contains_key(&owner) {
+            allowlists.insert(owner, load_allowlist(owner)?);
+        }
+        let remaining = allowlists[&owner]
+            .get(name)
+            .into_iter()
+            .flat_map(|dependency| dependency.versions.keys())
+            .filter(|allowed| matches!(&version_req, Some(removed) if removed != *allowed))
+            .chain(narrowed_to.iter())
+            .collect::<Vec<_>>();
+
+        for version in versions {
+            let still_allowed = version
+                .as_deref()
+                .and_then(|version| Version::parse(version).ok())
+                .map_or(false, |version| {
+                    remaining.iter().any(|allowed| allowed.matches(&version))
+                });
+            if !still_allowed {
+                impacts.push(Impact {
+                    function,
+                    owner,
+                    version,
+                });
+            }
+        }
+    }
+    Ok(impacts)
 }
 
 pub(crate) fn parse_allowlist(contents: &str) -> Result<AllowList, Error> {
@@ -514,7 +992,7 @@ pub(crate) fn parse_allowlist(contents: &str) -> Result<AllowList, Error> {
 
 #[cfg(test)]
 mod tests {
-    use crate::allow_list::{parse_allowlist, Error, OrderedVersionReq};
+    use crate::allow_list::{parse_allowlist, validate_entries, Error, OrderedVersionReq};
     use semver::VersionReq;
 
     const TOML: &str = r#"
@@ -541,6 +1019,48 @@ d = { version = "=3.4.5", features = [ "x", "y", "z" ], default-features = false
         assert_eq!(parse_allowlist("a = { default-features = 'false' }"), Err(Error::UnsupportedValueType(toml::Value::String("false".to_string()))));
     }
 
+    #[test]
+    fn test_allowlist_validate_entries() {
+        let statuses = validate_entries(
+            r#"
+a = [ "=1.2.3", "1.2.3", { features = [ "x" ] } ]
+b = { version = ">=1, <2", default-features = false }
+"#,
+        );
+        let summary = statuses
+            .iter()
+            .map(|status| {
+                (
+                    status.name.as_deref().unwrap(),
+                    status.version_req.as_deref(),
+                    status.error.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("a", Some("=1.2.3"), None),
+                (
+                    "a",
+                    None,
+                    Some("`1.2.3` is not a supported version requirement.  Use wildcard (`*`), exact (`=x.y.z`), caret (`^x.y.z`), tilde (`~x.y.z`), or bounded ranges (`>=a.b.c, <=x.y.z`)")
+                ),
+                ("a", None, Some("Dependency entry is missing the `version` attribute")),
+                ("b", Some(">=1, <2"), None),
+            ]
+        );
+
+        let statuses = validate_entries("a = [");
+        assert_eq!(statuses.len(), 1);
+        assert!(statuses[0].name.is_none());
+        assert!(statuses[0]
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("Not a TOML file: "));
+    }
+
     #[test]
     fn test_allowlist_star() -> eyre::Result<()> {
         let allowed = parse_allowlist(TOML)?;
@@ -578,8 +1098,7 @@ d = { version = "=3.4.5", features = [ "x", "y", "z" ], default-features = false
     #[test]
     fn test_allowlist_version_formats() -> eyre::Result<()> {
         assert_eq!(OrderedVersionReq::try_from("1.2.3"), Err(Error::UnsupportedVersionReq("1.2.3".to_string())));
-        assert_eq!(OrderedVersionReq::try_from("^1.2.3"), Err(Error::UnsupportedVersionReq("^1.2.3".to_string())));
-        assert_eq!(OrderedVersionReq::try_from("~1.2.3"), Err(Error::UnsupportedVersionReq("~1.2.3".to_string())));
+        assert_eq!(OrderedVersionReq::try_from("^1.2.3-alpha"), Err(Error::UnsupportedVersionReq("^1.2.3-alpha".to_string())));
         assert_eq!(OrderedVersionReq::try_from(">1.2.3"), Err(Error::UnsupportedVersionReq(">1.2.3".to_string())));
         assert_eq!(OrderedVersionReq::try_from(">=1.2.3"), Err(Error::UnsupportedVersionReq(">=1.2.3".to_string())));
         assert_eq!(OrderedVersionReq::try_from("<1.2.3"), Err(Error::UnsupportedVersionReq("<1.2.3".to_string())));
@@ -592,4 +1111,35 @@ d = { version = "=3.4.5", features = [ "x", "y", "z" ], default-features = false
         assert_eq!(OrderedVersionReq::try_from("*"), Ok(OrderedVersionReq::try_from("*")?));
         Ok(())
     }
+
+    #[rustfmt::skip]
+    #[test]
+    fn test_allowlist_caret_tilde() -> eyre::Result<()> {
+        let expanded = |version: &str| OrderedVersionReq::try_from(version).map(|v| v.to_string());
+        assert_eq!(expanded("^1.2.3")?, ">=1.2.3, <2.0.0");
+        assert_eq!(expanded("^1.2")?, ">=1.2.0, <2.0.0");
+        assert_eq!(expanded("^1")?, ">=1.0.0, <2.0.0");
+        assert_eq!(expanded("^0.2.3")?, ">=0.2.3, <0.3.0");
+        assert_eq!(expanded("^0.0.3")?, ">=0.0.3, <0.0.4");
+        assert_eq!(expanded("^0.0")?, ">=0.0.0, <0.1.0");
+        assert_eq!(expanded("^0")?, ">=0.0.0, <1.0.0");
+        assert_eq!(expanded("~1.2.3")?, ">=1.2.3, <1.3.0");
+        assert_eq!(expanded("~1.2")?, ">=1.2.0, <1.3.0");
+        assert_eq!(expanded("~1")?, ">=1.0.0, <2.0.0");
+
+        let allowed = parse_allowlist(r#"serde = [ "^1.0", "~1.0.100", "=1.0.5", ">=1.0.0, <=2.0.0", "~0.9" ]"#)?;
+        let dep = allowed.get("serde").expect("no dependency for `serde`");
+        let versions = dep.versions.keys().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            vec![">=0.9.0, <0.10.0", ">=1.0.0, <2.0.0", ">=1.0.0, <=2.0.0", "=1.0.5", ">=1.0.100, <1.1.0"]
+        );
+
+        // the largest matching version is used
+        assert_eq!(dep.get_dependency_entry("1.0.150")?.get("version").and_then(|v| v.as_str()), Some("=1.0.150"));
+        assert_eq!(dep.get_dependency_entry("^1.0")?.get("version").and_then(|v| v.as_str()), Some(">=1.0.100, <1.1.0"));
+        assert_eq!(dep.get_dependency_entry("~0.9.1")?.get("version").and_then(|v| v.as_str()), Some(">=0.9.0, <0.10.0"));
+        assert_eq!(dep.get_dependency_entry("2.1"), Err(Error::VersionNotPermitted("2.1".to_string())));
+        Ok(())
+    }
 }
@@ -0,0 +1,244 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+//! An on-disk cache of decoded user function shared libraries, shared by every backend
+//!
+//! Loading a function from `pg_catalog.pg_proc.prosrc` means base64-decoding and decompressing its
+//! shared library, which each new backend would otherwise do on its first call
+//! to the function.  When `plrust.shared_library_cache` is on, the first backend to do so also
+//! writes the library to a directory under `plrust.work_dir`, and others `dlopen()` it from there.
+//!
+//! Entries are keyed by the function's Oid and `generation_number`, so an entry for an older
+//! version of a function is never used.  Nor is an entry trusted merely for being there:  `prosrc`
+//! records the SHA-256 of each library, and a cached library whose contents don't hash to it is
+//! ignored.  The library is hashed through the same open file it's then `dlopen()`'d through, so it
+//! can't be swapped for another in between.  Everything else we'd need to know about the library,
+//! such as its symbol and lints, still comes from `prosrc`.  Libraries are written to a temporary
+//! file and renamed into place, so a library is never modified once another backend might have
+//! `dlopen()`'d it.
+//!
+//! Each time a backend adds a library, it also removes the entries of this database's functions
+//! that have since been replaced or dropped, and of databases that have since been dropped.
+use std::fs::{DirBuilder, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use pgrx::{pg_sys, IntoDatum};
+use sha2::{Digest, Sha256};
+
+use crate::gucs;
+use crate::pgproc::PgProc;
+use crate::target::CompilationTarget;
+
+/// A shared library found in the cache, held open so that what's `dlopen()`'d is what was hashed
+#[derive(Debug)]
+pub(crate) struct CachedLibrary {
+    pub(crate) path: PathBuf,
+    pub(crate) file: File,
+}
+
+/// The cache directory, which is specific to this Postgres cluster in case another shares the
+/// same `plrust.work_dir`
+fn cache_dir() -> PathBuf {
+    // SAFETY:  the system identifier is set during startup, long before any user function can be called
+    let system_identifier = unsafe { pg_sys::GetSystemIdentifier() };
+    gucs::work_dir()
+        .join("shared_library_cache")
+        .join(system_identifier.to_string())
+}
+
+/// The filename prefix shared by every cache entry for the specified function
+fn entry_prefix(db_oid: pg_sys::Oid, fn_oid: pg_sys::Oid) -> String {
+    format!("{}_{}_", db_oid.as_u32(), fn_oid.as_u32())
+}
+
+/// The database, function, and generation of the cache entry named `name`, if it is one
+fn parse_entry_name(name: &str) -> Option<(pg_sys::Oid, pg_sys::Oid, u64)> {
+    let mut parts = name.splitn(4, '_');
+    let db_oid = parts.next()?.parse::<u32>().ok()?;
+    let fn_oid = parts.next()?.parse::<u32>().ok()?;
+    let generation_number = parts.next()?.parse().ok()?;
+    parts.next()?;
+    Some((db_oid.into(), fn_oid.into(), generation_number))
+}
+
+/// Returns the path to a cache entry's shared library
+fn entry_path(
+    db_oid: pg_sys::Oid,
+    fn_oid: pg_sys::Oid,
+    generation_number: u64,
+    target: &CompilationTarget,
+) -> PathBuf {
+    cache_dir().join(format!(
+        "{}{generation_number}_{target}{}",
+        entry_prefix(db_oid, fn_oid),
+        std::env::consts::DLL_SUFFIX
+    ))
+}
+
+/// The SHA-256 of a shared library, as lowercase hex, as recorded in `prosrc`
+pub(crate) fn sha256(shared_object: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(shared_object))
+}
+
+/// Find the shared library for the specified function in the cache, provided its contents hash to
+/// the `sha256` recorded in `prosrc`.  Any problem reading the cache is treated as a cache miss, as
+/// the library can always be loaded from `prosrc` instead.
+#[tracing::instrument(level = "debug")]
+pub(crate) fn lookup(
+    db_oid: pg_sys::Oid,
+    fn_oid: pg_sys::Oid,
+    generation_number: u64,
+    target: &CompilationTarget,
+    sha256: &str,
+) -> Option<CachedLibrary> {
+    let so_path = entry_path(db_oid, fn_oid, generation_number, target);
+    let mut file = File::open(&so_path).ok()?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).ok()?;
+
+    // it had better be the library `prosrc` says it is
+    if format!("{:x}", hasher.finalize()) != sha256 {
+        tracing::warn!(
+            "ignoring `{}`, as it isn't the library it should be",
+            so_path.display()
+        );
+        return None;
+    }
+
+    Some(CachedLibrary {
+        path: so_path,
+        file,
+    })
+}
+
+/// Add the shared library for the specified function to the cache, and remove any entries for
+/// other generations of the function
+#[tracing::instrument(level = "debug", skip(shared_object))]
+pub(crate) fn store(
+    db_oid: pg_sys::Oid,
+    fn_oid: pg_sys::Oid,
+    generation_number: u64,
+    target: &CompilationTarget,
+    shared_object: &[u8],
+) -> eyre::Result<()> {
+    let dir = cache_dir();
+    create_cache_dir(&dir)?;
+
+    let so_path = entry_path(db_oid, fn_oid, generation_number, target);
+    write_atomically(&dir, &so_path, shared_object)?;
+
+    // this function's older generations will never be used again, and nor will the entries of
+    // functions and databases that are gone
+    let prefix = entry_prefix(db_oid, fn_oid);
+    let current = format!("{prefix}{generation_number}_");
+    for entry in std::fs::read_dir(&dir)?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let stale = if name.starts_with(&prefix) {
+            !name.starts_with(&current)
+        } else {
+            parse_entry_name(&name).map_or(false, |(entry_db_oid, entry_fn_oid, generation)| {
+                is_stale(db_oid, entry_db_oid, entry_fn_oid, generation)
+            })
+        };
+        if stale {
+            // a concurrent backend may have beaten us to it
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+
+    Ok(())
+}
+
+/// Can the cache entry for generation `generation_number` of function `fn_oid`, in database
+/// `db_oid`, no longer be used?  We can only look up functions in our own database, `my_db_oid`, so
+/// the entries of other databases are stale only once their database is dropped.  A function that
+/// was replaced in a transaction that's yet to commit could have its new generation removed, but
+/// that just means it's loaded from `prosrc` again.
+fn is_stale(
+    my_db_oid: pg_sys::Oid,
+    db_oid: pg_sys::Oid,
+    fn_oid: pg_sys::Oid,
+    generation_number: u64,
+) -> bool {
+    if db_oid == my_db_oid {
+        PgProc::new(fn_oid).map_or(true, |pg_proc| {
+            pg_proc.generation_number() != generation_number
+        })
+    } else {
+        // SAFETY:  `pg_database` is a shared catalog, so its syscache can be searched from any
+        // database, and we're in a transaction as we're loading a function
+        !unsafe {
+            pg_sys::SearchSysCacheExists(
+                pg_sys::SysCacheIdentifier_DATABASEOID as _,
+                db_oid.into_datum().unwrap(),
+                pg_sys::Datum::from(0),
+                pg_sys::Datum::from(0),
+                pg_sys::Datum::from(0),
+            )
+        }
+    }
+}
+
+/// Remove every entry from the cache, returning how many files were removed
+#[tracing::instrument(level = "debug")]
+pub(crate) fn clear() -> eyre::Result<u64> {
+    let mut removed = 0;
+    match std::fs::read_dir(cache_dir()) {
+        Ok(entries) => {
+            for entry in entries.flatten() {
+                if std::fs::remove_file(entry.path()).is_ok() {
+                    removed += 1;
+                }
+            }
+        }
+        // no cache directory means an empty cache
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(removed)
+}
+
+fn create_cache_dir(dir: &Path) -> eyre::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+
+    #[cfg(unix)]
+    {
+        // nobody but us has any business in here
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+
+    Ok(builder.create(dir)?)
+}
+
+fn write_atomically(dir: &Path, path: &Path, contents: &[u8]) -> eyre::Result<()> {
+    let mut temp_file = tempfile::Builder::new().prefix(".tmp").tempfile_in(dir)?;
+    temp_file.write_all(contents)?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_names() {
+        assert_eq!(
+            Some((16384.into(), 24576.into(), 42)),
+            parse_entry_name("16384_24576_42_x86_64-unknown-linux-gnu.so")
+        );
+        // a library being written
+        assert_eq!(None, parse_entry_name(".tmpAbC123"));
+        assert_eq!(None, parse_entry_name("16384_24576_42"));
+    }
+}
@@ -0,0 +1,217 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+//! Compiling user functions without network access
+//!
+//! `plrust.local_crate_source` names a directory of crates for `cargo` to use instead of crates.io:
+//! either a local registry, as made by `cargo local-registry`, which has an `index` directory next
+//! to the `.crate` files, or a directory made by `cargo vendor`, which has each crate unpacked.  We
+//! replace crates.io with it through `cargo --config`, so there's no `.cargo/config.toml` to
+//! maintain, and run `cargo --offline`.
+//!
+//! A crate missing from the directory would otherwise only be noticed when a function that needs it
+//! is compiled, so whenever the setting is given a value we check that it has a version of each
+//! crate the allow-list file allows, and `plrust.check_local_crate_source()` checks it against
+//! whichever allow-list is configured.
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+use pgrx::pg_sys;
+use semver::{Version, VersionReq};
+
+use crate::allow_list::{self, AllowList};
+use crate::gucs;
+
+/// What we call `plrust.local_crate_source` in `cargo`'s configuration
+const SOURCE_NAME: &str = "plrust-local-crates";
+
+/// Point `cargo` at `plrust.local_crate_source`, if it's set, and keep it offline if it should be
+pub(crate) fn configure_cargo(command: &mut Command) {
+    if let Some(path) = gucs::local_crate_source() {
+        let kind = if is_local_registry(&path) {
+            "local-registry"
+        } else {
+            "directory"
+        };
+        // a TOML string, so it's quoted and escaped
+        let path = toml::Value::String(path.display().to_string());
+        command.arg("--config");
+        command.arg(format!("source.crates-io.replace-with = \"{SOURCE_NAME}\""));
+        command.arg("--config");
+        command.arg(format!("source.{SOURCE_NAME}.{kind} = {path}"));
+    }
+
+    if gucs::cargo_offline() {
+        command.arg("--offline");
+    }
+}
+
+/// Warn about any crates missing from `path`, the proposed `plrust.local_crate_source`.  This is
+/// called by the setting's check hook, outside of any transaction, so it can only consider an
+/// allow-list file, not `plrust.dependency_allowlist`
+pub(crate) fn warn_missing_crates(path: &Path) {
+    let missing = if allow_list::is_file_configured() {
+        allow_list::load_allowlist_file()
+            .and_then(|allow_list| missing_crates(path, Some(&allow_list)))
+    } else {
+        missing_crates(path, None)
+    };
+
+    match missing {
+        Ok(missing) if missing.is_empty() => {}
+        Ok(missing) => pgrx::warning!(
+            "`plrust.local_crate_source` ({}) has no version of these crates user functions may need: {}",
+            path.display(),
+            missing
+                .iter()
+                .map(|(name, version_req)| format!("{name} {version_req}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Err(e) => pgrx::warning!(
+            "could not check `plrust.local_crate_source` ({}): {e}",
+            path.display()
+        ),
+    }
+}
+
+/// The crates that user functions run by `role` may need, but that `plrust.local_crate_source` has
+/// no version of:  `plrust-trusted-pgrx`, and those the allow-list, file or table, allows `role`
+pub(crate) fn check(role: pg_sys::Oid) -> eyre::Result<Vec<(String, VersionReq)>> {
+    let path = gucs::local_crate_source()
+        .ok_or_else(|| eyre::eyre!("`plrust.local_crate_source` is not set"))?;
+    let allow_list = if allow_list::is_configured() {
+        Some(allow_list::load_allowlist(role)?)
+    } else {
+        None
+    };
+    missing_crates(&path, allow_list.as_ref())
+}
+
+/// The crates user functions may need but `path` has no version of.  Without an `allow_list`,
+/// that's only `plrust-trusted-pgrx`
+fn missing_crates(
+    path: &Path,
+    allow_list: Option<&AllowList>,
+) -> eyre::Result<Vec<(String, VersionReq)>> {
+    let available = available_versions(path)?;
+
+    let mut wanted = vec![(
+        "plrust-trusted-pgrx".to_string(),
+        VersionReq::parse(&gucs::get_trusted_pgrx_version())?,
+    )];
+    for (name, dependency) in allow_list.into_iter().flatten() {
+        for version_req in dependency.version_reqs() {
+            wanted.push((name.clone(), version_req.clone()));
+        }
+    }
+
+    Ok(wanted
+        .into_iter()
+        .filter(|(name, version_req)| {
+            !available.get(name).map_or(false, |versions| {
+                versions.iter().any(|version| version_req.matches(version))
+            })
+        })
+        .collect())
+}
+
+fn is_local_registry(path: &Path) -> bool {
+    path.join("index").is_dir()
+}
+
+/// Every version of every crate in `path`
+fn available_versions(path: &Path) -> eyre::Result<BTreeMap<String, Vec<Version>>> {
+    let mut available = BTreeMap::<String, Vec<Version>>::new();
+    let local_registry = is_local_registry(path);
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let name_and_version = if local_registry {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|file_name| file_name.strip_suffix(".crate"))
+                .and_then(parse_crate_file_name)
+        } else {
+            // `cargo vendor` unpacks each crate into its own directory, named after the crate and,
+            // if there's more than one version of it, the version.  Its `Cargo.toml` is definitive
+            match std::fs::read_to_string(entry.path().join("Cargo.toml")) {
+                Ok(cargo_toml) => parse_package(&cargo_toml),
+                Err(_) => None,
+            }
+        };
+
+        if let Some((name, version)) = name_and_version {
+            available.entry(name).or_default().push(version);
+        }
+    }
+    Ok(available)
+}
+
+/// Split a `.crate` file's name, less the extension, into the crate's name and version.  Both can
+/// contain dashes, but the version is whatever follows the first dash that leaves one to parse
+fn parse_crate_file_name(file_name: &str) -> Option<(String, Version)> {
+    file_name.match_indices('-').find_map(|(i, _)| {
+        Version::parse(&file_name[i + 1..])
+            .ok()
+            .map(|version| (file_name[..i].to_string(), version))
+    })
+}
+
+/// The name and version of the package a `Cargo.toml` describes
+fn parse_package(cargo_toml: &str) -> Option<(String, Version)> {
+    let cargo_toml = toml::from_str::<toml::value::Table>(cargo_toml).ok()?;
+    let package = cargo_toml.get("package")?.as_table()?;
+    Some((
+        package.get("name")?.as_str()?.to_string(),
+        Version::parse(package.get("version")?.as_str()?).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_file_names() {
+        assert_eq!(
+            parse_crate_file_name("plrust-trusted-pgrx-1.2.7"),
+            Some((
+                "plrust-trusted-pgrx".to_string(),
+                Version::parse("1.2.7").unwrap()
+            ))
+        );
+        assert_eq!(
+            parse_crate_file_name("wasm-bindgen-0.2.0-beta-1"),
+            Some((
+                "wasm-bindgen".to_string(),
+                Version::parse("0.2.0-beta-1").unwrap()
+            ))
+        );
+        assert_eq!(parse_crate_file_name("no-version"), None);
+    }
+
+    #[test]
+    fn vendored_packages() {
+        let cargo_toml = r#"
+            [package]
+            edition = "2021"
+            name = "rand"
+            version = "0.8.5"
+
+            [dependencies.rand_core]
+            version = "0.6.0"
+        "#;
+        assert_eq!(
+            parse_package(cargo_toml),
+            Some(("rand".to_string(), Version::parse("0.8.5").unwrap()))
+        );
+        assert_eq!(parse_package("[workspace]"), None);
+    }
+}
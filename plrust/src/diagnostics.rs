@@ -0,0 +1,141 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+//! Adding the column, constraint, and table a user function's `PgError` names to its ERROR
+//!
+//! `plrust-trusted-pgrx` raises a `PgError` through `pgrx`'s `ErrorReport`, which only carries the
+//! SQLSTATE, message, DETAIL, and HINT, and loses anything else Postgres had added to the ERROR
+//! when it's caught and raised again on its way back to us.  So the user function first hands
+//! the other fields to us through [`plrust_user_error_fields`].  When the ERROR arrives here,
+//! [`attach`] pushes an error context callback, which Postgres calls while finally raising it, and
+//! which sets the fields with `err_generic_string()`.
+//!
+//! Postgres resets `error_context_stack` wherever the ERROR is caught, so the callback is only ever
+//! called for that ERROR, or for others raised while it's on its way.  The latter are told apart by
+//! their SQLSTATE.
+use std::cell::RefCell;
+use std::ffi::{c_void, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use pgrx::{pg_guard, pg_sys};
+
+thread_local! {
+    /// The fields the `PgError` raised by the user function currently being called named, if any
+    static USER_ERROR_FIELDS: RefCell<Option<UserErrorFields>> = RefCell::new(None);
+}
+
+/// Whether [`USER_ERROR_FIELDS`] may be set, so that [`forget`] needn't touch it on every call
+static NAMED: AtomicBool = AtomicBool::new(false);
+
+struct UserErrorFields {
+    sqlstate: i32,
+    column: Option<String>,
+    constraint: Option<String>,
+    table: Option<String>,
+}
+
+/// The error context callback [`attach`] pushes.  Only one ERROR is ever on its way at a time, so
+/// it's never needed twice
+static mut SET_FIELDS: pg_sys::ErrorContextCallback = pg_sys::ErrorContextCallback {
+    previous: std::ptr::null_mut(),
+    callback: Some(set_fields),
+    arg: std::ptr::null_mut(),
+};
+
+/// Called by a user function with the column, constraint, and table named by the `PgError` it's
+/// about to raise with the SQLSTATE `sqlstate`.  A field that wasn't set is a null pointer
+///
+/// # Safety
+///
+/// Each non-null pointer must point to its length in bytes
+#[no_mangle]
+pub unsafe extern "C" fn plrust_user_error_fields(
+    sqlstate: i32,
+    column: *const u8,
+    column_len: usize,
+    constraint: *const u8,
+    constraint_len: usize,
+    table: *const u8,
+    table_len: usize,
+) {
+    let field = |field: *const u8, len: usize| {
+        // SAFETY:  the caller assures us this is valid
+        (!field.is_null()).then(|| unsafe {
+            String::from_utf8_lossy(std::slice::from_raw_parts(field, len)).into_owned()
+        })
+    };
+    let fields = UserErrorFields {
+        sqlstate,
+        column: field(column, column_len),
+        constraint: field(constraint, constraint_len),
+        table: field(table, table_len),
+    };
+    USER_ERROR_FIELDS.with(|pending| pending.replace(Some(fields)));
+    NAMED.store(true, Ordering::Relaxed);
+}
+
+/// Forget the fields of an error from a previous call, such as one that was caught
+#[inline]
+pub(crate) fn forget() {
+    if NAMED.load(Ordering::Relaxed) {
+        NAMED.store(false, Ordering::Relaxed);
+        USER_ERROR_FIELDS.with(|fields| fields.take());
+    }
+}
+
+/// Have the fields the user function named added to the ERROR it raised, which is on its way
+pub(crate) fn attach() {
+    if USER_ERROR_FIELDS.with(|fields| fields.borrow().is_none()) {
+        return;
+    }
+
+    // SAFETY:  Postgres is single-threaded and `error_context_stack` is a valid list, which
+    // `SET_FIELDS` stays valid in for as long as it's there
+    unsafe {
+        let callback = std::ptr::addr_of_mut!(SET_FIELDS);
+        let mut context = pg_sys::error_context_stack;
+        while !context.is_null() {
+            if context == callback {
+                return;
+            }
+            context = (*context).previous;
+        }
+
+        (*callback).previous = pg_sys::error_context_stack;
+        pg_sys::error_context_stack = callback;
+    }
+}
+
+#[pg_guard]
+unsafe extern "C" fn set_fields(_arg: *mut c_void) {
+    // SAFETY:  Postgres only calls us while raising an error
+    let sqlstate = unsafe { pg_sys::geterrcode() };
+    let Some(fields) = USER_ERROR_FIELDS.with(|fields| {
+        let mut fields = fields.borrow_mut();
+        match &*fields {
+            Some(pending) if pending.sqlstate == sqlstate => fields.take(),
+            _ => None,
+        }
+    }) else {
+        return;
+    };
+
+    for (code, value) in [
+        (pg_sys::PG_DIAG_COLUMN_NAME, fields.column),
+        (pg_sys::PG_DIAG_CONSTRAINT_NAME, fields.constraint),
+        (pg_sys::PG_DIAG_TABLE_NAME, fields.table),
+    ] {
+        // a name with a NUL in it can't be given to Postgres, nor be the name of anything
+        if let Some(value) = value.and_then(|value| CString::new(value).ok()) {
+            // SAFETY:  as above, and Postgres copies the string
+            unsafe {
+                pg_sys::err_generic_string(code as _, value.as_ptr());
+            }
+        }
+    }
+}
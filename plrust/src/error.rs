@@ -21,10 +21,20 @@ pub(crate) enum PlRustError {
     LibLoading(#[from] libloading::Error),
     #[error("`cargo build` failed")]
     CargoBuildFail,
+    #[error("`cargo metadata` failed")]
+    CargoMetadataFail,
+    #[error("Function's dependencies violate the dependency policy")]
+    DependencyPolicyViolation,
+    #[error("Function's dependencies do not pass `plrust.compile_lints`")]
+    StrictDependencyViolation,
     #[error("Generating `Cargo.toml`")]
     GeneratingCargoToml,
     #[error("Function `{0}` does not exist")]
     NoSuchFunction(pgrx::pg_sys::Oid),
+    #[error("Function `{0}` is not a PL/Rust function")]
+    NotPlRustFunction(pgrx::pg_sys::Oid),
+    #[error("Function `{0}` has not been compiled")]
+    FunctionNotCompiled(pgrx::pg_sys::Oid),
     #[error("Oid `{0}` was not mappable to a Rust type")]
     NoOidToRustMapping(pgrx::pg_sys::Oid),
     #[error("Generated Rust type (`{1}`) for `{0}` was unparsable: {2}")]
@@ -37,4 +47,6 @@ pub(crate) enum PlRustError {
     FunctionNotCompiledForTarget(CompilationTarget),
     #[error("Function not compiled with required lints: {0}")]
     MissingLints(LintSet),
+    #[error("Function was compiled with `plrust-trusted-pgrx` version `{0}`, but `{1}` is configured.  It must be recompiled")]
+    TrustedPgrxVersionMismatch(String, String),
 }
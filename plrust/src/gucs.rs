@@ -7,16 +7,17 @@ All rights reserved.
 Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
 */
 
-use std::ffi::CStr;
+use std::ffi::{c_char, c_void, CStr, CString};
 use std::path::PathBuf;
 use std::str::FromStr;
 
 use pgrx::guc::{GucContext, GucRegistry, GucSetting};
 use pgrx::pg_sys::AsPgCStr;
-use pgrx::{pg_sys, GucFlags};
+use pgrx::{pg_guard, pg_sys, GucFlags, PgMemoryContexts};
 
 use crate::target::{CompilationTarget, CrossCompilationTarget, TargetErr};
-use crate::{target, DEFAULT_LINTS};
+use crate::user_crate::profile::{AllowedOverrides, ProfileSetting};
+use crate::{allow_list, crate_source, preload, target, DEFAULT_LINTS};
 
 static PLRUST_WORK_DIR: GucSetting<Option<&'static CStr>> =
     GucSetting::<Option<&'static CStr>>::new(None);
@@ -24,7 +25,22 @@ pub(crate) static PLRUST_PATH_OVERRIDE: GucSetting<Option<&'static CStr>> =
     GucSetting::<Option<&'static CStr>>::new(None);
 static PLRUST_TRACING_LEVEL: GucSetting<Option<&'static CStr>> =
     GucSetting::<Option<&'static CStr>>::new(None);
-pub(crate) static PLRUST_ALLOWED_DEPENDENCIES: GucSetting<Option<&'static CStr>> =
+/// `plrust.allowed_dependencies` is defined directly, rather than through [`GucRegistry`], so it can
+/// have a check hook.  This is its value, which Postgres manages
+static mut PLRUST_ALLOWED_DEPENDENCIES: *mut c_char = std::ptr::null_mut();
+/// The contents of the `plrust.allowed_dependencies` file when its value was last accepted, which
+/// is the allow-list until the value next is.  This is the setting's "extra", which Postgres manages
+static mut PLRUST_ALLOWED_DEPENDENCIES_CONTENTS: *const c_char = std::ptr::null();
+static PLRUST_ALLOWED_DEPENDENCIES_TABLE: GucSetting<bool> = GucSetting::<bool>::new(false);
+static PLRUST_DENIED_CRATES: GucSetting<Option<&'static CStr>> =
+    GucSetting::<Option<&'static CStr>>::new(None);
+static PLRUST_ALLOWED_TRANSITIVE_CRATES: GucSetting<Option<&'static CStr>> =
+    GucSetting::<Option<&'static CStr>>::new(None);
+static PLRUST_ALLOWED_BUILD_SCRIPTS: GucSetting<Option<&'static CStr>> =
+    GucSetting::<Option<&'static CStr>>::new(None);
+static PLRUST_STRICT_DEPENDENCIES: GucSetting<Option<&'static CStr>> =
+    GucSetting::<Option<&'static CStr>>::new(None);
+static PLRUST_STRICT_DEPENDENCIES_EXEMPT: GucSetting<Option<&'static CStr>> =
     GucSetting::<Option<&'static CStr>>::new(None);
 static PLRUST_COMPILATION_TARGETS: GucSetting<Option<&'static CStr>> =
     GucSetting::<Option<&'static CStr>>::new(None);
@@ -32,6 +48,30 @@ pub(crate) static PLRUST_COMPILE_LINTS: GucSetting<Option<&'static CStr>> =
     GucSetting::<Option<&'static CStr>>::new(Some(DEFAULT_LINTS));
 pub(crate) static PLRUST_REQUIRED_LINTS: GucSetting<Option<&'static CStr>> =
     GucSetting::<Option<&'static CStr>>::new(None);
+/// `plrust.local_crate_source` is defined directly too, for its check hook
+static mut PLRUST_LOCAL_CRATE_SOURCE: *mut c_char = std::ptr::null_mut();
+static PLRUST_CARGO_OFFLINE: GucSetting<bool> = GucSetting::<bool>::new(false);
+static PLRUST_REUSE_COMPILED_FUNCTIONS: GucSetting<bool> = GucSetting::<bool>::new(false);
+/// The `plrust.profile_*` settings and `plrust.allowed_profile_overrides` are defined directly too,
+/// for check hooks that reject values `cargo` wouldn't accept
+static mut PLRUST_PROFILE_OPT_LEVEL: *mut c_char = std::ptr::null_mut();
+static mut PLRUST_PROFILE_LTO: *mut c_char = std::ptr::null_mut();
+static mut PLRUST_PROFILE_CODEGEN_UNITS: *mut c_char = std::ptr::null_mut();
+static mut PLRUST_PROFILE_DEBUG: *mut c_char = std::ptr::null_mut();
+static mut PLRUST_PROFILE_STRIP: *mut c_char = std::ptr::null_mut();
+static mut PLRUST_ALLOWED_PROFILE_OVERRIDES: *mut c_char = std::ptr::null_mut();
+static PLRUST_SHARED_LIBRARY_CACHE: GucSetting<bool> = GucSetting::<bool>::new(false);
+/// `plrust.track_functions`, `plrust.max_call_memory`, and `plrust.max_backend_memory` are defined
+/// directly too, for their assign hook, as call sites keep copies of them
+static mut PLRUST_TRACK_FUNCTIONS: bool = false;
+static PLRUST_CHECK_FOR_INTERRUPTS: GucSetting<bool> = GucSetting::<bool>::new(true);
+static mut PLRUST_MAX_CALL_MEMORY: i32 = 0;
+static mut PLRUST_MAX_BACKEND_MEMORY: i32 = 0;
+static mut PLRUST_DEBUG_UNCACHED_CALLS: bool = false;
+static PLRUST_MAX_LOADED_FUNCTIONS: GucSetting<i32> = GucSetting::<i32>::new(0);
+/// `plrust.preload_functions` is defined directly too, for its check hook
+static mut PLRUST_PRELOAD_FUNCTIONS: *mut c_char = std::ptr::null_mut();
+static PLRUST_PANIC_BACKTRACES: GucSetting<bool> = GucSetting::<bool>::new(false);
 
 const PGRX_VERSION_FROM_BUILD_RS: &'static str = concat!(
     env!(
@@ -74,11 +114,74 @@ pub(crate) fn init() {
         GucFlags::default(),
     );
 
+    // SAFETY:  Postgres copies neither the name nor the descriptions, so they must live as long as
+    // the backend does, and `PLRUST_ALLOWED_DEPENDENCIES` is only ever written by Postgres
+    unsafe {
+        let description = "The full path of a toml file containing crates and versions allowed when creating PL/Rust functions";
+        pg_sys::DefineCustomStringVariable(
+            PgMemoryContexts::TopMemoryContext.pstrdup("plrust.allowed_dependencies"),
+            PgMemoryContexts::TopMemoryContext.pstrdup(description),
+            PgMemoryContexts::TopMemoryContext.pstrdup(description),
+            std::ptr::addr_of_mut!(PLRUST_ALLOWED_DEPENDENCIES),
+            std::ptr::null(),
+            pg_sys::GucContext_PGC_SIGHUP,
+            0,
+            Some(check_allowed_dependencies),
+            Some(assign_allowed_dependencies),
+            None,
+        );
+    }
+
+    GucRegistry::define_bool_guc(
+        "plrust.allowed_dependencies_table",
+        "If true, the dependency allow-list is the `plrust.dependency_allowlist` table rather than the file named by `plrust.allowed_dependencies`",
+        "The table is managed with `plrust.allow_dependency()` and `plrust.revoke_dependency()`, and each database has its own",
+        &PLRUST_ALLOWED_DEPENDENCIES_TABLE,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
     GucRegistry::define_string_guc(
-        "plrust.allowed_dependencies",
-        "The full path of a toml file containing crates and versions allowed when creating PL/Rust functions",
-        "The full path of a toml file containing crates and versions allowed when creating PL/Rust functions",
-        &PLRUST_ALLOWED_DEPENDENCIES,
+        "plrust.denied_crates",
+        "A comma-separated list of crates that user functions may not depend on, directly or indirectly",
+        "The crates `plrust-trusted-pgrx` itself depends on are exempt",
+        &PLRUST_DENIED_CRATES,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_string_guc(
+        "plrust.allowed_transitive_crates",
+        "A comma-separated list of the crates user functions' dependencies may themselves depend on",
+        "If unspecified, any crate is allowed.  The crates `plrust-trusted-pgrx` itself depends on are exempt",
+        &PLRUST_ALLOWED_TRANSITIVE_CRATES,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_string_guc(
+        "plrust.allowed_build_scripts",
+        "A comma-separated list of the crates user functions' dependencies may include that have build scripts or are procedural macros",
+        "If unspecified, any crate is allowed.  The crates `plrust-trusted-pgrx` itself depends on are exempt",
+        &PLRUST_ALLOWED_BUILD_SCRIPTS,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_string_guc(
+        "plrust.strict_dependencies",
+        "A comma-separated list of the crates user functions' dependencies may include that must also pass `plrust.compile_lints`, or `*` for all of them",
+        "Each version of a crate is checked the first time it's used, and the result is kept in `plrust.work_dir`",
+        &PLRUST_STRICT_DEPENDENCIES,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_string_guc(
+        "plrust.strict_dependencies_exempt",
+        "A comma-separated list of crates, as `name` or `name@version`, that `plrust.strict_dependencies` doesn't apply to",
+        "For crates that have been vetted some other way",
+        &PLRUST_STRICT_DEPENDENCIES_EXEMPT,
         GucContext::Sighup,
         GucFlags::default(),
     );
@@ -118,6 +221,211 @@ pub(crate) fn init() {
         GucContext::Sighup,
         GucFlags::default(),
     );
+
+    // SAFETY:  as for `plrust.allowed_dependencies`
+    unsafe {
+        pg_sys::DefineCustomStringVariable(
+            PgMemoryContexts::TopMemoryContext.pstrdup("plrust.local_crate_source"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("The full path of a local registry or `cargo vendor` directory that user functions' dependencies are taken from, instead of crates.io"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("Setting this implies `plrust.cargo_offline`.  It must have `plrust-trusted-pgrx`, the crates it depends on, and any allowed dependencies"),
+            std::ptr::addr_of_mut!(PLRUST_LOCAL_CRATE_SOURCE),
+            std::ptr::null(),
+            pg_sys::GucContext_PGC_SIGHUP,
+            0,
+            Some(check_local_crate_source),
+            None,
+            None,
+        );
+    }
+
+    GucRegistry::define_bool_guc(
+        "plrust.cargo_offline",
+        "If true, `cargo` is run with `--offline` when compiling user functions, so it never uses the network",
+        "Dependencies must then be in `cargo`'s own cache already, or in `plrust.local_crate_source`",
+        &PLRUST_CARGO_OFFLINE,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_bool_guc(
+        "plrust.reuse_compiled_functions",
+        "If true, PL/Rust will reuse a function's existing compiled artifact, such as after a `pg_restore`, instead of compiling it again",
+        "The artifact is only reused for functions owned by a superuser, and when it was built for this host, this Postgres major version, and the current `plrust.trusted_pgrx_version` with all of `plrust.required_lints`",
+        &PLRUST_REUSE_COMPILED_FUNCTIONS,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+
+    // SAFETY:  as for `plrust.allowed_dependencies`
+    unsafe {
+        define_profile_guc(
+            "plrust.profile_opt_level",
+            "The `opt-level` to compile user functions with.  Supported values are: 0, 1, 2, 3, s, z",
+            "If unspecified, the default is 3",
+            std::ptr::addr_of_mut!(PLRUST_PROFILE_OPT_LEVEL),
+            b"3\0".as_ptr().cast(),
+            check_profile_opt_level,
+        );
+        define_profile_guc(
+            "plrust.profile_lto",
+            "The link-time optimization (`lto`) to compile user functions with.  Supported values are: off, thin, fat",
+            "If unspecified, cargo's default is used",
+            std::ptr::addr_of_mut!(PLRUST_PROFILE_LTO),
+            std::ptr::null(),
+            check_profile_lto,
+        );
+        define_profile_guc(
+            "plrust.profile_codegen_units",
+            "The number of `codegen-units` to compile user functions with, from 1 to 256",
+            "If unspecified, cargo's default is used",
+            std::ptr::addr_of_mut!(PLRUST_PROFILE_CODEGEN_UNITS),
+            std::ptr::null(),
+            check_profile_codegen_units,
+        );
+        define_profile_guc(
+            "plrust.profile_debug",
+            "The amount of `debug` info to compile user functions with.  Supported values are: none, line-directives-only, line-tables-only, limited, full",
+            "If unspecified, cargo's default is used",
+            std::ptr::addr_of_mut!(PLRUST_PROFILE_DEBUG),
+            std::ptr::null(),
+            check_profile_debug,
+        );
+        define_profile_guc(
+            "plrust.profile_strip",
+            "What to `strip` from compiled user functions.  Supported values are: none, debuginfo, symbols",
+            "If unspecified, cargo's default is used",
+            std::ptr::addr_of_mut!(PLRUST_PROFILE_STRIP),
+            std::ptr::null(),
+            check_profile_strip,
+        );
+        define_profile_guc(
+            "plrust.allowed_profile_overrides",
+            "A comma-separated list of `plrust.profile_*` settings user functions may override in a `[profile]` section, each optionally limited to a `|`-separated list of values",
+            "If unspecified, user functions may not override any settings.  For example: 'opt-level, lto = off|thin'",
+            std::ptr::addr_of_mut!(PLRUST_ALLOWED_PROFILE_OVERRIDES),
+            std::ptr::null(),
+            check_allowed_profile_overrides,
+        );
+    }
+
+    GucRegistry::define_bool_guc(
+        "plrust.shared_library_cache",
+        "If true, decoded user function shared libraries are cached on disk, under `plrust.work_dir`, for use by every backend",
+        "This avoids decoding a function's shared library from `pg_catalog.pg_proc` the first time each backend calls it",
+        &PLRUST_SHARED_LIBRARY_CACHE,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
+    // SAFETY:  as for `plrust.allowed_dependencies`
+    unsafe {
+        pg_sys::DefineCustomBoolVariable(
+            PgMemoryContexts::TopMemoryContext.pstrdup("plrust.track_functions"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("If true, PL/Rust collects execution, load, and compilation statistics for user functions"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("The statistics are shared by every backend and exposed by the `plrust.stat_functions` view"),
+            std::ptr::addr_of_mut!(PLRUST_TRACK_FUNCTIONS),
+            false,
+            pg_sys::GucContext_PGC_SUSET,
+            0,
+            None,
+            Some(assign_call_setting_bool),
+            None,
+        );
+    }
+
+    GucRegistry::define_bool_guc(
+        "plrust.check_for_interrupts",
+        "If true, user functions are compiled to check for interrupts, such as a query cancel, at the start of every function and loop body",
+        "Functions compiled without these checks can't be cancelled, nor stopped by `statement_timeout`, until they return",
+        &PLRUST_CHECK_FOR_INTERRUPTS,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
+    // SAFETY:  as for `plrust.allowed_dependencies`
+    unsafe {
+        pg_sys::DefineCustomIntVariable(
+            PgMemoryContexts::TopMemoryContext.pstrdup("plrust.max_call_memory"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("The most memory a single call to a user function may allocate, or 0 for no limit"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("Only memory allocated by the user function's Rust code is counted, not memory Postgres allocates on its behalf"),
+            std::ptr::addr_of_mut!(PLRUST_MAX_CALL_MEMORY),
+            0,
+            0,
+            i32::MAX,
+            pg_sys::GucContext_PGC_SUSET,
+            pg_sys::GUC_UNIT_KB as _,
+            None,
+            Some(assign_call_setting_int),
+            None,
+        );
+
+        pg_sys::DefineCustomIntVariable(
+            PgMemoryContexts::TopMemoryContext.pstrdup("plrust.max_backend_memory"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("The most memory all user functions may have allocated at once in a single backend, or 0 for no limit"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("Only memory allocated by user functions' Rust code is counted, not memory Postgres allocates on their behalf"),
+            std::ptr::addr_of_mut!(PLRUST_MAX_BACKEND_MEMORY),
+            0,
+            0,
+            i32::MAX,
+            pg_sys::GucContext_PGC_SUSET,
+            pg_sys::GUC_UNIT_KB as _,
+            None,
+            Some(assign_call_setting_int),
+            None,
+        );
+    }
+
+    GucRegistry::define_int_guc(
+        "plrust.max_loaded_functions",
+        "The most user functions a single backend keeps loaded at once, or 0 for no limit",
+        "When another function must be loaded, the least recently used is unloaded to make room for it",
+        &PLRUST_MAX_LOADED_FUNCTIONS,
+        0,
+        i32::MAX,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+
+    // SAFETY:  as for `plrust.allowed_dependencies`
+    unsafe {
+        pg_sys::DefineCustomStringVariable(
+            PgMemoryContexts::TopMemoryContext.pstrdup("plrust.preload_functions"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("A comma-separated list of user functions for `plrust.preload_functions()` to load"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("Each entry is a function signature, such as `public.my_function(int)`, or a schema name followed by `.*` for every PL/Rust function in that schema"),
+            std::ptr::addr_of_mut!(PLRUST_PRELOAD_FUNCTIONS),
+            std::ptr::null(),
+            pg_sys::GucContext_PGC_SUSET,
+            0,
+            Some(check_preload_functions),
+            None,
+            None,
+        );
+    }
+
+    // SAFETY:  as for `plrust.allowed_dependencies`
+    unsafe {
+        pg_sys::DefineCustomBoolVariable(
+            PgMemoryContexts::TopMemoryContext.pstrdup("plrust.debug_uncached_calls"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("If true, every call to a user function looks it up, rather than using what the call site cached"),
+            PgMemoryContexts::TopMemoryContext.pstrdup("Only meant for testing and benchmarking PL/Rust itself"),
+            std::ptr::addr_of_mut!(PLRUST_DEBUG_UNCACHED_CALLS),
+            false,
+            pg_sys::GucContext_PGC_SUSET,
+            pg_sys::GUC_NOT_IN_SAMPLE as _,
+            None,
+            Some(assign_call_setting_bool),
+            None,
+        );
+    }
+
+    GucRegistry::define_bool_guc(
+        "plrust.panic_backtraces",
+        "If true, errors from user functions that panic include a backtrace of the user's code in their DETAIL",
+        "Capturing a backtrace is slow, and it only has line numbers if functions are compiled with `plrust.profile_debug`, so this is meant for development servers",
+        &PLRUST_PANIC_BACKTRACES,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
 }
 
 pub(crate) fn work_dir() -> PathBuf {
@@ -131,6 +439,467 @@ pub(crate) fn work_dir() -> PathBuf {
     .expect("plrust.work_dir is not a valid path")
 }
 
+pub(crate) fn allowed_dependencies() -> Option<&'static CStr> {
+    // SAFETY:  Postgres only ever sets this to NULL or a valid string
+    unsafe {
+        let value = PLRUST_ALLOWED_DEPENDENCIES;
+        (!value.is_null()).then(|| CStr::from_ptr(value))
+    }
+}
+
+/// The contents of the `plrust.allowed_dependencies` file, as they were read when its value was
+/// last accepted, if it could be read
+pub(crate) fn allowed_dependencies_contents() -> Option<&'static str> {
+    // SAFETY:  this is NULL or the string [`check_allowed_dependencies`] made, which Postgres keeps
+    // for as long as it's the setting's "extra"
+    unsafe {
+        let contents = PLRUST_ALLOWED_DEPENDENCIES_CONTENTS;
+        (!contents.is_null())
+            .then(|| CStr::from_ptr(contents).to_str().ok())
+            .flatten()
+    }
+}
+
+/// Read the `plrust.allowed_dependencies` file, and keep its contents as the allow-list if the
+/// value is accepted, so that editing the file does nothing until the configuration is reloaded.
+/// A file with problems is rejected when the configuration is reloaded, so the allow-list stays as
+/// it was, rather than failing the next time a function is created.  When it's first set, such as
+/// at startup, rejecting it would leave no allow-list at all, which allows every dependency, so
+/// instead it's accepted with a WARNING
+#[pg_guard]
+unsafe extern "C" fn check_allowed_dependencies(
+    newval: *mut *mut c_char,
+    extra: *mut *mut c_void,
+    _source: pg_sys::GucSource,
+) -> bool {
+    // SAFETY:  Postgres gives us a valid pointer to the proposed value, which can be NULL
+    let path = unsafe { *newval };
+    if path.is_null() {
+        return true;
+    }
+    // SAFETY:  it's not NULL, so it's a valid string
+    let path = unsafe { CStr::from_ptr(path) }.to_string_lossy();
+
+    let (contents, problem) = match std::fs::read_to_string(&*path) {
+        Ok(contents) => {
+            let problem = allow_list::check_allowlist_file(&contents).err();
+            (Some(contents), problem)
+        }
+        Err(e) => (
+            None,
+            Some(format!("{}: {e}", allow_list::Error::CannotReadAllowList)),
+        ),
+    };
+
+    if let Some(problem) = problem {
+        // SAFETY:  we're only reading whether it's been set before
+        if unsafe { PLRUST_ALLOWED_DEPENDENCIES.is_null() } {
+            pgrx::warning!("`plrust.allowed_dependencies` file `{path}` has problems: {problem}");
+        } else {
+            // SAFETY:  Postgres reports this string in the DETAIL of the error rejecting the value
+            unsafe {
+                pg_sys::GUC_check_errdetail_string =
+                    PgMemoryContexts::CurrentMemoryContext.pstrdup(&problem);
+            }
+            return false;
+        }
+    }
+
+    // a file with a NUL in it isn't TOML, so it was accepted above with a WARNING, and leaving it
+    // unread is no different
+    if let Some(contents) = contents.and_then(|contents| CString::new(contents).ok()) {
+        let contents = contents.as_bytes_with_nul();
+        // SAFETY:  Postgres frees a setting's "extra" with `free()`, so it must come from
+        // `guc_malloc()`, which reports running out of memory at LOG and returns NULL
+        unsafe {
+            let copy = pg_sys::guc_malloc(pg_sys::LOG as _, contents.len());
+            if copy.is_null() {
+                return false;
+            }
+            std::ptr::copy_nonoverlapping(contents.as_ptr(), copy.cast(), contents.len());
+            *extra = copy;
+        }
+    }
+    true
+}
+
+#[pg_guard]
+unsafe extern "C" fn assign_allowed_dependencies(_newval: *const c_char, extra: *mut c_void) {
+    // SAFETY:  Postgres is single-threaded, and `extra` is what `check_allowed_dependencies` made
+    unsafe {
+        PLRUST_ALLOWED_DEPENDENCIES_CONTENTS = extra.cast();
+    }
+}
+
+pub(crate) fn allowed_dependencies_table() -> bool {
+    PLRUST_ALLOWED_DEPENDENCIES_TABLE.get()
+}
+
+pub(crate) fn local_crate_source() -> Option<PathBuf> {
+    // SAFETY:  Postgres only ever sets this to NULL or a valid string
+    let path = unsafe {
+        let value = PLRUST_LOCAL_CRATE_SOURCE;
+        (!value.is_null()).then(|| CStr::from_ptr(value))?
+    };
+    crate_source_path(path)
+}
+
+fn crate_source_path(path: &CStr) -> Option<PathBuf> {
+    let path = path
+        .to_str()
+        .expect("plrust.local_crate_source is not valid UTF8")
+        .trim();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// Warn about any crates user functions may need that a new `plrust.local_crate_source` is missing,
+/// rather than failing when a function that needs one is compiled.  Only the postmaster does, so
+/// that a reload doesn't have every backend warn about the same crates.  This never rejects the
+/// value, as user functions can't be compiled without it
+#[pg_guard]
+unsafe extern "C" fn check_local_crate_source(
+    newval: *mut *mut c_char,
+    _extra: *mut *mut c_void,
+    _source: pg_sys::GucSource,
+) -> bool {
+    // SAFETY:  Postgres gives us a valid pointer to the proposed value, which can be NULL, and sets
+    // `IsUnderPostmaster` long before any configuration is read
+    let path = unsafe {
+        let path = *newval;
+        if path.is_null() || pg_sys::IsUnderPostmaster {
+            return true;
+        }
+        CStr::from_ptr(path)
+    };
+
+    if let Some(path) = crate_source_path(path) {
+        crate_source::warn_missing_crates(&path);
+    }
+    true
+}
+
+/// Is `cargo` to be run with `--offline`?  It always is with a `plrust.local_crate_source`
+pub(crate) fn cargo_offline() -> bool {
+    PLRUST_CARGO_OFFLINE.get() || local_crate_source().is_some()
+}
+
+pub(crate) fn denied_crates() -> Option<Vec<String>> {
+    crate_list(&PLRUST_DENIED_CRATES, "plrust.denied_crates")
+}
+
+pub(crate) fn allowed_transitive_crates() -> Option<Vec<String>> {
+    crate_list(
+        &PLRUST_ALLOWED_TRANSITIVE_CRATES,
+        "plrust.allowed_transitive_crates",
+    )
+}
+
+pub(crate) fn allowed_build_scripts() -> Option<Vec<String>> {
+    crate_list(
+        &PLRUST_ALLOWED_BUILD_SCRIPTS,
+        "plrust.allowed_build_scripts",
+    )
+}
+
+pub(crate) fn strict_dependencies() -> Option<Vec<String>> {
+    crate_list(&PLRUST_STRICT_DEPENDENCIES, "plrust.strict_dependencies")
+}
+
+pub(crate) fn strict_dependencies_exempt() -> Option<Vec<String>> {
+    crate_list(
+        &PLRUST_STRICT_DEPENDENCIES_EXEMPT,
+        "plrust.strict_dependencies_exempt",
+    )
+}
+
+/// The crate names in a comma-separated list GUC, or `None` if it's unset.  Set but empty is an
+/// empty list
+fn crate_list(guc: &GucSetting<Option<&'static CStr>>, name: &str) -> Option<Vec<String>> {
+    let value = guc
+        .get()?
+        .to_str()
+        .unwrap_or_else(|_| panic!("{name} is not valid UTF8"));
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect(),
+    )
+}
+
+pub(crate) fn reuse_compiled_functions() -> bool {
+    PLRUST_REUSE_COMPILED_FUNCTIONS.get()
+}
+
+/// Define one of the `plrust.profile_*` settings, or `plrust.allowed_profile_overrides`, which are
+/// all reloaded with the configuration
+///
+/// # Safety
+///
+/// As for `plrust.allowed_dependencies`, `value` must be one of our statics, which only Postgres
+/// writes
+unsafe fn define_profile_guc(
+    name: &str,
+    short_desc: &str,
+    long_desc: &str,
+    value: *mut *mut c_char,
+    boot_value: *const c_char,
+    check_hook: unsafe extern "C" fn(*mut *mut c_char, *mut *mut c_void, pg_sys::GucSource) -> bool,
+) {
+    unsafe {
+        pg_sys::DefineCustomStringVariable(
+            PgMemoryContexts::TopMemoryContext.pstrdup(name),
+            PgMemoryContexts::TopMemoryContext.pstrdup(short_desc),
+            PgMemoryContexts::TopMemoryContext.pstrdup(long_desc),
+            value,
+            boot_value,
+            pg_sys::GucContext_PGC_SIGHUP,
+            0,
+            Some(check_hook),
+            None,
+            None,
+        );
+    }
+}
+
+/// The value of a string setting we defined directly, or `None` when it's unset or blank
+///
+/// # Safety
+///
+/// `value` must be NULL or a valid string, as Postgres only ever sets our settings to
+unsafe fn string_setting(value: *const c_char, name: &str) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+    // SAFETY:  the caller assures us it's a valid string
+    let value = unsafe { CStr::from_ptr(value) }
+        .to_str()
+        .unwrap_or_else(|_| panic!("{name} is not valid UTF8"))
+        .trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Reject a proposed setting with `problem` in the DETAIL of the error that says so
+fn reject_setting(problem: impl std::fmt::Display) -> bool {
+    // SAFETY:  Postgres reports this string in the DETAIL of the error rejecting the value
+    unsafe {
+        pg_sys::GUC_check_errdetail_string =
+            PgMemoryContexts::CurrentMemoryContext.pstrdup(&problem.to_string());
+    }
+    false
+}
+
+/// Reject a `plrust.profile_*` value `cargo` wouldn't accept for `setting`, as every function
+/// compiled with it would fail to.  An unset setting leaves it to `cargo`
+///
+/// # Safety
+///
+/// `newval` must be the pointer Postgres gives a check hook
+unsafe fn check_profile_setting(setting: ProfileSetting, newval: *mut *mut c_char) -> bool {
+    // SAFETY:  the caller assures us it's a valid pointer to the proposed value, which can be NULL
+    let Some(value) = (unsafe { string_setting(*newval, "plrust.profile_*") }) else {
+        return true;
+    };
+    match setting.normalize(&toml::Value::String(value)) {
+        Ok(_) => true,
+        Err(e) => reject_setting(e),
+    }
+}
+
+#[pg_guard]
+unsafe extern "C" fn check_profile_opt_level(
+    newval: *mut *mut c_char,
+    _extra: *mut *mut c_void,
+    _source: pg_sys::GucSource,
+) -> bool {
+    unsafe { check_profile_setting(ProfileSetting::OptLevel, newval) }
+}
+
+#[pg_guard]
+unsafe extern "C" fn check_profile_lto(
+    newval: *mut *mut c_char,
+    _extra: *mut *mut c_void,
+    _source: pg_sys::GucSource,
+) -> bool {
+    unsafe { check_profile_setting(ProfileSetting::Lto, newval) }
+}
+
+#[pg_guard]
+unsafe extern "C" fn check_profile_codegen_units(
+    newval: *mut *mut c_char,
+    _extra: *mut *mut c_void,
+    _source: pg_sys::GucSource,
+) -> bool {
+    unsafe { check_profile_setting(ProfileSetting::CodegenUnits, newval) }
+}
+
+#[pg_guard]
+unsafe extern "C" fn check_profile_debug(
+    newval: *mut *mut c_char,
+    _extra: *mut *mut c_void,
+    _source: pg_sys::GucSource,
+) -> bool {
+    unsafe { check_profile_setting(ProfileSetting::Debug, newval) }
+}
+
+#[pg_guard]
+unsafe extern "C" fn check_profile_strip(
+    newval: *mut *mut c_char,
+    _extra: *mut *mut c_void,
+    _source: pg_sys::GucSource,
+) -> bool {
+    unsafe { check_profile_setting(ProfileSetting::Strip, newval) }
+}
+
+/// Reject a `plrust.allowed_profile_overrides` that doesn't parse, as every function with a
+/// `[profile]` section would fail to compile with it
+#[pg_guard]
+unsafe extern "C" fn check_allowed_profile_overrides(
+    newval: *mut *mut c_char,
+    _extra: *mut *mut c_void,
+    _source: pg_sys::GucSource,
+) -> bool {
+    // SAFETY:  Postgres gives us a valid pointer to the proposed value, which can be NULL
+    let Some(value) = (unsafe { string_setting(*newval, "plrust.allowed_profile_overrides") })
+    else {
+        return true;
+    };
+    match AllowedOverrides::try_from(value.as_str()) {
+        Ok(_) => true,
+        Err(e) => reject_setting(e),
+    }
+}
+
+/// Returns the `[profile.release]` settings configured by the `plrust.profile_*` GUCs, skipping
+/// those that are unset
+pub(crate) fn release_profile_settings() -> Vec<(ProfileSetting, String)> {
+    // SAFETY:  Postgres only ever sets these to NULL or a valid string
+    unsafe {
+        [
+            (ProfileSetting::OptLevel, PLRUST_PROFILE_OPT_LEVEL),
+            (ProfileSetting::Lto, PLRUST_PROFILE_LTO),
+            (ProfileSetting::CodegenUnits, PLRUST_PROFILE_CODEGEN_UNITS),
+            (ProfileSetting::Debug, PLRUST_PROFILE_DEBUG),
+            (ProfileSetting::Strip, PLRUST_PROFILE_STRIP),
+        ]
+    }
+    .into_iter()
+    .filter_map(|(setting, value)| {
+        // SAFETY:  as above
+        let value = unsafe { string_setting(value, "plrust.profile_*") }?;
+        Some((setting, value))
+    })
+    .collect()
+}
+
+pub(crate) fn allowed_profile_overrides() -> String {
+    // SAFETY:  Postgres only ever sets this to NULL or a valid string
+    unsafe {
+        string_setting(
+            PLRUST_ALLOWED_PROFILE_OVERRIDES,
+            "plrust.allowed_profile_overrides",
+        )
+    }
+    .unwrap_or_default()
+}
+
+pub(crate) fn shared_library_cache() -> bool {
+    PLRUST_SHARED_LIBRARY_CACHE.get()
+}
+
+pub(crate) fn track_functions() -> bool {
+    // SAFETY:  Postgres is single-threaded, and it's only ever written by Postgres
+    unsafe { PLRUST_TRACK_FUNCTIONS }
+}
+
+/// Let call sites know that one of the settings they keep a copy of has changed
+#[pg_guard]
+unsafe extern "C" fn assign_call_setting_bool(_newval: bool, _extra: *mut c_void) {
+    crate::plrust::call_settings_changed();
+}
+
+#[pg_guard]
+unsafe extern "C" fn assign_call_setting_int(_newval: i32, _extra: *mut c_void) {
+    crate::plrust::call_settings_changed();
+}
+
+pub(crate) fn check_for_interrupts() -> bool {
+    PLRUST_CHECK_FOR_INTERRUPTS.get()
+}
+
+/// `plrust.max_call_memory`, in bytes.  Zero means there's no limit
+pub(crate) fn max_call_memory() -> usize {
+    // SAFETY:  as for `plrust.track_functions`
+    unsafe { PLRUST_MAX_CALL_MEMORY as usize * 1024 }
+}
+
+/// `plrust.max_backend_memory`, in bytes.  Zero means there's no limit
+pub(crate) fn max_backend_memory() -> usize {
+    // SAFETY:  as for `plrust.track_functions`
+    unsafe { PLRUST_MAX_BACKEND_MEMORY as usize * 1024 }
+}
+
+/// `plrust.max_loaded_functions`.  Zero means there's no limit
+pub(crate) fn max_loaded_functions() -> usize {
+    PLRUST_MAX_LOADED_FUNCTIONS.get() as usize
+}
+
+pub(crate) fn preload_functions() -> String {
+    // SAFETY:  Postgres only ever sets this to NULL or a valid string
+    unsafe {
+        let value = PLRUST_PRELOAD_FUNCTIONS;
+        if value.is_null() {
+            return String::new();
+        }
+        CStr::from_ptr(value)
+            .to_str()
+            .expect("plrust.preload_functions is not valid UTF8")
+            .to_string()
+    }
+}
+
+/// Reject a `plrust.preload_functions` with an entry that's neither a function signature nor
+/// `schema.*`, rather than failing when `plrust.preload_functions()` is called.  Whether the
+/// functions exist can only be checked then, in a database
+#[pg_guard]
+unsafe extern "C" fn check_preload_functions(
+    newval: *mut *mut c_char,
+    _extra: *mut *mut c_void,
+    _source: pg_sys::GucSource,
+) -> bool {
+    // SAFETY:  Postgres gives us a valid pointer to the proposed value, which can be NULL
+    let spec = unsafe {
+        let spec = *newval;
+        if spec.is_null() {
+            return true;
+        }
+        CStr::from_ptr(spec).to_string_lossy()
+    };
+
+    match preload::check_spec(&spec) {
+        Ok(()) => true,
+        Err(problem) => {
+            // SAFETY:  Postgres reports this string in the DETAIL of the error rejecting the value
+            unsafe {
+                pg_sys::GUC_check_errdetail_string =
+                    PgMemoryContexts::CurrentMemoryContext.pstrdup(&problem);
+            }
+            false
+        }
+    }
+}
+
+pub(crate) fn debug_uncached_calls() -> bool {
+    // SAFETY:  as for `plrust.track_functions`
+    unsafe { PLRUST_DEBUG_UNCACHED_CALLS }
+}
+
+pub(crate) fn panic_backtraces() -> bool {
+    PLRUST_PANIC_BACKTRACES.get()
+}
+
 pub(crate) fn tracing_level() -> tracing::Level {
     PLRUST_TRACING_LEVEL
         .get()
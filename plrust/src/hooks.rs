@@ -229,13 +229,13 @@ fn handle_alter_function(
 }
 
 /// Return the specified function's `prolang` value from `pg_catalog.pg_proc`
-fn lookup_func_lang(pg_proc_oid: pg_sys::Oid) -> eyre::Result<pg_sys::Oid> {
+pub(crate) fn lookup_func_lang(pg_proc_oid: pg_sys::Oid) -> eyre::Result<pg_sys::Oid> {
     let meta = PgProc::new(pg_proc_oid)?;
     Ok(meta.prolang())
 }
 
 /// Returns [`pg_sys::Oid::INVALID`] if the `plrust` language isn't installed in the current database
-fn plrust_lang_oid() -> pg_sys::Oid {
+pub(crate) fn plrust_lang_oid() -> pg_sys::Oid {
     static PLRUST_LANG_NAME: &[u8] = b"plrust\0"; // want this to look like a c string
 
     unsafe {
@@ -38,10 +38,18 @@ cfg_if::cfg_if! {
 }
 
 mod allow_list;
+mod cache;
+mod crate_source;
+mod diagnostics;
 mod error;
 mod gucs;
+mod library;
 mod logging;
+mod memory;
+mod panics;
 mod plrust;
+mod preload;
+mod stats;
 
 mod user_crate;
 
@@ -139,6 +147,7 @@ fn _PG_init() {
 
     gucs::init();
     hooks::init();
+    stats::init();
 
     use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
@@ -170,7 +179,6 @@ fn _PG_init() {
 CREATE FUNCTION plrust_call_handler() RETURNS language_handler
     LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
 ")]
-#[tracing::instrument(level = "debug")]
 unsafe fn plrust_call_handler(fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
     unsafe fn plrust_call_handler_inner(
         fcinfo: pg_sys::FunctionCallInfo,
@@ -198,6 +206,7 @@ unsafe fn plrust_call_handler(fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum
     }
 }
 
+/// The dependencies PL/Rust functions owned by the current role may use
 #[pg_extern]
 fn allowed_dependencies<'a>() -> Result<
     Option<
@@ -218,6 +227,649 @@ fn allowed_dependencies<'a>() -> Result<
     Ok(Some(TableIterator::new(allowed_dependencies)))
 }
 
+/// Check each entry of the dependency allow-list, returning a row for each with whether it's valid,
+/// its version requirement as PL/Rust understands it, and what's wrong with it if it isn't.  A
+/// problem with the allow-list as a whole, such as a file that can't be read, is a row without a
+/// `name`
+#[pg_extern]
+fn validate_allowlist<'a>() -> ::pgrx::iter::TableIterator<
+    'a,
+    (
+        name!(name, Option<String>),
+        name!(entry, Option<String>),
+        name!(valid, bool),
+        name!(version_req, Option<String>),
+        name!(error, Option<String>),
+    ),
+> {
+    let rows = allow_list::validate_allowlist()
+        .into_iter()
+        .map(|status| {
+            (
+                status.name,
+                status.entry,
+                status.error.is_none(),
+                status.version_req,
+                status.error,
+            )
+        })
+        .collect::<Vec<_>>();
+    TableIterator::new(rows)
+}
+
+/// Check that `plrust.local_crate_source` has a version of each crate user functions the current
+/// role creates may need:  `plrust-trusted-pgrx`, and those the dependency allow-list, file or
+/// table, allows the current role.  Returns a row for each crate it has no version of
+#[pg_extern]
+fn check_local_crate_source<'a>() -> Result<
+    Option<::pgrx::iter::TableIterator<'a, (name!(name, String), name!(version_req, String))>>,
+    Box<dyn std::error::Error + Send + Sync + 'static>,
+> {
+    // SAFETY:  we're in a transaction, so asking about our current user is fine
+    let role = unsafe { pg_sys::GetUserId() };
+    let missing = crate_source::check(role)?
+        .into_iter()
+        .map(|(name, version_req)| (name, version_req.to_string()))
+        .collect::<Vec<_>>();
+    Ok(Some(TableIterator::new(missing)))
+}
+
+extension_sql!(
+    r#"
+CREATE TABLE dependency_allowlist (
+    "name" TEXT NOT NULL,
+    "version_req" TEXT NOT NULL,
+    "features" TEXT[] NOT NULL DEFAULT '{}',
+    "default_features" bool NOT NULL DEFAULT true,
+    "role" regrole NOT NULL DEFAULT '0',
+    PRIMARY KEY ("name", "version_req", "role")
+);
+COMMENT ON TABLE dependency_allowlist IS 'The dependency allow-list, when `plrust.allowed_dependencies_table` is on';
+COMMENT ON COLUMN dependency_allowlist.role IS 'The role whose members may use the entry, or `-` for every role';
+
+-- functions are compiled as their owner, who must be able to read the allow-list
+GRANT SELECT ON dependency_allowlist TO PUBLIC;
+
+SELECT pg_catalog.pg_extension_config_dump('dependency_allowlist', '');
+"#,
+    name = "dependency_allowlist_table"
+);
+
+/// Allow PL/Rust functions owned by members of `role` to depend on a version of a crate, with the
+/// specified features, by adding it to the `plrust.dependency_allowlist` table.  The `public` role
+/// means every role.  Only superusers may do this.
+#[pg_extern(sql = r#"
+CREATE FUNCTION allow_dependency(
+    "name" TEXT,
+    "version_req" TEXT,
+    "features" TEXT[] DEFAULT '{}',
+    "default_features" bool DEFAULT true,
+    "role" TEXT DEFAULT 'public'
+) RETURNS void STRICT LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn allow_dependency(
+    name: &str,
+    version_req: &str,
+    features: Vec<String>,
+    default_features: bool,
+    role: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    // SAFETY:  we're in a transaction, so asking about our current user is fine
+    if !unsafe { pg_sys::superuser() } {
+        ereport!(
+            ERROR,
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            "must be superuser to change the PL/Rust dependency allow-list"
+        );
+    }
+
+    allow_list::allow_dependency(name, version_req, features, default_features, role)?;
+    Ok(())
+}
+
+/// Remove a version of a crate from the `plrust.dependency_allowlist` table, or every version of
+/// it when `version_req` is NULL, for `role`, or every role when it's NULL.  Returns how many
+/// entries were removed.  Only superusers may do this.
+#[pg_extern(sql = r#"
+CREATE FUNCTION revoke_dependency(
+    "name" TEXT,
+    "version_req" TEXT DEFAULT NULL,
+    "role" TEXT DEFAULT NULL
+) RETURNS bigint LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn revoke_dependency(
+    name: &str,
+    version_req: Option<&str>,
+    role: Option<&str>,
+) -> Result<i64, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    // SAFETY:  we're in a transaction, so asking about our current user is fine
+    if !unsafe { pg_sys::superuser() } {
+        ereport!(
+            ERROR,
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            "must be superuser to change the PL/Rust dependency allow-list"
+        );
+    }
+
+    Ok(allow_list::revoke_dependency(name, version_req, role)?)
+}
+
+/// List the PL/Rust functions that would no longer compile if the allow-list entry for `name` with
+/// `version_req` were removed, or every entry for `name` when it's NULL.  When `narrowed_to` is given,
+/// it's the entry that would replace the one removed.  `version` is what the function was built
+/// against, which is NULL for functions compiled before PL/Rust recorded that
+#[pg_extern(sql = r#"
+CREATE FUNCTION dependency_impact(
+    "name" TEXT,
+    "version_req" TEXT DEFAULT NULL,
+    "narrowed_to" TEXT DEFAULT NULL
+) RETURNS TABLE (
+    "function" regprocedure,
+    "owner" regrole,
+    "version" TEXT
+) LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn dependency_impact<'a>(
+    name: &str,
+    version_req: Option<&str>,
+    narrowed_to: Option<&str>,
+) -> Result<
+    ::pgrx::iter::TableIterator<
+        'a,
+        (
+            name!(function, pg_sys::Oid),
+            name!(owner, pg_sys::Oid),
+            name!(version, Option<String>),
+        ),
+    >,
+    Box<dyn std::error::Error + Send + Sync + 'static>,
+> {
+    let rows = allow_list::dependency_impact(name, version_req, narrowed_to)?
+        .into_iter()
+        .map(|impact| (impact.function, impact.owner, impact.version))
+        .collect::<Vec<_>>();
+    Ok(TableIterator::new(rows))
+}
+
+extension_sql!(
+    r#"
+CREATE TABLE libraries (
+    "name" TEXT NOT NULL PRIMARY KEY,
+    "version" TEXT NOT NULL,
+    "source" TEXT NOT NULL,
+    "dependencies" TEXT NOT NULL DEFAULT ''
+);
+COMMENT ON TABLE libraries IS 'Library crates PL/Rust functions may declare as dependencies';
+
+-- functions are compiled as their owner, who must be able to read the libraries they use
+GRANT SELECT ON libraries TO PUBLIC;
+
+SELECT pg_catalog.pg_extension_config_dump('libraries', '');
+"#,
+    name = "libraries_table"
+);
+
+/// Make a library crate, with the specified Rust `source` and TOML `dependencies`, available to
+/// PL/Rust functions, which depend on it by `name` like any other crate.  An existing library with
+/// that name is replaced.  Only superusers may do this.
+#[pg_extern(sql = r#"
+CREATE FUNCTION register_library(
+    "name" TEXT,
+    "source" TEXT,
+    "version" TEXT DEFAULT '0.1.0',
+    "dependencies" TEXT DEFAULT NULL
+) RETURNS void LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn register_library(
+    name: &str,
+    source: &str,
+    version: &str,
+    dependencies: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    // SAFETY:  we're in a transaction, so asking about our current user is fine
+    if !unsafe { pg_sys::superuser() } {
+        ereport!(
+            ERROR,
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            "must be superuser to change PL/Rust libraries"
+        );
+    }
+
+    library::register_library(name, source, version, dependencies)?;
+    Ok(())
+}
+
+/// Remove a library crate, returning whether it existed.  Functions already compiled with it are
+/// unaffected.  Only superusers may do this.
+#[pg_extern]
+fn unregister_library(
+    name: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    // SAFETY:  we're in a transaction, so asking about our current user is fine
+    if !unsafe { pg_sys::superuser() } {
+        ereport!(
+            ERROR,
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            "must be superuser to change PL/Rust libraries"
+        );
+    }
+
+    Ok(library::unregister_library(name)?)
+}
+
+/// Compile existing PL/Rust functions for each of the configured `plrust.compilation_targets` they
+/// haven't yet been compiled for, leaving their existing compiled artifacts alone.  When `func`
+/// is NULL, every PL/Rust function in the current database is considered.
+///
+/// Returns a row for each function and target that was compiled.  Only superusers may do this.
+///
+/// `pgrx` doesn't know about `regprocedure`, so we declare the CREATE FUNCTION statement ourselves.
+#[pg_extern(sql = r#"
+CREATE FUNCTION compile_missing_targets("func" regprocedure DEFAULT NULL) RETURNS TABLE (
+    "fn_oid" oid,
+    "target" TEXT
+) LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn compile_missing_targets<'a>(
+    func: default!(Option<pg_sys::Oid>, "NULL"),
+) -> Result<
+    ::pgrx::iter::TableIterator<'a, (name!(fn_oid, pg_sys::Oid), name!(target, String))>,
+    Box<dyn std::error::Error + Send + Sync + 'static>,
+> {
+    // SAFETY:  we're in a transaction, so asking about our current user is fine
+    if !unsafe { pg_sys::superuser() } {
+        ereport!(
+            ERROR,
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            "must be superuser to compile PL/Rust functions for additional targets"
+        );
+    }
+
+    let plrust_functions = plrust::plrust_function_oids()?;
+    let fn_oids = match func {
+        None => plrust_functions,
+        Some(fn_oid) if plrust_functions.contains(&fn_oid) => vec![fn_oid],
+        Some(fn_oid) => return Err(PlRustError::NotPlRustFunction(fn_oid).into()),
+    };
+
+    let mut compiled = Vec::new();
+    for fn_oid in fn_oids {
+        for target in plrust::compile_missing_targets(fn_oid)? {
+            compiled.push((fn_oid, target.to_string()));
+        }
+    }
+    Ok(TableIterator::new(compiled))
+}
+
+/// Describe what PL/Rust has stored for the specified function, with one row per compiled target.
+/// If what's stored can't be read, such as when the function hasn't been compiled, there's a single
+/// row with only `error` saying why, and a library whose size can't be read has that in `error`, so
+/// one such function doesn't keep the `plrust.functions` view from describing the rest.
+///
+/// `pgrx` doesn't know about `regprocedure`, so we declare the CREATE FUNCTION statement ourselves.
+#[pg_extern(sql = r#"
+CREATE FUNCTION function_info("func" regprocedure) RETURNS TABLE (
+    "source" TEXT,
+    "dependencies" TEXT,
+    "trusted_pgrx_version" TEXT,
+    "pg_major_version" INT,
+    "capabilities" TEXT[],
+    "target" TEXT,
+    "symbol" TEXT,
+    "binary_size" BIGINT,
+    "stored_size" BIGINT,
+    "lints" TEXT[],
+    "error" TEXT
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn function_info<'a>(
+    func: pg_sys::Oid,
+) -> Result<
+    ::pgrx::iter::TableIterator<
+        'a,
+        (
+            name!(source, Option<String>),
+            name!(dependencies, Option<String>),
+            name!(trusted_pgrx_version, Option<String>),
+            name!(pg_major_version, Option<i32>),
+            name!(capabilities, Option<Vec<String>>),
+            name!(target, Option<String>),
+            name!(symbol, Option<String>),
+            name!(binary_size, Option<i64>),
+            name!(stored_size, Option<i64>),
+            name!(lints, Option<Vec<String>>),
+            name!(error, Option<String>),
+        ),
+    >,
+    Box<dyn std::error::Error + Send + Sync + 'static>,
+> {
+    if hooks::lookup_func_lang(func)? != hooks::plrust_lang_oid() {
+        return Err(PlRustError::NotPlRustFunction(func).into());
+    }
+
+    let info = match prosrc::function_info(func) {
+        Ok(info) => info,
+        Err(e) => {
+            let row = (
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(e.to_string()),
+            );
+            return Ok(TableIterator::new(vec![row]));
+        }
+    };
+    let dependencies = user_crate::split_source_sections(&info.src).dependencies;
+    let capabilities = info
+        .capabilities
+        .iter()
+        .map(|capability| capability.to_string())
+        .collect::<Vec<_>>();
+
+    let rows = info
+        .libraries
+        .into_iter()
+        .map(|library| {
+            let (binary_size, error) = match library.binary_size {
+                Ok(binary_size) => (Some(binary_size as i64), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            (
+                Some(info.src.clone()),
+                Some(dependencies.clone()),
+                Some(info.trusted_pgrx_version.clone()),
+                info.pg_major_version.map(|version| version as i32),
+                Some(capabilities.clone()),
+                Some(library.target.to_string()),
+                library.symbol,
+                binary_size,
+                Some(library.stored_size as i64),
+                Some(library.lints.iter().collect::<Vec<String>>()),
+                error,
+            )
+        })
+        .collect::<Vec<_>>();
+    Ok(TableIterator::new(rows))
+}
+
+extension_sql!(
+    r#"
+CREATE VIEW functions AS
+SELECT p.oid::regprocedure AS "function",
+       pg_catalog.pg_get_userbyid(p.proowner) AS "owner",
+       info.*
+  FROM pg_catalog.pg_proc p
+  INNER JOIN pg_catalog.pg_language l ON p.prolang = l.oid
+  CROSS JOIN LATERAL function_info(p.oid::regprocedure) info
+ WHERE l.lanname = 'plrust';
+"#,
+    name = "functions_view",
+    requires = [function_info]
+);
+
+/// The crates, and their versions, the specified PL/Rust function's dependencies resolved to when
+/// it was compiled.  `direct` is whether the function declared the crate itself.  Functions compiled
+/// before PL/Rust recorded this have no rows
+#[pg_extern(sql = r#"
+CREATE FUNCTION resolved_dependencies("func" regprocedure) RETURNS TABLE (
+    "name" TEXT,
+    "version" TEXT,
+    "direct" bool
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn resolved_dependencies<'a>(
+    func: pg_sys::Oid,
+) -> Result<
+    ::pgrx::iter::TableIterator<
+        'a,
+        (
+            name!(name, String),
+            name!(version, String),
+            name!(direct, bool),
+        ),
+    >,
+    Box<dyn std::error::Error + Send + Sync + 'static>,
+> {
+    if hooks::lookup_func_lang(func)? != hooks::plrust_lang_oid() {
+        return Err(PlRustError::NotPlRustFunction(func).into());
+    }
+
+    let rows = prosrc::function_info(func)?
+        .dependencies
+        .unwrap_or_default()
+        .into_iter()
+        .map(|dependency| (dependency.name, dependency.version, dependency.direct))
+        .collect::<Vec<_>>();
+    Ok(TableIterator::new(rows))
+}
+
+extension_sql!(
+    r#"
+CREATE VIEW function_dependencies AS
+SELECT p.oid::regprocedure AS "function",
+       pg_catalog.pg_get_userbyid(p.proowner) AS "owner",
+       d.*
+  FROM pg_catalog.pg_proc p
+  INNER JOIN pg_catalog.pg_language l ON p.prolang = l.oid
+  CROSS JOIN LATERAL resolved_dependencies(p.oid::regprocedure) d
+ WHERE l.lanname = 'plrust';
+"#,
+    name = "function_dependencies_view",
+    requires = [resolved_dependencies]
+);
+
+/// Generate, but don't build, the crate for the specified PL/Rust function, returning its
+/// pretty-printed `lib.rs` and `Cargo.toml`.  Like compiling the function, this considers the
+/// current state of the database and PL/Rust's configuration
+#[pg_extern(sql = r#"
+CREATE FUNCTION generated_crate("func" regprocedure) RETURNS TABLE (
+    "lib_rs" TEXT,
+    "cargo_toml" TEXT
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn generated_crate_for_function<'a>(
+    func: pg_sys::Oid,
+) -> Result<
+    ::pgrx::iter::TableIterator<'a, (name!(lib_rs, String), name!(cargo_toml, String))>,
+    Box<dyn std::error::Error + Send + Sync + 'static>,
+> {
+    if hooks::lookup_func_lang(func)? != hooks::plrust_lang_oid() {
+        return Err(PlRustError::NotPlRustFunction(func).into());
+    }
+
+    let generated = plrust::generated_crate_for_function(func)?;
+    Ok(TableIterator::once(generated))
+}
+
+/// Generate, but don't build, the crate for a hypothetical PL/Rust function with the specified
+/// source code and signature, returning its pretty-printed `lib.rs` and `Cargo.toml`
+#[pg_extern(sql = r#"
+CREATE FUNCTION generated_crate(
+    "source" TEXT,
+    "argument_names" TEXT[] DEFAULT '{}',
+    "argument_types" regtype[] DEFAULT '{}',
+    "return_type" regtype DEFAULT 'void',
+    "returns_set" bool DEFAULT false,
+    "strict" bool DEFAULT false
+) RETURNS TABLE (
+    "lib_rs" TEXT,
+    "cargo_toml" TEXT
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn generated_crate_for_source<'a>(
+    source: &str,
+    argument_names: Vec<String>,
+    argument_types: Vec<pg_sys::Oid>,
+    return_type: pg_sys::Oid,
+    returns_set: bool,
+    strict: bool,
+) -> Result<
+    ::pgrx::iter::TableIterator<'a, (name!(lib_rs, String), name!(cargo_toml, String))>,
+    Box<dyn std::error::Error + Send + Sync + 'static>,
+> {
+    let generated = plrust::generated_crate_for_source(
+        source,
+        argument_names,
+        argument_types,
+        return_type,
+        returns_set,
+        strict,
+    )?;
+    Ok(TableIterator::once(generated))
+}
+
+/// Remove every shared library from the `plrust.shared_library_cache`, returning the number of
+/// files removed.  Only superusers may do this.
+#[pg_extern]
+fn clear_shared_library_cache() -> Result<i64, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    // SAFETY:  we're in a transaction, so asking about our current user is fine
+    if !unsafe { pg_sys::superuser() } {
+        ereport!(
+            ERROR,
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            "must be superuser to clear the PL/Rust shared library cache"
+        );
+    }
+
+    Ok(cache::clear()? as i64)
+}
+
+/// Describe each PL/Rust function the current backend has loaded.
+///
+/// `pgrx` doesn't know about `regprocedure`, so we declare the CREATE FUNCTION statement ourselves.
+#[pg_extern(sql = r#"
+CREATE FUNCTION loaded_functions() RETURNS TABLE (
+    "function" regprocedure,
+    "symbol" TEXT,
+    "calls" BIGINT,
+    "loaded_at" timestamptz
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn loaded_functions<'a>() -> ::pgrx::iter::TableIterator<
+    'a,
+    (
+        name!(function, pg_sys::Oid),
+        name!(symbol, String),
+        name!(calls, i64),
+        name!(loaded_at, TimestampWithTimeZone),
+    ),
+> {
+    TableIterator::new(plrust::loaded_functions())
+}
+
+/// Unload the specified PL/Rust function from the current backend, or every function when `func`
+/// is NULL, returning how many were unloaded.  Their shared libraries are closed at the end of the
+/// transaction, and a function is loaded again the next time it's called.
+#[pg_extern(sql = r#"
+CREATE FUNCTION unload_functions("func" regprocedure DEFAULT NULL) RETURNS bigint
+    LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn unload_functions(func: Option<pg_sys::Oid>) -> i64 {
+    plrust::unload_functions(func) as i64
+}
+
+/// Load the specified PL/Rust functions into the current backend, without calling them, so that
+/// their first call doesn't pay to load them.  `functions` is in the `plrust.preload_functions`
+/// format, and when NULL, the functions named by `plrust.preload_functions` are loaded.  The
+/// current user must be allowed to execute each function they name.
+///
+/// Returns how many functions were loaded, not counting those that already were.
+#[pg_extern]
+fn preload_functions(
+    functions: default!(Option<&str>, "NULL"),
+) -> Result<i64, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let loaded = match functions {
+        Some(functions) => preload::preload(functions, true)?,
+        None => preload::preload(&gucs::preload_functions(), false)?,
+    };
+    Ok(loaded as i64)
+}
+
+/// The statistics `plrust.track_functions` has collected for functions in the current database.
+/// Times are in milliseconds.  Use the `plrust.stat_functions` view instead.
+#[pg_extern]
+fn function_stats<'a>() -> ::pgrx::iter::TableIterator<
+    'a,
+    (
+        name!(funcid, pg_sys::Oid),
+        name!(calls, i64),
+        name!(errors, i64),
+        name!(total_time, f64),
+        name!(max_time, f64),
+        name!(loads, i64),
+        name!(load_time, f64),
+        name!(compiles, i64),
+        name!(compile_time, f64),
+    ),
+> {
+    let millis = |duration: std::time::Duration| duration.as_secs_f64() * 1000.0;
+    let rows = stats::current_database_stats()
+        .into_iter()
+        .map(|(fn_oid, stats)| {
+            (
+                fn_oid,
+                stats.calls as i64,
+                stats.errors as i64,
+                millis(stats.total_time),
+                millis(stats.max_time),
+                stats.loads as i64,
+                millis(stats.load_time),
+                stats.compiles as i64,
+                millis(stats.compile_time),
+            )
+        })
+        .collect::<Vec<_>>();
+    TableIterator::new(rows)
+}
+
+extension_sql!(
+    r#"
+CREATE VIEW stat_functions AS
+SELECT s.funcid,
+       n.nspname AS schemaname,
+       p.proname AS funcname,
+       s.calls,
+       s.errors,
+       s.total_time,
+       s.max_time,
+       s.loads,
+       s.load_time,
+       s.compiles,
+       s.compile_time
+  FROM function_stats() s
+  INNER JOIN pg_catalog.pg_proc p ON p.oid = s.funcid
+  INNER JOIN pg_catalog.pg_namespace n ON p.pronamespace = n.oid;
+"#,
+    name = "stat_functions_view",
+    requires = [function_stats]
+);
+
+/// Discard the statistics `plrust.track_functions` has collected for the specified function, or
+/// for every function in the current database when `func` is NULL.  Only superusers may do this.
+#[pg_extern(sql = r#"
+CREATE FUNCTION stat_functions_reset("func" regprocedure DEFAULT NULL) RETURNS void
+    LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+"#)]
+fn stat_functions_reset(func: Option<pg_sys::Oid>) {
+    // SAFETY:  we're in a transaction, so asking about our current user is fine
+    if !unsafe { pg_sys::superuser() } {
+        ereport!(
+            ERROR,
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            "must be superuser to reset PL/Rust function statistics"
+        );
+    }
+
+    stats::reset(func);
+}
+
 /// Called by Postgres, not you.
 /// # Safety
 /// Don't.
@@ -241,6 +893,14 @@ unsafe fn plrust_validator(fn_oid: pg_sys::Oid, fcinfo: pg_sys::FunctionCallInfo
         }
 
         unsafe { plrust::unload_function(fn_oid) };
+
+        // the administrator has asked that we keep existing compiled artifacts, which is generally
+        // only the case when restoring a database
+        if gucs::reuse_compiled_functions() && plrust::reuse_compiled_function(fn_oid)? {
+            tracing::debug!("reusing the existing compiled artifact for function {fn_oid}");
+            return Ok(());
+        }
+
         // NOTE:  We purposely ignore the `check_function_bodies` GUC for compilation as we need to
         // compile the function when it's created to avoid locking during function execution
         let output = plrust::compile_function(fn_oid)?;
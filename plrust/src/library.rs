@@ -0,0 +1,267 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+/*!
+Shared library crates, curated by the administrator
+
+A library's source is stored in the `plrust.libraries` table.  A user function declares one in its
+`[dependencies]` like any other crate, and PL/Rust writes the library into the function's crate
+directory, next to the function itself, and depends on it by path.  Being a path dependency, `cargo`
+doesn't cap its lints, so it's compiled by `plrustc` with the same `plrust.compile_lints` as the
+function.
+*/
+
+use std::path::Path;
+
+use eyre::{eyre, WrapErr};
+use pgrx::prelude::*;
+use pgrx::{spi, PgBuiltInOids};
+use quote::quote;
+use semver::{Version, VersionReq};
+
+use crate::user_crate::crating::cargo_toml_template;
+use crate::user_crate::lint::LintSet;
+use crate::user_crate::parse_source;
+
+/// Where, relative to a function's crate directory, its libraries are written
+const LIBRARIES_DIR: &str = "libraries";
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Library {
+    pub(crate) name: String,
+    version: Version,
+    source: String,
+    /// the library's own `[dependencies]`, which are subject to the allow-list of the function
+    /// using it
+    pub(crate) dependencies: toml::value::Table,
+}
+
+impl Library {
+    /// The entry for this library in the `[dependencies]` of a function's `Cargo.toml`
+    pub(crate) fn dependency_entry(&self) -> toml::Value {
+        let path = format!("{LIBRARIES_DIR}/{}", self.name);
+        toml::Value::Table(toml::toml! {
+            path = path
+        })
+    }
+
+    /// Generates the library's `lib.rs`, forbidding the same `lints` as the function using it
+    fn lib_rs(&self, lints: &LintSet) -> eyre::Result<String> {
+        let file = parse_source::<syn::File>(&self.source)
+            .wrap_err_with(|| format!("Parsing the source of library `{}`", self.name))?;
+        let header: syn::File = syn::parse2(quote! {
+            #![deny(unknown_lints)]
+            #lints
+        })
+        .wrap_err("Generating library lints")?;
+
+        Ok(format!(
+            "{}\n{}",
+            prettyplease::unparse(&header),
+            prettyplease::unparse(&file)
+        ))
+    }
+
+    fn cargo_toml(&self) -> eyre::Result<toml::value::Table> {
+        // the library uses the same `plrust-trusted-pgrx` as the function, whose features are
+        // unified with the function's, so it needn't say which Postgres version it's for
+        let template = cargo_toml_template(&self.name, "")?;
+        let mut dependencies = match template.get("dependencies") {
+            Some(toml::Value::Table(dependencies)) => dependencies.clone(),
+            _ => return Err(eyre!("the `Cargo.toml` template has no `[dependencies]`")),
+        };
+        dependencies.extend(self.dependencies.clone());
+
+        let name = self.name.clone();
+        let version = self.version.to_string();
+        let mut cargo_toml = toml::toml! {
+            [package]
+            edition = "2021"
+            name = name
+            version = version
+        };
+        cargo_toml.insert("dependencies".into(), toml::Value::Table(dependencies));
+        Ok(cargo_toml)
+    }
+
+    /// Write the library into the crate directory of the function that uses it
+    pub(crate) fn provision(&self, crate_dir: &Path, lints: &LintSet) -> eyre::Result<()> {
+        let library_dir = crate_dir.join(LIBRARIES_DIR).join(&self.name);
+        let src_dir = library_dir.join("src");
+        std::fs::create_dir_all(&src_dir).wrap_err("Could not create library directory")?;
+
+        std::fs::write(src_dir.join("lib.rs"), self.lib_rs(lints)?)
+            .wrap_err("Writing library `lib.rs`")?;
+        let cargo_toml =
+            toml::to_string(&self.cargo_toml()?).wrap_err("Stringifying library `Cargo.toml`")?;
+        std::fs::write(library_dir.join("Cargo.toml"), cargo_toml)
+            .wrap_err("Writing library `Cargo.toml`")?;
+        Ok(())
+    }
+}
+
+/// Remove the registered libraries from a function's `dependencies`, returning them.  The version
+/// the function asks for must match the library's
+pub(crate) fn take_libraries(dependencies: &mut toml::value::Table) -> eyre::Result<Vec<Library>> {
+    if dependencies.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let names = dependencies.keys().cloned().collect::<Vec<_>>();
+    let mut libraries = load_libraries(names)?;
+    for library in &mut libraries {
+        let wanted = dependencies
+            .remove(&library.name)
+            .expect("library should be a dependency");
+        let wanted_version = match &wanted {
+            toml::Value::String(version) => Some(version.as_str()),
+            toml::Value::Table(table) => {
+                if let Some(property) = table.keys().find(|property| *property != "version") {
+                    return Err(eyre!(
+                        "`{}` is a PL/Rust library, and its `{property}` property is not supported",
+                        library.name
+                    ));
+                }
+                table.get("version").and_then(|version| version.as_str())
+            }
+            _ => None,
+        }
+        .ok_or_else(|| eyre!("`{}` does not specify a version", library.name))?;
+
+        let wanted_version = VersionReq::parse(wanted_version)
+            .wrap_err_with(|| format!("`{}` has a malformed version", library.name))?;
+        if !wanted_version.matches(&library.version) {
+            return Err(eyre!(
+                "`{}` is a PL/Rust library at version `{}`, which doesn't match `{wanted_version}`",
+                library.name,
+                library.version
+            ));
+        }
+    }
+    Ok(libraries)
+}
+
+fn load_libraries(names: Vec<String>) -> eyre::Result<Vec<Library>> {
+    let rows = Spi::connect(|client| {
+        client
+            .select(
+                "SELECT name, version, source, dependencies
+                   FROM plrust.libraries
+                  WHERE name = ANY($1)
+                  ORDER BY name",
+                None,
+                Some(vec![(
+                    PgBuiltInOids::TEXTARRAYOID.oid(),
+                    names.into_datum(),
+                )]),
+            )?
+            .map(|row| {
+                Ok((
+                    row.get::<String>(1)?.unwrap_or_default(),
+                    row.get::<String>(2)?.unwrap_or_default(),
+                    row.get::<String>(3)?.unwrap_or_default(),
+                    row.get::<String>(4)?.unwrap_or_default(),
+                ))
+            })
+            .collect::<spi::Result<Vec<_>>>()
+    })?;
+
+    rows.into_iter()
+        .map(|(name, version, source, dependencies)| {
+            Ok(Library {
+                version: Version::parse(&version)
+                    .wrap_err_with(|| format!("library `{name}` has a malformed version"))?,
+                dependencies: parse_dependencies(&dependencies)
+                    .wrap_err_with(|| format!("library `{name}` has malformed dependencies"))?,
+                name,
+                source,
+            })
+        })
+        .collect()
+}
+
+fn parse_dependencies(dependencies: &str) -> eyre::Result<toml::value::Table> {
+    crate::user_crate::validate_user_dependencies(dependencies.to_string())
+}
+
+/// Crate names are ASCII alphanumerics, `-`, and `_`, and don't start with a digit
+fn is_valid_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Add a library to the `plrust.libraries` table, or replace the one already there with that name
+pub(crate) fn register_library(
+    name: &str,
+    source: &str,
+    version: &str,
+    dependencies: Option<&str>,
+) -> eyre::Result<()> {
+    let name = name.trim();
+    if !is_valid_name(name) {
+        return Err(eyre!("`{name}` is not a valid crate name"));
+    }
+    if name == "pgrx" {
+        return Err(eyre!("`pgrx` is reserved for `plrust-trusted-pgrx`"));
+    }
+    let version = Version::parse(version.trim())
+        .wrap_err_with(|| format!("`{version}` is not a valid version"))?;
+    syn::parse_file(source).wrap_err("Parsing library source")?;
+    let dependencies = dependencies.unwrap_or_default();
+    let parsed = parse_dependencies(dependencies).wrap_err("Parsing library dependencies")?;
+    if parsed.contains_key(name) {
+        return Err(eyre!("library `{name}` cannot depend on itself"));
+    }
+
+    Spi::run_with_args(
+        "INSERT INTO plrust.libraries (name, version, source, dependencies)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (name) DO UPDATE
+            SET version = excluded.version, source = excluded.source, dependencies = excluded.dependencies",
+        Some(vec![
+            (PgBuiltInOids::TEXTOID.oid(), name.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), version.to_string().into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), source.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), dependencies.into_datum()),
+        ]),
+    )?;
+    Ok(())
+}
+
+/// Remove a library from the `plrust.libraries` table, returning whether it was there.  Functions
+/// already compiled with it are unaffected
+pub(crate) fn unregister_library(name: &str) -> eyre::Result<bool> {
+    let removed = Spi::get_one_with_args::<bool>(
+        "WITH removed AS (
+            DELETE FROM plrust.libraries WHERE name = $1 RETURNING 1
+         )
+         SELECT count(*) > 0 FROM removed",
+        vec![(PgBuiltInOids::TEXTOID.oid(), name.trim().into_datum())],
+    )?;
+    Ok(removed.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_names() {
+        assert!(is_valid_name("helpers"));
+        assert!(is_valid_name("my-helpers_2"));
+        assert!(is_valid_name("_private"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("2fast"));
+        assert!(!is_valid_name("../etc"));
+        assert!(!is_valid_name("has space"));
+    }
+}
@@ -0,0 +1,255 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+//! Accounting for the memory user functions allocate, so it can be limited
+//!
+//! Memory user functions allocate from Rust doesn't come from a Postgres memory context, so
+//! Postgres can't limit it.  Instead, `plrust-trusted-pgrx` provides user crates with a global
+//! allocator that asks us, through [`plrust_memory_reserve`] and [`plrust_memory_release`], before
+//! allocating.  We keep a running total of what's allocated by the current call and by all calls
+//! in this backend, and refuse allocations that would exceed `plrust.max_call_memory` or
+//! `plrust.max_backend_memory`.
+//!
+//! User crates are compiled such that a refused allocation panics, which Postgres sees as an ERROR
+//! from the function.  [`CallMemory::limit_exceeded`] is how we then tell that it was caused by
+//! one of our limits, so we can raise a more useful one.
+//!
+//! Panicking allocates too, for the panic's payload and message.  Were those allocations refused,
+//! the panic would become a double panic, which aborts the backend, so after the first refusal
+//! during a call the limits are raised by [`UNWIND_RESERVE`] for the remainder of it.
+//!
+//! What a function still holds once its calls return, such as in its `static`s, is counted against
+//! its shared library's [`LibraryMemory`].  Rust never frees it once the library is closed, but
+//! neither can any function use it anymore, so it then stops counting against
+//! `plrust.max_backend_memory`.
+//!
+//! Memory allocated while neither limit is set is counted against the backend, but not against a
+//! call or shared library, as calls then skip this bookkeeping.
+//!
+//! Only functions compiled against a `plrust-trusted-pgrx` with the allocator can be limited, so
+//! while either limit is set, calling any other raises an ERROR.
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicIsize, AtomicU8, AtomicUsize, Ordering};
+
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::{pg_sys, PgLogLevel, PgSqlErrorCode};
+
+use crate::gucs;
+
+/// Bytes allocated by user functions in this backend, and not yet freed
+static BACKEND_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Bytes allocated, less those freed, during the current call.  This is negative if the call has
+/// freed more than it allocated, such as memory from a previous call
+static CALL_ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+/// Like [`CALL_ALLOCATED`], but not counting the calls the current call makes to other functions,
+/// so only what the called function's own shared library allocated
+static LIBRARY_ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+/// The limits in effect for the current call, in bytes, where zero means there's no limit
+static CALL_LIMIT: AtomicUsize = AtomicUsize::new(0);
+static BACKEND_LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+/// Which limit, if any, an allocation was refused for during the current call
+static EXCEEDED: AtomicU8 = AtomicU8::new(NOT_EXCEEDED);
+const NOT_EXCEEDED: u8 = 0;
+const CALL_LIMIT_EXCEEDED: u8 = 1;
+const BACKEND_LIMIT_EXCEEDED: u8 = 2;
+
+/// How far past its limits a call may go once it's been refused an allocation, so that it can
+/// unwind
+const UNWIND_RESERVE: usize = 64 * 1024;
+
+/// Called by a user function's global allocator before it allocates `size` bytes.  Returns
+/// `false`, and records why, if doing so would exceed one of our limits
+#[no_mangle]
+pub extern "C" fn plrust_memory_reserve(size: usize) -> bool {
+    let call = CALL_ALLOCATED.fetch_add(size as isize, Ordering::Relaxed) + size as isize;
+    LIBRARY_ALLOCATED.fetch_add(size as isize, Ordering::Relaxed);
+    let backend = BACKEND_ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+
+    let reserve = if allocation_refused() {
+        UNWIND_RESERVE
+    } else {
+        0
+    };
+    let call_limit = CALL_LIMIT.load(Ordering::Relaxed);
+    let backend_limit = BACKEND_LIMIT.load(Ordering::Relaxed);
+    let exceeded = if call_limit > 0 && call > call_limit.saturating_add(reserve) as isize {
+        CALL_LIMIT_EXCEEDED
+    } else if backend_limit > 0 && backend > backend_limit.saturating_add(reserve) {
+        BACKEND_LIMIT_EXCEEDED
+    } else {
+        return true;
+    };
+
+    plrust_memory_release(size);
+    // the first refusal is the one we report
+    let _ = EXCEEDED.compare_exchange(NOT_EXCEEDED, exceeded, Ordering::Relaxed, Ordering::Relaxed);
+    false
+}
+
+/// Called by a user function's global allocator after it frees `size` bytes, or fails to
+/// allocate `size` bytes it reserved
+#[no_mangle]
+pub extern "C" fn plrust_memory_release(size: usize) {
+    CALL_ALLOCATED.fetch_sub(size as isize, Ordering::Relaxed);
+    LIBRARY_ALLOCATED.fetch_sub(size as isize, Ordering::Relaxed);
+    release_from_backend(size);
+}
+
+fn release_from_backend(size: usize) {
+    // an allocation made before we started counting could be freed later, so never wrap around
+    let _ = BACKEND_ALLOCATED.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |allocated| {
+        Some(allocated.saturating_sub(size))
+    });
+}
+
+/// `plrust.max_call_memory` and `plrust.max_backend_memory`, in bytes, where zero means there's
+/// no limit
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct MemoryLimits {
+    call: usize,
+    backend: usize,
+}
+
+impl MemoryLimits {
+    pub(crate) fn current() -> Self {
+        Self {
+            call: gucs::max_call_memory(),
+            backend: gucs::max_backend_memory(),
+        }
+    }
+
+    /// Is either limit set?
+    #[inline]
+    pub(crate) fn any(&self) -> bool {
+        self.call > 0 || self.backend > 0
+    }
+}
+
+/// The memory a loaded shared library's functions hold between calls.  When the library is closed
+/// and this is dropped, it's no longer counted against `plrust.max_backend_memory`
+#[derive(Default)]
+pub(crate) struct LibraryMemory {
+    allocated: Cell<isize>,
+}
+
+impl Drop for LibraryMemory {
+    fn drop(&mut self) {
+        if let Ok(allocated) = usize::try_from(self.allocated.get()) {
+            release_from_backend(allocated);
+        }
+    }
+}
+
+/// Has the current call been refused an allocation?
+pub(crate) fn allocation_refused() -> bool {
+    EXCEEDED.load(Ordering::Relaxed) != NOT_EXCEEDED
+}
+
+/// Accounts for the memory allocated during a single call to a user function.  Calls can be
+/// nested, such as when a function uses SPI to call another, and when an inner call finishes what
+/// it allocated is counted against the outer call as well
+pub(crate) struct CallMemory<'a> {
+    library: &'a LibraryMemory,
+    outer_allocated: isize,
+    outer_library_allocated: isize,
+    outer_call_limit: usize,
+    outer_backend_limit: usize,
+}
+
+impl<'a> CallMemory<'a> {
+    /// Start accounting for a call to the user function `fn_oid`, whose shared library is
+    /// `library`, under `limits`.  Raises an ERROR if the function, not having been compiled with
+    /// `plrust-trusted-pgrx`'s allocator, as `limits_enforced` says, can't be held to them
+    pub(crate) fn start(
+        fn_oid: pg_sys::Oid,
+        library: &'a LibraryMemory,
+        limits_enforced: bool,
+        limits: MemoryLimits,
+    ) -> Self {
+        if !limits_enforced && limits.any() {
+            let guc = if limits.call > 0 {
+                "plrust.max_call_memory"
+            } else {
+                "plrust.max_backend_memory"
+            };
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_OBJECT_NOT_IN_PREREQUISITE_STATE,
+                format!(
+                    "PL/Rust function {} can't be held to `{guc}`",
+                    function_name(fn_oid)
+                ),
+                "plrust::memory::CallMemory::start",
+            )
+            .set_hint(
+                "It was compiled against a `plrust-trusted-pgrx` that can't limit its memory.  \
+                 Recompile it against a newer one with CREATE OR REPLACE FUNCTION",
+            )
+            .report(PgLogLevel::ERROR);
+        }
+
+        let this = Self {
+            library,
+            outer_allocated: CALL_ALLOCATED.swap(0, Ordering::Relaxed),
+            outer_library_allocated: LIBRARY_ALLOCATED.swap(0, Ordering::Relaxed),
+            outer_call_limit: CALL_LIMIT.load(Ordering::Relaxed),
+            outer_backend_limit: BACKEND_LIMIT.load(Ordering::Relaxed),
+        };
+        CALL_LIMIT.store(limits.call, Ordering::Relaxed);
+        BACKEND_LIMIT.store(limits.backend, Ordering::Relaxed);
+        EXCEEDED.store(NOT_EXCEEDED, Ordering::Relaxed);
+        this
+    }
+
+    /// If the user function `fn_oid` failed to allocate because it would have exceeded one of our
+    /// limits, returns a message saying so, identifying the function
+    pub(crate) fn limit_exceeded(&self, fn_oid: pg_sys::Oid) -> Option<String> {
+        let (guc, limit) = match EXCEEDED.load(Ordering::Relaxed) {
+            CALL_LIMIT_EXCEEDED => ("plrust.max_call_memory", CALL_LIMIT.load(Ordering::Relaxed)),
+            BACKEND_LIMIT_EXCEEDED => (
+                "plrust.max_backend_memory",
+                BACKEND_LIMIT.load(Ordering::Relaxed),
+            ),
+            _ => return None,
+        };
+
+        Some(format!(
+            "PL/Rust function {} exceeded `{guc}` of {}kB",
+            function_name(fn_oid),
+            limit / 1024
+        ))
+    }
+}
+
+impl Drop for CallMemory<'_> {
+    fn drop(&mut self) {
+        let allocated = CALL_ALLOCATED.load(Ordering::Relaxed);
+        CALL_ALLOCATED.store(self.outer_allocated + allocated, Ordering::Relaxed);
+        let library_allocated =
+            LIBRARY_ALLOCATED.swap(self.outer_library_allocated, Ordering::Relaxed);
+        self.library
+            .allocated
+            .set(self.library.allocated.get() + library_allocated);
+        CALL_LIMIT.store(self.outer_call_limit, Ordering::Relaxed);
+        BACKEND_LIMIT.store(self.outer_backend_limit, Ordering::Relaxed);
+        // if this call exceeded a limit, we've already said so
+        EXCEEDED.store(NOT_EXCEEDED, Ordering::Relaxed);
+    }
+}
+
+fn function_name(fn_oid: pg_sys::Oid) -> String {
+    // SAFETY:  `fn_oid` is a function being called, so it exists, and `format_procedure()` returns
+    // a palloc'd, NULL-terminated string
+    unsafe { CStr::from_ptr(pg_sys::format_procedure(fn_oid)) }
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,122 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+//! Reporting where in the user's code a user function panicked
+//!
+//! A panic's location is in the generated `lib.rs`, which users never see.  `plrust-trusted-pgrx`
+//! installs a panic hook in each user crate that maps it back to a line of the user's code, and
+//! hands it to us through [`plrust_user_panic`] along with the panic message.  When the panic then
+//! arrives here as an ERROR, [`UserPanic::report`] raises it again, naming the function and line.
+//!
+//! With `plrust.panic_backtraces` on, the hook also captures a backtrace of the frames in the user's
+//! code, which is reported as the ERROR's DETAIL.
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::{pg_sys, PgLogLevel, PgSqlErrorCode};
+
+use crate::{gucs, memory};
+
+thread_local! {
+    /// The panic reported by the user function currently being called, if it panicked
+    static USER_PANIC: RefCell<Option<UserPanic>> = RefCell::new(None);
+}
+
+/// Whether [`USER_PANIC`] may be set, so that [`forget`] needn't touch it on every call
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) struct UserPanic {
+    message: String,
+    /// The line of the user's code, or zero if the panic happened elsewhere, such as in a dependency
+    line: u32,
+    backtrace: String,
+}
+
+/// Called by a user function's panic hook to ask if it should capture a backtrace.  It shouldn't if
+/// it was refused an allocation, as capturing one allocates
+#[no_mangle]
+pub extern "C" fn plrust_capture_backtraces() -> bool {
+    gucs::panic_backtraces() && !memory::allocation_refused()
+}
+
+/// Called by a user function's panic hook with the panic message, where the panic happened, and
+/// the backtrace, if one was captured
+///
+/// # Safety
+///
+/// `message` and `backtrace` must point to `message_len` and `backtrace_len` bytes, respectively
+#[no_mangle]
+pub unsafe extern "C" fn plrust_user_panic(
+    message: *const u8,
+    message_len: usize,
+    line: u32,
+    backtrace: *const u8,
+    backtrace_len: usize,
+) {
+    // SAFETY:  the caller assures us these are valid
+    let (message, backtrace) = unsafe {
+        (
+            std::slice::from_raw_parts(message, message_len),
+            std::slice::from_raw_parts(backtrace, backtrace_len),
+        )
+    };
+    let user_panic = UserPanic {
+        message: String::from_utf8_lossy(message).into_owned(),
+        line,
+        backtrace: String::from_utf8_lossy(backtrace).into_owned(),
+    };
+    USER_PANIC.with(|panic| panic.replace(Some(user_panic)));
+    REPORTED.store(true, Ordering::Relaxed);
+}
+
+/// Forget any panic from a previous call, such as one the user function caught itself
+#[inline]
+pub(crate) fn forget() {
+    if REPORTED.load(Ordering::Relaxed) {
+        take();
+    }
+}
+
+/// The panic the current call reported, if it panicked
+pub(crate) fn take() -> Option<UserPanic> {
+    REPORTED.store(false, Ordering::Relaxed);
+    USER_PANIC.with(|panic| panic.take())
+}
+
+impl UserPanic {
+    /// Raise the panic as an ERROR from the user function `fn_oid`
+    pub(crate) fn report(self, fn_oid: pg_sys::Oid) -> ! {
+        // SAFETY:  `fn_oid` is the function we just called, so it exists, and `format_procedure()`
+        // returns a palloc'd, NULL-terminated string
+        let function = unsafe { CStr::from_ptr(pg_sys::format_procedure(fn_oid)) };
+        let function = function.to_string_lossy();
+        let message = match self.line {
+            0 => format!("PL/Rust function {function} panicked: {}", self.message),
+            line => format!(
+                "PL/Rust function {function} panicked at line {line}: {}",
+                self.message
+            ),
+        };
+
+        let mut report = ErrorReport::new(
+            PgSqlErrorCode::ERRCODE_INTERNAL_ERROR,
+            message,
+            "plrust::panics::UserPanic::report",
+        );
+        if !self.backtrace.is_empty() {
+            report = report.set_detail(format!(
+                "Backtrace of the user's code, most recent call first:\n{}",
+                self.backtrace.trim_end()
+            ));
+        }
+        report.report(PgLogLevel::ERROR);
+        unreachable!("ERROR-level reports don't return")
+    }
+}
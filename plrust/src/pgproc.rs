@@ -7,6 +7,7 @@ Use of this source code is governed by the PostgreSQL license that can be found
 */
 
 use crate::error::PlRustError;
+use crate::user_crate::parse_source;
 use pgrx::{pg_sys, FromDatum, IntoDatum, PgLogLevel, PgRelation, PgSqlErrorCode};
 use std::ptr::NonNull;
 
@@ -170,7 +171,8 @@ impl PgProc {
             .map(|name| {
                 let name = name.unwrap_or_else(|| String::default());
 
-                syn::parse_str::<syn::Ident>(&name)
+                parse_source::<syn::Ident>(&name)
+                    .map(|ident| (*ident).clone())
                     .unwrap_or_else(|_| {
                         static DETAIL:&'static str = "PL/Rust argument names must also be valid Rust identifiers.  Rust's identifier specification can be found at https://doc.rust-lang.org/reference/identifiers.html";
                         if name.is_empty() {
@@ -199,6 +201,11 @@ impl PgProc {
         self.get_attr(pg_sys::Anum_pg_proc_prorettype).unwrap()
     }
 
+    pub(crate) fn proowner(&self) -> pg_sys::Oid {
+        // SAFETY:  `proowner` has a NOT NULL constraint
+        self.get_attr(pg_sys::Anum_pg_proc_proowner).unwrap()
+    }
+
     pub(crate) fn proisstrict(&self) -> bool {
         // SAFETY: 'proisstrict' has a NOT NULL constraint
         self.get_attr(pg_sys::Anum_pg_proc_proisstrict).unwrap()
@@ -7,24 +7,185 @@ All rights reserved.
 Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
 */
 
+use std::panic::AssertUnwindSafe;
 use std::rc::Rc;
-use std::{cell::RefCell, collections::HashMap, process::Output};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    process::Output,
+};
 
 use eyre::WrapErr;
+use pgrx::datum::{clock_timestamp, TimestampWithTimeZone};
+use pgrx::pg_sys::pg_try::PgTryBuilder;
 use pgrx::{pg_sys::FunctionCallInfo, pg_sys::MyDatabaseId, prelude::*};
+use pgrx::{register_xact_callback, PgXactCallbackEvent};
 
-use crate::pgproc::PgProc;
+use crate::pgproc::{PgProc, ProArgMode};
+use crate::target::CompilationTarget;
+use crate::user_crate::crating::has_runtime_support;
 use crate::{
-    gucs, prosrc,
+    diagnostics, gucs, memory, panics, prosrc, stats,
     user_crate::{FnReady, UserCrate},
 };
 
 thread_local! {
-    pub(crate) static LOADED_SYMBOLS: RefCell<HashMap<pg_sys::Oid, Rc<UserCrate<FnReady>>>> = Default::default();
+    pub(crate) static LOADED_SYMBOLS: RefCell<HashMap<pg_sys::Oid, LoadedFunction>> = Default::default();
+
+    /// Counts calls to every user function, so we know which was least recently used
+    static CALL_CLOCK: Cell<u64> = Cell::new(0);
+
+    /// Changes whenever a function is unloaded or a setting a [`CachedFunction`] keeps a copy of
+    /// changes, which invalidates every [`CachedFunction`]
+    static EPOCH: Cell<u64> = Cell::new(0);
+
+    /// Functions unloaded during the current transaction, which are closed once it ends
+    static PENDING_CLOSE: RefCell<Vec<(pg_sys::Oid, Rc<UserCrate<FnReady>>)>> = Default::default();
+
+    /// The `pg_proc` syscache hash values Postgres has invalidated since we last checked, where
+    /// zero means the entire cache was invalidated
+    static INVALIDATED_HASH_VALUES: RefCell<Vec<u32>> = Default::default();
+}
+
+/// Past this many pending invalidations, we may as well consider every function invalidated
+const MAX_INVALIDATED_HASH_VALUES: usize = 1024;
+
+/// A user function loaded by this backend
+pub(crate) struct LoadedFunction {
+    user_crate: Rc<UserCrate<FnReady>>,
+    loaded_at: TimestampWithTimeZone,
+    /// Boxed so that a [`CachedFunction`] can point to it
+    counts: Box<CallCounts>,
+    /// The function's hash value in the `pg_proc` syscache, to match it with invalidations
+    hash_value: u32,
+}
+
+#[derive(Default)]
+struct CallCounts {
+    calls: Cell<u64>,
+    /// The [`CALL_CLOCK`] as of the last call to this function
+    last_used: Cell<u64>,
+}
+
+impl CallCounts {
+    fn count_call(&self) {
+        self.calls.set(self.calls.get() + 1);
+        self.last_used.set(CALL_CLOCK.with(|clock| {
+            clock.set(clock.get() + 1);
+            clock.get()
+        }));
+    }
+}
+
+/// What we keep in `flinfo->fn_extra`, so that later calls through the same `FmgrInfo`, such as for
+/// each row of a scan, needn't look up the function, nor the settings calls depend on.  It's only
+/// valid as long as [`EPOCH`] hasn't changed, as until then the function is still loaded.  And
+/// once unloaded, a function's shared library isn't closed until the end of the transaction, so
+/// the pointers remain valid for the duration of a call even if it unloads itself
+#[derive(Copy, Clone)]
+struct CachedFunction {
+    epoch: u64,
+    user_crate: *const UserCrate<FnReady>,
+    counts: *const CallCounts,
+    /// `plrust.track_functions`
+    track_functions: bool,
+    memory_limits: memory::MemoryLimits,
+}
+
+impl LoadedFunction {
+    fn new(fn_oid: pg_sys::Oid, user_crate: Rc<UserCrate<FnReady>>) -> Self {
+        // SAFETY:  the syscache is initialized long before we can load a function
+        let hash_value = unsafe {
+            pg_sys::GetSysCacheHashValue(
+                pg_sys::SysCacheIdentifier_PROCOID as _,
+                fn_oid.into_datum().unwrap(),
+                pg_sys::Datum::from(0),
+                pg_sys::Datum::from(0),
+                pg_sys::Datum::from(0),
+            )
+        };
+        Self {
+            user_crate,
+            loaded_at: clock_timestamp(),
+            counts: Default::default(),
+            hash_value,
+        }
+    }
 }
 
 pub(crate) fn init() {
-    ()
+    // SAFETY:  we're called from `_PG_init()`, and the callback is a valid, guarded function
+    unsafe {
+        pg_sys::CacheRegisterSyscacheCallback(
+            pg_sys::SysCacheIdentifier_PROCOID as _,
+            Some(invalidate_loaded_functions),
+            pg_sys::Datum::from(0),
+        );
+    }
+}
+
+/// Called when one of the settings a [`CachedFunction`] keeps a copy of changes
+pub(crate) fn call_settings_changed() {
+    EPOCH.with(|epoch| epoch.set(epoch.get() + 1));
+}
+
+/// Called by Postgres when entries in the `pg_proc` syscache are invalidated, such as when a
+/// function is replaced, altered, or dropped in any session.  We can't look at the catalog from
+/// here, so we only remember which entries were invalidated, for [`process_invalidations`].  Most
+/// won't be loaded functions, so until [`process_invalidations`] unloads one, call sites can keep
+/// what they've cached
+#[pg_guard]
+unsafe extern "C" fn invalidate_loaded_functions(
+    _arg: pg_sys::Datum,
+    _cache_id: std::os::raw::c_int,
+    hash_value: u32,
+) {
+    INVALIDATED_HASH_VALUES.with(|invalidated| {
+        let mut invalidated = invalidated.borrow_mut();
+        if invalidated.len() >= MAX_INVALIDATED_HASH_VALUES {
+            invalidated.clear();
+            invalidated.push(0);
+        } else if !invalidated.contains(&hash_value) {
+            invalidated.push(hash_value);
+        }
+    })
+}
+
+/// Unload the functions that have been replaced or dropped since we last checked.  The catalog is
+/// only consulted for those functions Postgres has told us were invalidated, so when nothing has
+/// changed this is cheap enough to do on every call
+fn process_invalidations(loaded_symbols: &mut HashMap<pg_sys::Oid, LoadedFunction>) {
+    let invalidated = INVALIDATED_HASH_VALUES.with(|invalidated| invalidated.take());
+    if invalidated.is_empty() {
+        return;
+    }
+
+    let everything = invalidated.contains(&0);
+    let maybe_changed = loaded_symbols
+        .iter()
+        .filter(|(_, loaded)| everything || invalidated.contains(&loaded.hash_value))
+        .map(|(fn_oid, _)| *fn_oid)
+        .collect::<Vec<_>>();
+
+    for fn_oid in maybe_changed {
+        // `generation_number` represents the transaction id and command id that inserted this
+        // row (in this case into pg_catalog.pg_proc).  If it's changed since we loaded the function,
+        // it was changed by (at least) the "OR REPLACE" bit of CREATE OR REPLACE or by an ALTER
+        // FUNCTION that changed one of the attributes of the function.  If it's gone, the function
+        // was dropped
+        let current_generation_number = PgProc::new(fn_oid)
+            .ok()
+            .map(|pg_proc| pg_proc.generation_number());
+        if current_generation_number != Some(loaded_symbols[&fn_oid].user_crate.generation_number())
+        {
+            tracing::trace!("Unloading function {fn_oid} as it was replaced or dropped");
+            let loaded = loaded_symbols.remove(&fn_oid).unwrap();
+            close_at_end_of_transaction(fn_oid, loaded.user_crate);
+            if current_generation_number.is_none() {
+                stats::forget_dropped(fn_oid);
+            }
+        }
+    }
 }
 
 #[tracing::instrument(level = "debug")]
@@ -32,73 +193,282 @@ pub(crate) unsafe fn unload_function(fn_oid: pg_sys::Oid) {
     LOADED_SYMBOLS.with(|loaded_symbols| {
         let mut loaded_symbols_handle = loaded_symbols.borrow_mut();
         let removed = loaded_symbols_handle.remove(&fn_oid);
-        if let Some(user_crate) = removed {
-            if let Ok(user_crate) = Rc::try_unwrap(user_crate) {
-                user_crate.close().unwrap();
-            }
+        if let Some(loaded) = removed {
+            // it might be replacing itself, so it may still be running
+            close_at_end_of_transaction(fn_oid, loaded.user_crate);
         }
     })
 }
 
+/// Unload the specified function, or every function when `fn_oid` is `None`, returning how many
+/// were unloaded.  They aren't closed until the end of the transaction, as this might be called from
+/// a set-returning function that isn't done with its shared library yet
 #[tracing::instrument(level = "debug")]
+pub(crate) fn unload_functions(fn_oid: Option<pg_sys::Oid>) -> usize {
+    LOADED_SYMBOLS.with(|loaded_symbols| {
+        let mut loaded_symbols_handle = loaded_symbols.borrow_mut();
+        let unloaded = match fn_oid {
+            Some(fn_oid) => loaded_symbols_handle
+                .remove_entry(&fn_oid)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            None => loaded_symbols_handle.drain().collect::<Vec<_>>(),
+        };
+
+        let count = unloaded.len();
+        for (fn_oid, loaded) in unloaded {
+            close_at_end_of_transaction(fn_oid, loaded.user_crate);
+        }
+        count
+    })
+}
+
+/// Describes each function this backend has loaded:  its Oid, symbol name, how many times it's
+/// been called, and when it was loaded
+pub(crate) fn loaded_functions() -> Vec<(pg_sys::Oid, String, i64, TimestampWithTimeZone)> {
+    LOADED_SYMBOLS.with(|loaded_symbols| {
+        let mut loaded_symbols_handle = loaded_symbols.borrow_mut();
+        process_invalidations(&mut loaded_symbols_handle);
+        loaded_symbols_handle
+            .iter()
+            .map(|(fn_oid, loaded)| {
+                (
+                    *fn_oid,
+                    loaded.user_crate.symbol_name().to_string(),
+                    loaded.counts.calls.get() as i64,
+                    loaded.loaded_at,
+                )
+            })
+            .collect()
+    })
+}
+
+/// If loading another function would exceed `plrust.max_loaded_functions`, unload the least
+/// recently used until it won't
+fn make_room_for_another(loaded_symbols: &mut HashMap<pg_sys::Oid, LoadedFunction>) {
+    let max_loaded_functions = gucs::max_loaded_functions();
+    if max_loaded_functions == 0 {
+        return;
+    }
+
+    while loaded_symbols.len() >= max_loaded_functions {
+        let Some(least_recently_used) = loaded_symbols
+            .iter()
+            .min_by_key(|(_, loaded)| loaded.counts.last_used.get())
+            .map(|(fn_oid, _)| *fn_oid)
+        else {
+            break;
+        };
+
+        tracing::debug!(
+            "unloading function {least_recently_used} to stay within `plrust.max_loaded_functions`"
+        );
+        let loaded = loaded_symbols.remove(&least_recently_used).unwrap();
+        close_at_end_of_transaction(least_recently_used, loaded.user_crate);
+    }
+}
+
+fn close_at_end_of_transaction(fn_oid: pg_sys::Oid, user_crate: Rc<UserCrate<FnReady>>) {
+    // it's no longer loaded, so call sites that cached it must look it up again
+    EPOCH.with(|epoch| epoch.set(epoch.get() + 1));
+    PENDING_CLOSE.with(|pending_close| {
+        let mut pending_close = pending_close.borrow_mut();
+        if pending_close.is_empty() {
+            register_xact_callback(PgXactCallbackEvent::Commit, close_pending);
+            register_xact_callback(PgXactCallbackEvent::Abort, close_pending);
+        }
+        pending_close.push((fn_oid, user_crate));
+    })
+}
+
+fn close_pending() {
+    let pending_close = PENDING_CLOSE.with(|pending_close| pending_close.take());
+    for (fn_oid, user_crate) in pending_close {
+        // if it's somehow still in use, its shared library is closed when it's dropped instead
+        if let Ok(user_crate) = Rc::try_unwrap(user_crate) {
+            if let Err(e) = user_crate.close() {
+                tracing::warn!("Failed to close unloaded function {fn_oid}.  Ignoring: {e}");
+            }
+        }
+    }
+}
+
 pub(crate) unsafe fn evaluate_function(
     fn_oid: pg_sys::Oid,
     fcinfo: FunctionCallInfo,
 ) -> eyre::Result<pg_sys::Datum> {
-    let user_crate_loaded = LOADED_SYMBOLS.with(|loaded_symbols| {
-        let mut loaded_symbols_handle = loaded_symbols.borrow_mut();
+    // SAFETY:  the call handler made sure Postgres gave us an `flinfo`
+    let flinfo = unsafe { (*fcinfo).flinfo };
+    let cached = match unsafe { cached_function(flinfo) } {
+        Some(cached) => cached,
+        None => unsafe { resolve_function(fn_oid, flinfo)? },
+    };
 
-        let user_crate_loaded = if let Some(current) = loaded_symbols_handle.get_mut(&fn_oid) {
-            let current_generation_number = PgProc::new(fn_oid)?.generation_number();
-
-            // `generation_number`` represents the transaction id and command id that inserted this
-            // row (in this case into pg_catalog.pg_proc).  So if it's changed from the last time we
-            // loaded the function then we have more work to do...
-            if current.generation_number() != current_generation_number {
-                // the function, which we've previously loaded, was changed by a concurrent session.
-                // This could be caused by (at least) the "OR REPLACE" bit of CREATE OR REPLACE or
-                // by an ALTER FUNCTION that changed one of the attributes of the function.
-                tracing::trace!(
-                    "Reloading function {fn_oid} due to change from concurrent session"
-                );
+    // SAFETY:  `cached` is current, so these point to a loaded function.  We're done with `counts`
+    // before calling the function, which could unload itself
+    let (user_crate, counts) = unsafe { (&*cached.user_crate, &*cached.counts) };
+    counts.count_call();
 
-                // load the new function
-                let new = prosrc::load(fn_oid)?;
+    // counts the call, and whether it raised an error, when it goes out of scope
+    let _timer = cached
+        .track_functions
+        .then(|| stats::CallTimer::start(fn_oid));
+    let memory = cached.memory_limits.any().then(|| {
+        memory::CallMemory::start(
+            fn_oid,
+            user_crate.memory(),
+            user_crate.runtime_support(),
+            cached.memory_limits,
+        )
+    });
 
-                // swap out the currently loaded function for the new one
-                let old = std::mem::replace(current, new);
+    // without its runtime support, a function can't report panics or error fields to us, and if
+    // its memory isn't limited, there's nothing to catch
+    if memory.is_none() && !user_crate.runtime_support() {
+        return Ok(unsafe { user_crate.evaluate(fcinfo) });
+    }
+
+    panics::forget();
+    diagnostics::forget();
+    let datum = PgTryBuilder::new(AssertUnwindSafe(|| unsafe { user_crate.evaluate(fcinfo) }))
+        .catch_others(|error| {
+            // the function panicked because we refused to let it allocate any more memory
+            if let Some(message) = memory
+                .as_ref()
+                .and_then(|memory| memory.limit_exceeded(fn_oid))
+            {
+                ereport!(
+                    ERROR,
+                    PgSqlErrorCode::ERRCODE_PROGRAM_LIMIT_EXCEEDED,
+                    message
+                )
+            }
 
-                // make a best effort to try and close the old loaded function.  If dlclose() fails,
-                // there's nothing we can do but carry on with the newly loaded version
-                if let Ok(old) = Rc::try_unwrap(old) {
-                    if let Err(e) = old.close() {
-                        tracing::warn!("Failed to close the old version of function {fn_oid}.  Ignoring, and continuing with new version: {e}");
-                    }
+            match panics::take() {
+                Some(user_panic) => user_panic.report(fn_oid),
+                None => {
+                    diagnostics::attach();
+                    error.rethrow()
                 }
             }
+        })
+        .execute();
+    Ok(datum)
+}
 
-            current
-        } else {
-            // loading the function for the first time
-            loaded_symbols_handle
-                .entry(fn_oid)
-                .or_insert(prosrc::load(fn_oid)?)
-        };
+/// The function `flinfo` cached the last time it was called, if it's still current
+///
+/// # Safety
+///
+/// `flinfo` must be the `FmgrInfo` Postgres is calling a user function through
+#[inline]
+unsafe fn cached_function(flinfo: *mut pg_sys::FmgrInfo) -> Option<CachedFunction> {
+    // SAFETY:  the caller assures us `flinfo` is valid, and [`resolve_function`] is the only thing
+    // that sets the `fn_extra` of functions that don't return sets
+    let cached = unsafe {
+        if (*flinfo).fn_retset || (*flinfo).fn_extra.is_null() {
+            return None;
+        }
+        *(*flinfo).fn_extra.cast::<CachedFunction>()
+    };
+    if cached.epoch != EPOCH.with(Cell::get) {
+        return None;
+    }
+
+    // a function Postgres has invalidated may have been replaced or dropped, which only
+    // [`resolve_function`] finds out
+    INVALIDATED_HASH_VALUES
+        .with(|invalidated| invalidated.borrow().is_empty())
+        .then_some(cached)
+}
+
+/// Find the function, loading it if need be, and cache it in `flinfo` for next time
+///
+/// # Safety
+///
+/// `flinfo` must be the `FmgrInfo` Postgres is calling the user function `fn_oid` through
+#[tracing::instrument(level = "debug", skip(flinfo))]
+unsafe fn resolve_function(
+    fn_oid: pg_sys::Oid,
+    flinfo: *mut pg_sys::FmgrInfo,
+) -> eyre::Result<CachedFunction> {
+    let cached = LOADED_SYMBOLS.with(|loaded_symbols| {
+        let mut loaded_symbols_handle = loaded_symbols.borrow_mut();
+
+        // any function replaced or dropped since we last checked, including this one, is unloaded
+        process_invalidations(&mut loaded_symbols_handle);
 
-        Ok::<_, eyre::Error>(user_crate_loaded.clone())
+        ensure_loaded(&mut loaded_symbols_handle, fn_oid)?;
+        let loaded = &loaded_symbols_handle[&fn_oid];
+        tracing::trace!(
+            "Resolved symbol {:?} for function {}",
+            loaded.user_crate.symbol_name(),
+            fn_oid
+        );
+
+        Ok::<_, eyre::Error>(CachedFunction {
+            epoch: EPOCH.with(Cell::get),
+            user_crate: Rc::as_ptr(&loaded.user_crate),
+            counts: &*loaded.counts,
+            track_functions: gucs::track_functions(),
+            memory_limits: memory::MemoryLimits::current(),
+        })
     })?;
 
-    tracing::trace!(
-        "Evaluating symbol {:?} for function {}",
-        user_crate_loaded.symbol_name(),
-        fn_oid
-    );
+    // SAFETY:  the caller assures us `flinfo` is valid, and its `fn_extra` is ours unless the
+    // function returns a set, in which case `pgrx` keeps the set's state there.  Setting
+    // `plrust.debug_uncached_calls` changed the epoch, so what's there already won't be used
+    unsafe {
+        if !(*flinfo).fn_retset && !gucs::debug_uncached_calls() {
+            if (*flinfo).fn_extra.is_null() {
+                (*flinfo).fn_extra = pg_sys::MemoryContextAlloc(
+                    (*flinfo).fn_mcxt,
+                    std::mem::size_of::<CachedFunction>(),
+                );
+            }
+            *(*flinfo).fn_extra.cast::<CachedFunction>() = cached;
+        }
+    }
+    Ok(cached)
+}
+
+/// Load the function, unless it already is, without calling it.  Returns `true` if it was loaded
+#[tracing::instrument(level = "debug")]
+pub(crate) fn preload_function(fn_oid: pg_sys::Oid) -> eyre::Result<bool> {
+    LOADED_SYMBOLS.with(|loaded_symbols| {
+        let mut loaded_symbols_handle = loaded_symbols.borrow_mut();
+        process_invalidations(&mut loaded_symbols_handle);
+        ensure_loaded(&mut loaded_symbols_handle, fn_oid)
+    })
+}
+
+/// Load the function if it isn't already, returning `true` if it was loaded
+fn ensure_loaded(
+    loaded_symbols: &mut HashMap<pg_sys::Oid, LoadedFunction>,
+    fn_oid: pg_sys::Oid,
+) -> eyre::Result<bool> {
+    if loaded_symbols.contains_key(&fn_oid) {
+        return Ok(false);
+    }
+
+    // loading the function for the first time, which might mean unloading another
+    let new = LoadedFunction::new(fn_oid, load_function(fn_oid)?);
+    make_room_for_another(loaded_symbols);
+    loaded_symbols.insert(fn_oid, new);
+    Ok(true)
+}
 
-    Ok(unsafe { user_crate_loaded.evaluate(fcinfo) })
+/// Load the function's shared library, recording how long that took
+fn load_function(fn_oid: pg_sys::Oid) -> eyre::Result<Rc<UserCrate<FnReady>>> {
+    let start = std::time::Instant::now();
+    let loaded = prosrc::load(fn_oid)?;
+    stats::record_load(fn_oid, start.elapsed());
+    Ok(loaded)
 }
 
 #[tracing::instrument(level = "debug")]
 pub(crate) fn compile_function(fn_oid: pg_sys::Oid) -> eyre::Result<Output> {
+    let start = std::time::Instant::now();
     let work_dir = gucs::work_dir();
     let target_dir = work_dir.join("target");
     // SAFETY: Postgres globally sets this to `const InvalidOid`, so is always read-safe,
@@ -110,6 +480,7 @@ pub(crate) fn compile_function(fn_oid: pg_sys::Oid) -> eyre::Result<Output> {
     // We want to introduce validation here.
     let crate_dir = provisioned.crate_dir().to_path_buf();
     let (validated, _output) = provisioned.validate(target_dir.as_path())?;
+    let dependencies = validated.dependencies().to_vec();
     let target_builds = validated.build(target_dir.as_path())?;
 
     // we gotta have at least one built crate and it's for this host's target triple
@@ -123,7 +494,14 @@ pub(crate) fn compile_function(fn_oid: pg_sys::Oid) -> eyre::Result<Output> {
         let (target_triple, shared_object, lints) = built.into_inner();
 
         // store the shared objects in our table
-        prosrc::create_or_replace_function(db_oid, fn_oid, target_triple, shared_object, lints)?;
+        prosrc::create_or_replace_function(
+            db_oid,
+            fn_oid,
+            target_triple,
+            shared_object,
+            lints,
+            Some(&dependencies),
+        )?;
     }
 
     // cleanup after ourselves
@@ -133,9 +511,174 @@ pub(crate) fn compile_function(fn_oid: pg_sys::Oid) -> eyre::Result<Output> {
         crate_dir.display()
     ))?;
 
+    if memory::MemoryLimits::current().any()
+        && !has_runtime_support(&gucs::get_trusted_pgrx_version())
+    {
+        pgrx::warning!(
+            "`plrust.max_call_memory` and `plrust.max_backend_memory` can't be enforced for \
+             functions compiled against `plrust-trusted-pgrx` {}, so calling this one will fail \
+             while either is set",
+            gucs::get_trusted_pgrx_version()
+        );
+    }
+
+    stats::record_compile(fn_oid, start.elapsed());
     Ok(this_output.unwrap())
 }
 
+/// Compile the function for each of the configured `plrust.compilation_targets` it doesn't
+/// already have a compiled artifact for.  Existing artifacts, including the one for this host, are
+/// left alone.
+///
+/// Returns the targets that were compiled, which is empty if there was nothing to do.
+#[tracing::instrument(level = "debug")]
+pub(crate) fn compile_missing_targets(fn_oid: pg_sys::Oid) -> eyre::Result<Vec<CompilationTarget>> {
+    let (_, cross_compilation_targets) = gucs::compilation_targets()?;
+    let missing = prosrc::missing_compilation_targets(fn_oid, cross_compilation_targets)?;
+    if missing.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let work_dir = gucs::work_dir();
+    let target_dir = work_dir.join("target");
+    // SAFETY: Postgres globally sets this to `const InvalidOid`, so is always read-safe,
+    // then writes it only during initialization, so we should not be racing anyone.
+    let db_oid = unsafe { MyDatabaseId };
+
+    let generated = unsafe { UserCrate::try_from_fn_oid(db_oid, fn_oid)? };
+    let provisioned = generated.provision(&work_dir)?;
+    let crate_dir = provisioned.crate_dir().to_path_buf();
+    let (validated, _output) = provisioned.validate(target_dir.as_path())?;
+    let target_builds = validated.build_for(target_dir.as_path(), missing)?;
+
+    let mut compiled = Vec::with_capacity(target_builds.len());
+    for (built, _output) in target_builds {
+        let (target_triple, shared_object, lints) = built.into_inner();
+        compiled.push(target_triple.clone());
+
+        // store the shared objects in our table, alongside the ones already there.  the
+        // dependencies already recorded are the ones this host's library was built against
+        prosrc::create_or_replace_function(
+            db_oid,
+            fn_oid,
+            target_triple,
+            shared_object,
+            lints,
+            None,
+        )?;
+    }
+
+    // cleanup after ourselves
+    tracing::trace!("removing {}", crate_dir.display());
+    std::fs::remove_dir_all(&crate_dir).wrap_err(format!(
+        "Problem deleting temporary crate directory at '{}'",
+        crate_dir.display()
+    ))?;
+
+    Ok(compiled)
+}
+
+/// Generate, but don't build, the crate for an existing PL/Rust function.  Returns its `lib.rs` and
+/// `Cargo.toml`, as they'd be written to disk during compilation
+#[tracing::instrument(level = "debug")]
+pub(crate) fn generated_crate_for_function(fn_oid: pg_sys::Oid) -> eyre::Result<(String, String)> {
+    // SAFETY: Postgres globally sets this to `const InvalidOid`, so is always read-safe,
+    // then writes it only during initialization, so we should not be racing anyone.
+    let db_oid = unsafe { MyDatabaseId };
+
+    let generated = unsafe { UserCrate::try_from_fn_oid(db_oid, fn_oid)? };
+    let (lib_rs, cargo_toml, _lints) = generated.generated_source()?;
+    Ok((lib_rs, cargo_toml))
+}
+
+/// Generate, but don't build, the crate for a function that doesn't exist, with the specified
+/// source code and signature.  Returns its `lib.rs` and `Cargo.toml`, as they'd be written to disk
+/// during compilation
+#[tracing::instrument(level = "debug", skip(source))]
+pub(crate) fn generated_crate_for_source(
+    source: &str,
+    argnames: Vec<String>,
+    argtypes: Vec<pg_sys::Oid>,
+    return_type: pg_sys::Oid,
+    return_set: bool,
+    is_strict: bool,
+) -> eyre::Result<(String, String)> {
+    if argnames.len() != argtypes.len() {
+        eyre::bail!(
+            "{} argument names were given for {} argument types",
+            argnames.len(),
+            argtypes.len()
+        );
+    }
+
+    let argnames = argnames
+        .iter()
+        .map(|name| {
+            syn::parse_str::<syn::Ident>(name).wrap_err(format!(
+                "`{name}` is an invalid Rust identifier and cannot be used as an argument name"
+            ))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let argmodes = vec![ProArgMode::In; argnames.len()];
+
+    // SAFETY: Postgres globally sets this to `const InvalidOid`, so is always read-safe,
+    // then writes it only during initialization, so we should not be racing anyone.
+    let db_oid = unsafe { MyDatabaseId };
+
+    // there's no function, so no Oid or generation number to speak of
+    let generated = UserCrate::try_from_parts(
+        0,
+        db_oid,
+        pg_sys::InvalidOid,
+        source,
+        // SAFETY:  we're in a transaction, so asking about our current user is fine
+        unsafe { pg_sys::GetUserId() },
+        argnames,
+        argtypes,
+        argmodes,
+        return_type,
+        return_set,
+        is_strict,
+    )?;
+    let (lib_rs, cargo_toml, _lints) = generated.generated_source()?;
+    Ok((lib_rs, cargo_toml))
+}
+
+/// Returns the Oids of every `LANGUAGE plrust` function in the current database
+pub(crate) fn plrust_function_oids() -> eyre::Result<Vec<pg_sys::Oid>> {
+    Ok(Spi::connect(|client| {
+        client
+            .select(
+                "SELECT p.oid FROM pg_catalog.pg_proc p
+                    INNER JOIN pg_catalog.pg_language l ON p.prolang = l.oid
+                 WHERE l.lanname = 'plrust'
+                 ORDER BY p.oid",
+                None,
+                None,
+            )?
+            .map(|row| row.get::<pg_sys::Oid>(1))
+            .filter_map(Result::transpose)
+            .collect::<spi::Result<Vec<_>>>()
+    })?)
+}
+
+/// Instead of compiling the function, determine if the compiled artifact already stored in
+/// `pg_catalog.pg_proc.prosrc` can be used as-is.  Returns `true` if it can, in which case there's
+/// nothing left to do.
+///
+/// The function's source is still fully processed, so it must pass the same dependency allow-list,
+/// argument, and return type checks as if it were being compiled.
+#[tracing::instrument(level = "debug")]
+pub(crate) fn reuse_compiled_function(fn_oid: pg_sys::Oid) -> eyre::Result<bool> {
+    // SAFETY: Postgres globally sets this to `const InvalidOid`, so is always read-safe,
+    // then writes it only during initialization, so we should not be racing anyone.
+    let db_oid = unsafe { MyDatabaseId };
+
+    // we don't need what is generated, just that it could be
+    let _generated = unsafe { UserCrate::try_from_fn_oid(db_oid, fn_oid)? };
+    prosrc::has_reusable_shared_library(fn_oid)
+}
+
 /// Represents the generated name PL/Rust gives to the user's function (at least the one to which
 /// we apply a `#[pg_extern]` annotation).  When the user function shared library is loaded, this
 /// is the only symbol we access from the library.
@@ -0,0 +1,192 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+//! Loading user functions before they're first called
+//!
+//! The first call to a user function in each backend pays to decode and `dlopen()` its shared
+//! library.  `plrust.preload_functions()` loads functions ahead of time, such as when a connection
+//! pool opens a new connection, and with no arguments it loads those `plrust.preload_functions`
+//! names.  They aren't loaded automatically, as the first chance we'd have to consult the catalog
+//! is the backend's first call to a PL/Rust function, which would then pay to load all of them.
+use pgrx::prelude::*;
+use pgrx::PgBuiltInOids;
+
+use crate::plrust;
+
+/// Load the functions named by `spec`, in the `plrust.preload_functions` format, into this
+/// backend.  Returns how many functions were loaded, not counting those already loaded.
+///
+/// With `check_execute`, it's an ERROR if the current user may not execute every function `spec`
+/// names, as someone who could call them would be loading them anyway.  The functions named by
+/// `plrust.preload_functions` are up to a superuser, and needn't be checked.
+#[tracing::instrument(level = "debug")]
+pub(crate) fn preload(spec: &str, check_execute: bool) -> eyre::Result<usize> {
+    check_spec(spec).map_err(|problem| eyre::eyre!(problem))?;
+
+    let mut loaded = 0;
+    for entry in split_entries(spec) {
+        let fn_oids = resolve(entry)?;
+        if fn_oids.is_empty() {
+            pgrx::warning!("`{entry}` doesn't name any PL/Rust functions to preload");
+        }
+        if check_execute {
+            fn_oids.iter().copied().for_each(check_execute_privilege);
+        }
+
+        for fn_oid in fn_oids {
+            // one function we can't load shouldn't keep us from loading the rest
+            match plrust::preload_function(fn_oid) {
+                Ok(true) => loaded += 1,
+                Ok(false) => {}
+                Err(e) => pgrx::warning!("could not preload `{entry}`: {e}"),
+            }
+        }
+    }
+    Ok(loaded)
+}
+
+/// Raise an ERROR unless the current user may execute the function `fn_oid`
+fn check_execute_privilege(fn_oid: pg_sys::Oid) {
+    // SAFETY:  we're in a transaction, and `fn_oid` came from the catalog
+    unsafe {
+        let user = pg_sys::GetUserId();
+        #[cfg(not(feature = "pg16"))]
+        let result = pg_sys::pg_proc_aclcheck(fn_oid, user, pg_sys::ACL_EXECUTE as _);
+        #[cfg(feature = "pg16")]
+        let result = pg_sys::object_aclcheck(
+            pg_sys::ProcedureRelationId,
+            fn_oid,
+            user,
+            pg_sys::ACL_EXECUTE as _,
+        );
+
+        if result != pg_sys::AclResult_ACLCHECK_OK {
+            pg_sys::aclcheck_error(
+                result,
+                pg_sys::ObjectType_OBJECT_FUNCTION,
+                pg_sys::get_func_name(fn_oid),
+            );
+        }
+    }
+}
+
+/// Split a comma-separated list of entries, ignoring the commas within a function signature's
+/// argument list
+fn split_entries(spec: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in spec.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                entries.push(&spec[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(&spec[start..]);
+
+    entries
+        .into_iter()
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// Check that every entry in `spec` is either `schema.*` or a function signature, as a malformed
+/// signature is a syntax error once it's looked up
+pub(crate) fn check_spec(spec: &str) -> Result<(), String> {
+    let mut depth = 0;
+    for c in spec.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(format!("`{spec}` has an unmatched `)`")),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(format!("`{spec}` has an unmatched `(`"));
+    }
+
+    for entry in split_entries(spec) {
+        let well_formed = match entry.strip_suffix(".*") {
+            Some(schema) => !schema.trim().is_empty() && !schema.contains(['(', ')']),
+            None => entry.split_once('(').map_or(false, |(name, args)| {
+                !name.trim().is_empty() && args.ends_with(')') && !args.contains('(')
+            }),
+        };
+        if !well_formed {
+            return Err(format!(
+                "`{entry}` is neither a function signature, such as `public.my_function(int)`, nor `schema.*`"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Find the PL/Rust functions an entry names:  either every function in a schema, written as
+/// `schema.*`, or a single function by its signature
+fn resolve(entry: &str) -> eyre::Result<Vec<pg_sys::Oid>> {
+    let (query, arg) = match entry.strip_suffix(".*") {
+        Some(schema) => (
+            "SELECT p.oid FROM pg_catalog.pg_proc p
+                INNER JOIN pg_catalog.pg_language l ON p.prolang = l.oid
+                INNER JOIN pg_catalog.pg_namespace n ON p.pronamespace = n.oid
+             WHERE l.lanname = 'plrust' AND n.nspname = $1
+             ORDER BY p.oid",
+            schema.trim().trim_matches('"'),
+        ),
+        None => (
+            "SELECT p.oid FROM pg_catalog.pg_proc p
+                INNER JOIN pg_catalog.pg_language l ON p.prolang = l.oid
+             WHERE l.lanname = 'plrust' AND p.oid = pg_catalog.to_regprocedure($1)",
+            entry,
+        ),
+    };
+
+    Ok(Spi::connect(|client| {
+        client
+            .select(
+                query,
+                None,
+                Some(vec![(PgBuiltInOids::TEXTOID.oid(), arg.into_datum())]),
+            )?
+            .map(|row| row.get::<pg_sys::Oid>(1))
+            .filter_map(Result::transpose)
+            .collect::<spi::Result<Vec<_>>>()
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries() {
+        assert_eq!(
+            split_entries("public.a(int, text), reporting.* ,b()"),
+            vec!["public.a(int, text)", "reporting.*", "b()"]
+        );
+        assert!(split_entries(" , ").is_empty());
+    }
+
+    #[test]
+    fn malformed_entries() {
+        assert!(check_spec("").is_ok());
+        assert!(check_spec("public.a(int, text), reporting.* ,b()").is_ok());
+        assert!(check_spec("foo(int").is_err());
+        assert!(check_spec("foo(int))").is_err());
+        assert!(check_spec("foo").is_err());
+        assert!(check_spec("(int)").is_err());
+        assert!(check_spec(".*").is_err());
+    }
+}
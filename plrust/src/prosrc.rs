@@ -24,10 +24,12 @@ use crate::error::PlRustError;
 use crate::gucs::get_trusted_pgrx_version;
 use crate::pgproc::PgProc;
 use crate::target;
-use crate::target::CompilationTarget;
+use crate::target::{CompilationTarget, CrossCompilationTarget};
 use crate::user_crate::capabilities::FunctionCapabilitySet;
-use crate::user_crate::lint::LintSet;
-use crate::user_crate::{FnReady, UserCrate};
+use crate::user_crate::crating::has_runtime_support;
+use crate::user_crate::lint::{required_lints, LintSet};
+use crate::user_crate::{FnReady, ResolvedDependency, UserCrate};
+use crate::{cache, gucs};
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 enum Encoding {
@@ -40,6 +42,10 @@ struct SharedLibrary {
     symbol: Option<String>,
     encoded: String,
     lints: LintSet,
+    /// The SHA-256 of the decoded library, as lowercase hex.  If there is `None`, the library was
+    /// compiled prior to this field
+    #[serde(default)]
+    sha256: Option<String>,
 }
 
 struct CompiledSharedLibrary {
@@ -62,18 +68,44 @@ impl SharedLibrary {
             symbol: Some(symbol),
             encoded: Self::CUSTOM_ENGINE.encode(compressed_bytes),
             lints,
+            sha256: Some(cache::sha256(&so_bytes)),
         })
     }
 
-    fn decode(&self) -> eyre::Result<Vec<u8>> {
+    /// The size, in bytes, of the shared library once decoded.  This comes from the trailer of the
+    /// gzip stream, which records the uncompressed size, so we needn't decompress the library
+    fn decoded_len(&self) -> eyre::Result<usize> {
         match self.encoding {
+            Encoding::GzBase64 => {
+                let b64_decoded = Self::CUSTOM_ENGINE.decode(&self.encoded)?;
+                let trailer = b64_decoded
+                    .len()
+                    .checked_sub(4)
+                    .map(|start| &b64_decoded[start..])
+                    .ok_or_else(|| eyre::eyre!("encoded shared library is truncated"))?;
+                Ok(u32::from_le_bytes(trailer.try_into()?) as usize)
+            }
+        }
+    }
+
+    fn decode(&self) -> eyre::Result<Vec<u8>> {
+        let bytes = match self.encoding {
             Encoding::GzBase64 => {
                 let mut bytes = Vec::new();
                 let b64_decoded = Self::CUSTOM_ENGINE.decode(&self.encoded)?;
                 GzDecoder::new(&b64_decoded[..]).read_to_end(&mut bytes)?;
-                Ok(bytes)
+                bytes
+            }
+        };
+
+        if let Some(sha256) = &self.sha256 {
+            if cache::sha256(&bytes) != *sha256 {
+                return Err(eyre::eyre!(
+                    "decoded shared library doesn't match its recorded SHA-256"
+                ));
             }
         }
+        Ok(bytes)
     }
 }
 
@@ -96,6 +128,16 @@ struct ProSrcEntry {
     /// and we'll just use an empty set of function capabilities for that
     #[serde(default = "FunctionCapabilitySet::empty")]
     capabilities: FunctionCapabilitySet,
+
+    /// The Postgres major version the artifacts in `lib` were compiled against.  If there is `None`,
+    /// the function was compiled prior to this field and we can't know
+    #[serde(default)]
+    pg_major_version: Option<u32>,
+
+    /// The crates, and their versions, the function's dependencies resolved to when it was compiled
+    /// for this host.  If there are `None`, the function was compiled prior to this field
+    #[serde(default)]
+    dependencies: Option<Vec<ResolvedDependency>>,
 }
 
 impl TryFrom<&PgProc> for ProSrcEntry {
@@ -137,6 +179,57 @@ impl ProSrcEntry {
     }
 }
 
+/// A description of a PL/Rust function's `pg_catalog.pg_proc.prosrc` entry, for introspection
+pub(crate) struct FunctionInfo {
+    pub(crate) src: String,
+    pub(crate) trusted_pgrx_version: String,
+    pub(crate) pg_major_version: Option<u32>,
+    pub(crate) capabilities: FunctionCapabilitySet,
+    pub(crate) libraries: Vec<SharedLibraryInfo>,
+    pub(crate) dependencies: Option<Vec<ResolvedDependency>>,
+}
+
+/// A description of one compiled shared library in a [`FunctionInfo`]
+pub(crate) struct SharedLibraryInfo {
+    pub(crate) target: CompilationTarget,
+    pub(crate) symbol: Option<String>,
+    /// size of the shared library itself, or why it couldn't be determined
+    pub(crate) binary_size: eyre::Result<usize>,
+    /// size of the shared library as encoded in `prosrc`
+    pub(crate) stored_size: usize,
+    pub(crate) lints: LintSet,
+}
+
+/// Describe what's stored in `pg_catalog.pg_proc.prosrc` for the specified `pg_proc_oid`, without
+/// decompressing any of its shared libraries
+#[tracing::instrument(level = "debug")]
+pub(crate) fn function_info(pg_proc_oid: pg_sys::Oid) -> eyre::Result<FunctionInfo> {
+    let pg_proc = PgProc::new(pg_proc_oid)?;
+    let entry = ProSrcEntry::try_from(&pg_proc)
+        .map_err(|_| PlRustError::FunctionNotCompiled(pg_proc_oid))?;
+
+    let libraries = entry
+        .lib
+        .into_iter()
+        .map(|(target, shared_library)| SharedLibraryInfo {
+            target,
+            binary_size: shared_library.decoded_len(),
+            stored_size: shared_library.encoded.len(),
+            symbol: shared_library.symbol,
+            lints: shared_library.lints,
+        })
+        .collect();
+
+    Ok(FunctionInfo {
+        src: entry.src,
+        trusted_pgrx_version: entry.trusted_pgrx_version,
+        pg_major_version: entry.pg_major_version,
+        capabilities: entry.capabilities,
+        libraries,
+        dependencies: entry.dependencies,
+    })
+}
+
 /// Given an arbitrary string, suss out how to treat it as source code.  If it's JSON that matches
 /// the structure of our [`ProSrcEntry`] struct, then we return its `src` property, throwing everything
 /// else away.
@@ -156,7 +249,8 @@ pub(crate) fn extract_source_and_capabilities_from_json(
 }
 
 /// Update the entry for the specified function in `pg_catalog.pg_proc.prosrc` to include the compiled
-/// `so_bytes` mapped to the specified `target_triple`
+/// `so_bytes` mapped to the specified `target_triple`, along with the `dependencies` it was built
+/// against, if they're given
 #[tracing::instrument(level = "debug")]
 pub(crate) fn create_or_replace_function(
     db_oid: pg_sys::Oid,
@@ -164,6 +258,7 @@ pub(crate) fn create_or_replace_function(
     target_triple: CompilationTarget,
     so_bytes: Vec<u8>,
     lints: LintSet,
+    dependencies: Option<&[ResolvedDependency]>,
 ) -> eyre::Result<()> {
     let pg_proc = PgProc::new(fn_oid)?;
     let mut entry = ProSrcEntry::try_from(&pg_proc).unwrap_or_else(|_| {
@@ -174,9 +269,19 @@ pub(crate) fn create_or_replace_function(
             lib: Default::default(),
             trusted_pgrx_version: get_trusted_pgrx_version(),
             capabilities: FunctionCapabilitySet::default(),
+            pg_major_version: None,
+            dependencies: None,
         }
     });
 
+    // the artifact we were given was just built with the current settings, so make sure the entry
+    // describes them
+    entry.trusted_pgrx_version = get_trusted_pgrx_version();
+    entry.pg_major_version = Some(pg_sys::PG_MAJORVERSION_NUM);
+    if let Some(dependencies) = dependencies {
+        entry.dependencies = Some(dependencies.to_vec());
+    }
+
     // always replace any existing bytes for the specified target_triple.  we only trust
     // what was given to us
     let symbol_name = crate::plrust::symbol_name(db_oid, fn_oid);
@@ -217,20 +322,173 @@ pub(crate) fn create_or_replace_function(
     Ok(())
 }
 
+/// Determine if the shared library already stored in `pg_catalog.pg_proc.prosrc` for the specified
+/// `pg_proc_oid` can be used as-is instead of compiling the function again.  This is typically
+/// the case after a `pg_restore`, where `prosrc` is our JSON structure rather than raw source code.
+///
+/// Nothing in `prosrc` can be verified:  anyone who can create a PL/Rust function can write any
+/// JSON they like, including a native library of their own and the lints it claims to have been
+/// compiled with.  So a stored library is only ever reused when the function's owner is a
+/// superuser, which is the case while a superuser restores a dump, as the function's ownership is
+/// only changed after it's created.
+///
+/// Beyond that, a stored library is only reusable if it was compiled for this host's target
+/// triple, against this Postgres major version, with the currently configured
+/// `plrust-trusted-pgrx` version, and with every lint in `plrust.required_lints`.  It must also
+/// decode cleanly -- the gzip stream carries a CRC32 of the uncompressed bytes, and the entry the
+/// library's SHA-256, so a corrupted artifact won't.
+#[tracing::instrument(level = "debug")]
+pub(crate) fn has_reusable_shared_library(pg_proc_oid: pg_sys::Oid) -> eyre::Result<bool> {
+    let pg_proc = PgProc::new(pg_proc_oid)?;
+
+    // SAFETY:  `superuser_arg()` only looks up the role in the syscache
+    if !unsafe { pg_sys::superuser_arg(pg_proc.proowner()) } {
+        tracing::debug!("function {pg_proc_oid} is not owned by a superuser");
+        return Ok(false);
+    }
+
+    let entry = match ProSrcEntry::try_from(&pg_proc) {
+        Ok(entry) => entry,
+        // it's just source code, so there's nothing to reuse
+        Err(_) => return Ok(false),
+    };
+
+    if entry.pg_major_version != Some(pg_sys::PG_MAJORVERSION_NUM) {
+        tracing::debug!("function {pg_proc_oid} was compiled for a different Postgres version");
+        return Ok(false);
+    }
+
+    if entry.trusted_pgrx_version != get_trusted_pgrx_version() {
+        tracing::debug!(
+            "function {pg_proc_oid} was compiled with a different `plrust-trusted-pgrx` version"
+        );
+        return Ok(false);
+    }
+
+    let this_target = target::tuple()?;
+    let shared_library = match entry.lib.get(this_target) {
+        Some(shared_library) => shared_library,
+        None => {
+            tracing::debug!("function {pg_proc_oid} was not compiled for `{this_target}`");
+            return Ok(false);
+        }
+    };
+
+    // libraries from before we stored the symbol name rely on it being derived from the function's
+    // Oid, which is unlikely to be the same after a restore
+    if shared_library.symbol.is_none() {
+        return Ok(false);
+    }
+
+    if !required_lints().is_subset(&shared_library.lints) {
+        tracing::debug!("function {pg_proc_oid} was not compiled with all required lints");
+        return Ok(false);
+    }
+
+    match shared_library.decode() {
+        Ok(bytes) if !bytes.is_empty() => Ok(true),
+        Ok(_) => Ok(false),
+        Err(e) => {
+            tracing::warn!("stored library for function {pg_proc_oid} failed to decode: {e}");
+            Ok(false)
+        }
+    }
+}
+
+/// Of the specified cross compilation `targets`, return those which the function identified by
+/// `pg_proc_oid` does not yet have a compiled shared library for.
+///
+/// It's an error if the function's existing libraries were compiled with a different
+/// `plrust-trusted-pgrx` version than is currently configured, as the function would end up with
+/// libraries compiled from different versions of it.
+#[tracing::instrument(level = "debug", skip(targets))]
+pub(crate) fn missing_compilation_targets(
+    pg_proc_oid: pg_sys::Oid,
+    targets: impl IntoIterator<Item = CrossCompilationTarget>,
+) -> eyre::Result<Vec<CrossCompilationTarget>> {
+    let pg_proc = PgProc::new(pg_proc_oid)?;
+    let entry = ProSrcEntry::try_from(&pg_proc)?;
+
+    let trusted_pgrx_version = get_trusted_pgrx_version();
+    if entry.trusted_pgrx_version != trusted_pgrx_version {
+        return Err(PlRustError::TrustedPgrxVersionMismatch(
+            entry.trusted_pgrx_version,
+            trusted_pgrx_version,
+        ))?;
+    }
+
+    Ok(targets
+        .into_iter()
+        .filter(|target| !entry.lib.contains_key(&target.target()))
+        .collect())
+}
+
 /// Dynamically load the shared library stored in `pg_catalog.pg_proc.prosrc` for the specified `pg_proc_oid`
 /// procedure object id and the `target_triple` of the host.
 #[tracing::instrument(level = "debug")]
 pub(crate) fn load(pg_proc_oid: pg_sys::Oid) -> eyre::Result<Rc<UserCrate<FnReady>>> {
     tracing::debug!("loading function oid `{pg_proc_oid}`");
     let pg_proc = PgProc::new(pg_proc_oid)?;
-    let mut entry = ProSrcEntry::try_from(&pg_proc)?;
     let this_target = target::tuple()?;
-    let so = entry.decode_shared_library(this_target)?;
 
     // SAFETY: Postgres globally sets this to `const InvalidOid`, so is always read-safe,
     // then writes it only during initialization, so we should not be racing anyone.
     let db_oid = unsafe { MyDatabaseId };
 
+    let mut entry = ProSrcEntry::try_from(&pg_proc)?;
+    let runtime_support = has_runtime_support(&entry.trusted_pgrx_version);
+
+    // only a library whose SHA-256 we know can be checked against what's in the cache
+    let use_cache = gucs::shared_library_cache();
+    let sha256 = entry
+        .lib
+        .get(this_target)
+        .and_then(|shared_library| shared_library.sha256.clone())
+        .filter(|_| use_cache);
+
+    if let Some(sha256) = &sha256 {
+        if let Some(library) = cache::lookup(
+            db_oid,
+            pg_proc_oid,
+            pg_proc.generation_number(),
+            this_target,
+            sha256,
+        ) {
+            let shared_library = &entry.lib[this_target];
+            let validated = UserCrate::cached(
+                pg_proc.generation_number(),
+                db_oid,
+                pg_proc_oid,
+                library,
+                shared_library.symbol.clone(),
+                shared_library.lints.clone(),
+            )?;
+            match unsafe { validated.load(runtime_support) } {
+                Ok(loaded) => return Ok(Rc::new(loaded)),
+                // the cached library could have been removed out from under us by a backend caching
+                // a different generation of this function, so we'll just load it the long way
+                Err(e) => tracing::warn!(
+                    "failed to load function {pg_proc_oid} from the shared library cache: {e}"
+                ),
+            }
+        }
+    }
+
+    let so = entry.decode_shared_library(this_target)?;
+
+    if sha256.is_some() {
+        if let Err(e) = cache::store(
+            db_oid,
+            pg_proc_oid,
+            pg_proc.generation_number(),
+            this_target,
+            &so.bytes,
+        ) {
+            // the cache is only an optimization
+            tracing::warn!("failed to add function {pg_proc_oid} to the shared library cache: {e}");
+        }
+    }
+
     // fabricate a FnLoad version of the UserCrate so that we can "load()" it -- tho we're
     // long since past the idea of crates, but whatev, I just work here
     let built = UserCrate::built(
@@ -243,8 +501,34 @@ pub(crate) fn load(pg_proc_oid: pg_sys::Oid) -> eyre::Result<Rc<UserCrate<FnRead
         so.metadata.lints,
     );
     let validated = unsafe { built.validate()? };
-    let loaded = unsafe { validated.load()? };
+    let loaded = unsafe { validated.load(runtime_support)? };
 
     // all good
     Ok(Rc::new(loaded))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_crate::lint::Lint;
+
+    #[test]
+    fn shared_library_sha256() -> eyre::Result<()> {
+        let so_bytes = b"\x7fELF not really a shared library".to_vec();
+        let mut shared_library = SharedLibrary::new(
+            "plrust_fn_1".to_string(),
+            so_bytes.clone(),
+            LintSet::from_iter(std::iter::empty::<Lint>()),
+        )?;
+        assert_eq!(so_bytes, shared_library.decode()?);
+
+        // a library that isn't the one its entry says it is
+        shared_library.sha256 = Some(cache::sha256(b"something else entirely"));
+        assert!(shared_library.decode().is_err());
+
+        // libraries compiled before we recorded their SHA-256 can't be checked
+        shared_library.sha256 = None;
+        assert_eq!(so_bytes, shared_library.decode()?);
+        Ok(())
+    }
+}
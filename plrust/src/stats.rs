@@ -0,0 +1,255 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+//! Per-function execution statistics, shared by every backend
+//!
+//! When `plrust.track_functions` is on, each call to a user function is counted and timed, as is
+//! every time a backend loads a function's shared library and every time a function is compiled.
+//! The statistics live in shared memory, so they're aggregated across backends, and are exposed
+//! by the `plrust.stat_functions` view.
+//!
+//! So that calls don't contend for the shared statistics, or need a lock while unwinding from an
+//! ERROR, each backend collects its own until the end of the transaction, then adds them to the
+//! shared statistics all at once.  A backend's statistics from its current transaction are also
+//! added before it reads or resets the shared statistics, so it always sees its own calls.
+//!
+//! Shared memory is sized when the cluster starts, so we only track up to [`MAX_TRACKED_FUNCTIONS`]
+//! functions.  Once that many are tracked, calls to other functions go uncounted until the
+//! statistics are reset, and each backend that can't count one warns, once, that it can't.  A
+//! function's statistics are discarded when a backend that loaded it sees it was dropped.
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::time::{Duration, Instant};
+
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::{
+    pg_guard, pg_shmem_init, pg_sys, PGRXSharedMemory, PgLogLevel, PgLwLock, PgSqlErrorCode,
+};
+
+use crate::gucs;
+
+/// The maximum number of functions, across all databases, we'll keep statistics for.  Must be a
+/// power of two
+const MAX_TRACKED_FUNCTIONS: usize = 4096;
+
+static STAT_FUNCTIONS: PgLwLock<
+    heapless::FnvIndexMap<FunctionKey, FunctionStats, MAX_TRACKED_FUNCTIONS>,
+> = PgLwLock::new();
+
+thread_local! {
+    /// Statistics this backend has collected, by function, since it last added them to
+    /// [`STAT_FUNCTIONS`]
+    static PENDING: RefCell<HashMap<pg_sys::Oid, FunctionStats>> = RefCell::new(HashMap::new());
+
+    /// Has this backend warned that [`STAT_FUNCTIONS`] is full?
+    static WARNED_FULL: Cell<bool> = Cell::new(false);
+}
+
+/// Function Oids are only unique within a database
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct FunctionKey {
+    pub(crate) db_oid: pg_sys::Oid,
+    pub(crate) fn_oid: pg_sys::Oid,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct FunctionStats {
+    pub(crate) calls: u64,
+    pub(crate) errors: u64,
+    pub(crate) total_time: Duration,
+    pub(crate) max_time: Duration,
+    pub(crate) loads: u64,
+    pub(crate) load_time: Duration,
+    pub(crate) compiles: u64,
+    pub(crate) compile_time: Duration,
+}
+
+// SAFETY:  `FunctionStats` is plain old data, with no pointers to backend-local memory
+unsafe impl PGRXSharedMemory for FunctionStats {}
+
+/// Request our shared memory, and have every backend add the statistics it collects at the end of
+/// each transaction.  Must be called from `_PG_init()`, while shared preload libraries are being
+/// loaded
+pub(crate) fn init() {
+    pg_shmem_init!(STAT_FUNCTIONS);
+
+    // SAFETY:  Postgres copies the callback into its own list, which backends inherit
+    unsafe {
+        pg_sys::RegisterXactCallback(Some(flush_at_end_of_transaction), std::ptr::null_mut());
+    }
+}
+
+#[pg_guard]
+unsafe extern "C" fn flush_at_end_of_transaction(event: pg_sys::XactEvent, _arg: *mut c_void) {
+    match event {
+        pg_sys::XactEvent_XACT_EVENT_COMMIT
+        | pg_sys::XactEvent_XACT_EVENT_PARALLEL_COMMIT
+        | pg_sys::XactEvent_XACT_EVENT_ABORT
+        | pg_sys::XactEvent_XACT_EVENT_PARALLEL_ABORT
+        | pg_sys::XactEvent_XACT_EVENT_PREPARE => flush(),
+        _ => {}
+    }
+}
+
+/// Add the statistics this backend has collected to [`STAT_FUNCTIONS`]
+fn flush() {
+    let pending = PENDING.with(|pending| std::mem::take(&mut *pending.borrow_mut()));
+    if pending.is_empty() {
+        return;
+    }
+
+    let mut untracked = false;
+    {
+        let mut stats = STAT_FUNCTIONS.exclusive();
+        for (fn_oid, collected) in pending {
+            let key = current_key(fn_oid);
+            match stats.get_mut(&key) {
+                Some(function_stats) => function_stats.add(&collected),
+                None => {
+                    // if we're already tracking as many functions as we can, this one goes uncounted
+                    untracked |= stats.insert(key, collected).is_err();
+                }
+            }
+        }
+    }
+
+    // only once we've released the lock, and only once per backend, lest every transaction warn
+    if untracked && !WARNED_FULL.with(|warned| warned.replace(true)) {
+        ErrorReport::new(
+            PgSqlErrorCode::ERRCODE_PROGRAM_LIMIT_EXCEEDED,
+            format!("PL/Rust is already keeping statistics for {MAX_TRACKED_FUNCTIONS} functions, so calls to others go uncounted"),
+            "stats::flush",
+        )
+        .set_hint("Reset the statistics of functions that don't need them with `plrust.stat_functions_reset()`")
+        .report(PgLogLevel::WARNING);
+    }
+}
+
+fn current_key(fn_oid: pg_sys::Oid) -> FunctionKey {
+    // SAFETY: Postgres globally sets this to `const InvalidOid`, so is always read-safe,
+    // then writes it only during initialization, so we should not be racing anyone.
+    let db_oid = unsafe { pg_sys::MyDatabaseId };
+    FunctionKey { db_oid, fn_oid }
+}
+
+impl FunctionStats {
+    fn add(&mut self, other: &FunctionStats) {
+        self.calls += other.calls;
+        self.errors += other.errors;
+        self.total_time += other.total_time;
+        self.max_time = self.max_time.max(other.max_time);
+        self.loads += other.loads;
+        self.load_time += other.load_time;
+        self.compiles += other.compiles;
+        self.compile_time += other.compile_time;
+    }
+}
+
+/// Collect statistics for the function in this backend, until the end of the transaction
+fn record(fn_oid: pg_sys::Oid, update: impl FnOnce(&mut FunctionStats)) {
+    PENDING.with(|pending| update(pending.borrow_mut().entry(fn_oid).or_default()));
+}
+
+/// Times a single call to a user function, recording it when dropped.  If we're unwinding, be it
+/// from a Rust panic or a Postgres ERROR, the call is counted as an error too.  Only started when
+/// `plrust.track_functions` is on
+pub(crate) struct CallTimer {
+    fn_oid: pg_sys::Oid,
+    start: Instant,
+}
+
+impl CallTimer {
+    pub(crate) fn start(fn_oid: pg_sys::Oid) -> Self {
+        Self {
+            fn_oid,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for CallTimer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        let errored = std::thread::panicking();
+        record(self.fn_oid, |stats| {
+            stats.calls += 1;
+            stats.errors += errored as u64;
+            stats.total_time += elapsed;
+            stats.max_time = stats.max_time.max(elapsed);
+        });
+    }
+}
+
+/// Record that loading the function's shared library took `elapsed`
+pub(crate) fn record_load(fn_oid: pg_sys::Oid, elapsed: Duration) {
+    if gucs::track_functions() {
+        record(fn_oid, |stats| {
+            stats.loads += 1;
+            stats.load_time += elapsed;
+        });
+    }
+}
+
+/// Record that compiling the function took `elapsed`
+pub(crate) fn record_compile(fn_oid: pg_sys::Oid, elapsed: Duration) {
+    if gucs::track_functions() {
+        record(fn_oid, |stats| {
+            stats.compiles += 1;
+            stats.compile_time += elapsed;
+        });
+    }
+}
+
+/// The statistics for every tracked function in the current database
+pub(crate) fn current_database_stats() -> Vec<(pg_sys::Oid, FunctionStats)> {
+    flush();
+
+    // SAFETY: Postgres globally sets this to `const InvalidOid`, so is always read-safe,
+    // then writes it only during initialization, so we should not be racing anyone.
+    let db_oid = unsafe { pg_sys::MyDatabaseId };
+    STAT_FUNCTIONS
+        .share()
+        .iter()
+        .filter(|(key, _)| key.db_oid == db_oid)
+        .map(|(key, stats)| (key.fn_oid, *stats))
+        .collect()
+}
+
+/// Discard the statistics for a function in the current database that was dropped, so its slot
+/// can be used by another.  Should the transaction that dropped it roll back, it starts over
+pub(crate) fn forget_dropped(fn_oid: pg_sys::Oid) {
+    PENDING.with(|pending| pending.borrow_mut().remove(&fn_oid));
+    STAT_FUNCTIONS.exclusive().remove(&current_key(fn_oid));
+}
+
+/// Discard the statistics for the specified function, or for every function when `fn_oid` is
+/// `None`.  Only the current database's statistics are affected
+pub(crate) fn reset(fn_oid: Option<pg_sys::Oid>) {
+    flush();
+
+    let mut stats = STAT_FUNCTIONS.exclusive();
+    match fn_oid {
+        Some(fn_oid) => {
+            stats.remove(&current_key(fn_oid));
+        }
+        None => {
+            // SAFETY: Postgres globally sets this to `const InvalidOid`, so is always read-safe,
+            // then writes it only during initialization, so we should not be racing anyone.
+            let db_oid = unsafe { pg_sys::MyDatabaseId };
+            let keys = stats
+                .keys()
+                .filter(|key| key.db_oid == db_oid)
+                .copied()
+                .collect::<Vec<_>>();
+            for key in keys {
+                stats.remove(&key);
+            }
+        }
+    }
+}
@@ -12,6 +12,7 @@ Use of this source code is governed by the PostgreSQL license that can be found
 mod tests {
     use pgrx::prelude::*;
     use pgrx::spi;
+    use pgrx::PgBuiltInOids;
 
     #[pg_test]
     fn test_allowed_dependencies() -> spi::Result<()> {
@@ -80,6 +81,156 @@ mod tests {
             Ok(())
         })
     }
+
+    #[pg_test]
+    fn test_validate_allowlist() -> spi::Result<()> {
+        // every entry of the allow-list file is valid, and `rand` has two
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT string_agg(format('%s %s', name, version_req), '; ' ORDER BY name, version_req)
+                   FROM plrust.validate_allowlist()
+                  WHERE valid AND error IS NULL"
+            )?,
+            Some(
+                "owo-colors =3.5.0; plutonium *; rand =0.8.3; rand >0.8.4, <0.8.6; syn =2.0.28; tokio =1.19.2"
+                    .to_string()
+            )
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[should_panic = "`plrust.local_crate_source` is not set"]
+    fn test_check_local_crate_source_not_set() -> spi::Result<()> {
+        Spi::run("SELECT * FROM plrust.check_local_crate_source()")
+    }
+
+    #[pg_test]
+    fn test_dependency_allowlist_table() -> spi::Result<()> {
+        Spi::run("SELECT plrust.allow_dependency('rand', '>=0.8, <0.9')")?;
+        Spi::run("SELECT plrust.allow_dependency('rand', '=0.8.5', '{small_rng}', false)")?;
+        // allowing the same version again replaces its features
+        Spi::run("SELECT plrust.allow_dependency('rand', '=0.8.5', '{std}', false)")?;
+        Spi::run("SELECT plrust.allow_dependency('bitvec', '*')")?;
+
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT string_agg(format('%s %s %s %s', name, version_req, features, default_features), '; ' ORDER BY name, version_req)
+                   FROM plrust.dependency_allowlist"
+            )?,
+            Some("bitvec * {} t; rand =0.8.5 {std} f; rand >=0.8, <0.9 {} t".to_string())
+        );
+
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT plrust.revoke_dependency('rand', '=0.8.5')")?,
+            Some(1)
+        );
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT plrust.revoke_dependency('rand')")?,
+            Some(1)
+        );
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM plrust.dependency_allowlist")?,
+            Some(1)
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    fn test_dependency_allowlist_table_roles() {
+        fn wrapped() -> eyre::Result<()> {
+            Spi::run("CREATE ROLE analytics")?;
+            Spi::run("CREATE ROLE analyst IN ROLE analytics")?;
+            Spi::run("CREATE ROLE application")?;
+            Spi::run("SELECT plrust.allow_dependency('serde_json', '*')")?;
+            Spi::run("SELECT plrust.allow_dependency('statrs', '=0.16.0', role => 'analytics')")?;
+            Spi::run("SELECT plrust.allow_dependency('ndarray', '=0.15.6', role => 'analytics')")?;
+
+            let allowed = |role: &str| -> eyre::Result<Vec<String>> {
+                let role = Spi::get_one_with_args::<pg_sys::Oid>(
+                    "SELECT $1::regrole::oid",
+                    vec![(PgBuiltInOids::TEXTOID.oid(), role.into_datum())],
+                )?
+                .unwrap();
+                Ok(crate::allow_list::load_allowlist_table(role)?
+                    .into_keys()
+                    .collect())
+            };
+            assert_eq!(allowed("analyst")?, vec!["ndarray", "serde_json", "statrs"]);
+            assert_eq!(allowed("application")?, vec!["serde_json"]);
+
+            assert_eq!(
+                Spi::get_one::<i64>(
+                    "SELECT plrust.revoke_dependency('statrs', role => 'analytics')"
+                )?,
+                Some(1)
+            );
+            assert_eq!(allowed("analyst")?, vec!["ndarray", "serde_json"]);
+
+            // entries follow their role when it's renamed...
+            Spi::run("ALTER ROLE analytics RENAME TO data_science")?;
+            assert_eq!(allowed("analyst")?, vec!["ndarray", "serde_json"]);
+
+            // ...but don't outlive it
+            Spi::run("SELECT plrust.allow_dependency('rand', '*', role => 'application')")?;
+            assert_eq!(allowed("application")?, vec!["rand", "serde_json"]);
+            Spi::run("DROP ROLE application")?;
+            Spi::run("CREATE ROLE application")?;
+            assert_eq!(allowed("application")?, vec!["serde_json"]);
+            Ok(())
+        }
+        wrapped().unwrap()
+    }
+
+    #[pg_test]
+    #[should_panic = "role `nobody_at_all` does not exist"]
+    fn test_dependency_allowlist_table_unknown_role() -> spi::Result<()> {
+        Spi::run("SELECT plrust.allow_dependency('rand', '*', role => 'nobody_at_all')")
+    }
+
+    #[pg_test]
+    #[should_panic = "`0.8.5` is not a supported version requirement"]
+    fn test_dependency_allowlist_table_bad_version() -> spi::Result<()> {
+        Spi::run("SELECT plrust.allow_dependency('rand', '0.8.5')")
+    }
+
+    #[pg_test]
+    fn test_libraries() -> spi::Result<()> {
+        Spi::run(
+            "SELECT plrust.register_library('helpers', 'pub fn double(x: i32) -> i32 { x * 2 }')",
+        )?;
+        Spi::run(
+            "SELECT plrust.register_library('helpers', 'pub fn triple(x: i32) -> i32 { x * 3 }', '1.0.0')",
+        )?;
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT format('%s %s', version, source) FROM plrust.libraries WHERE name = 'helpers'"
+            )?,
+            Some("1.0.0 pub fn triple(x: i32) -> i32 { x * 3 }".to_string())
+        );
+
+        assert_eq!(
+            Spi::get_one::<bool>("SELECT plrust.unregister_library('helpers')")?,
+            Some(true)
+        );
+        assert_eq!(
+            Spi::get_one::<bool>("SELECT plrust.unregister_library('helpers')")?,
+            Some(false)
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    #[should_panic = "`../helpers` is not a valid crate name"]
+    fn test_libraries_bad_name() -> spi::Result<()> {
+        Spi::run("SELECT plrust.register_library('../helpers', 'pub fn f() {}')")
+    }
+
+    #[pg_test]
+    #[should_panic = "Parsing library source"]
+    fn test_libraries_bad_source() -> spi::Result<()> {
+        Spi::run("SELECT plrust.register_library('helpers', 'pub fn f( {}')")
+    }
 }
 
 #[cfg(any(test, feature = "pg_test"))]
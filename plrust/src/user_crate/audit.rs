@@ -0,0 +1,436 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+/*!
+Resolve and audit the crates a user function's dependencies pull in
+
+We resolve the provisioned crate's dependency graph with `cargo metadata`, so we can record which
+crates, and which versions of them, the function was built against.
+
+The allow-list only governs the dependencies a user function declares, yet those can depend on crates
+the administrator never reviewed, including ones with build scripts or procedural macros, which run
+arbitrary code when the function is compiled.  When any of `plrust.denied_crates`,
+`plrust.allowed_transitive_crates`, or `plrust.allowed_build_scripts` is set, we also check every
+crate in the graph.
+
+Crates named in `plrust.strict_dependencies` are then held to PL/Rust's lints, too.  See
+[`crate::user_crate::strict`].
+
+`plrust-trusted-pgrx`, and everything it depends on, is part of PL/Rust itself, so those crates are
+neither recorded nor audited when they're only pulled in by a user's dependency.  A crate the user
+declares directly always is, even if `plrust-trusted-pgrx` depends on it too.
+*/
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::Path;
+
+use color_eyre::{Section, SectionExt};
+use eyre::{eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::gucs;
+use crate::user_crate::cargo::cargo;
+use crate::user_crate::strict::{check_strict_dependencies, StrictCrate};
+use crate::PlRustError;
+
+/// What the administrator has said about the crates user functions may pull in.  `None` means
+/// there's no restriction
+#[derive(Debug, Default)]
+struct Policy {
+    denied: Vec<String>,
+    allowed_transitive: Option<Vec<String>>,
+    allowed_build_scripts: Option<Vec<String>>,
+}
+
+impl Policy {
+    fn configured() -> Option<Self> {
+        let policy = Self {
+            denied: gucs::denied_crates().unwrap_or_default(),
+            allowed_transitive: gucs::allowed_transitive_crates(),
+            allowed_build_scripts: gucs::allowed_build_scripts(),
+        };
+        (!policy.denied.is_empty()
+            || policy.allowed_transitive.is_some()
+            || policy.allowed_build_scripts.is_some())
+        .then_some(policy)
+    }
+}
+
+/// A crate, and the version of it, that a user function was built against
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) struct ResolvedDependency {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    /// whether the function declared this dependency itself, rather than it being a dependency
+    /// of one of those
+    pub(crate) direct: bool,
+}
+
+/// The parts of `cargo metadata --format-version 1` we need
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    resolve: Resolve,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    id: String,
+    name: String,
+    version: String,
+    /// where the package comes from, which is `None` for path dependencies
+    source: Option<String>,
+    targets: Vec<Target>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Target {
+    kind: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Resolve {
+    root: Option<String>,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Node {
+    id: String,
+    dependencies: Vec<String>,
+    /// the features enabled for the package
+    #[serde(default)]
+    features: Vec<String>,
+}
+
+impl Package {
+    fn has_target(&self, kind: &str) -> bool {
+        self.targets
+            .iter()
+            .any(|target| target.kind.iter().any(|k| k == kind))
+    }
+
+    /// Whether the package has a library crate, as opposed to only binaries
+    fn has_lib(&self) -> bool {
+        ["lib", "rlib", "dylib", "proc-macro"]
+            .iter()
+            .any(|kind| self.has_target(kind))
+    }
+}
+
+/// Resolve the dependency graph of the crate in `crate_dir`, checking it against the configured
+/// policy, if there is one.  Returns the crates the user's dependencies resolved to
+#[tracing::instrument(level = "debug", skip_all, fields(crate_dir = %crate_dir.display()))]
+pub(crate) fn audit_dependencies(
+    crate_dir: &Path,
+    cargo_target_dir: &Path,
+) -> eyre::Result<Vec<ResolvedDependency>> {
+    let mut command = cargo(cargo_target_dir, None)?;
+    command.current_dir(crate_dir);
+    command.arg("metadata");
+    command.arg("--format-version=1");
+    command.arg("--filter-platform");
+    command.arg(crate::target::tuple()?.as_str());
+
+    let output = command
+        .output()
+        .wrap_err("`cargo metadata` execution failure")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        return Err(eyre!(PlRustError::CargoMetadataFail)
+            .section(stderr.header("`cargo metadata` stderr:")));
+    }
+
+    let metadata = serde_json::from_slice::<Metadata>(&output.stdout)
+        .wrap_err("Parsing `cargo metadata` output")?;
+    let graph = UserGraph::new(&metadata);
+
+    if let Some(policy) = Policy::configured() {
+        let violations = graph.violations(&policy);
+        if !violations.is_empty() {
+            return Err(eyre!(PlRustError::DependencyPolicyViolation)
+                .section(violations.join("\n").header("Violations:")));
+        }
+    }
+
+    check_strict_dependencies(graph.strict_crates())?;
+
+    Ok(graph.resolved())
+}
+
+/// Crate names are the same whether they're spelled with dashes or underscores
+fn same_crate(a: &str, b: &str) -> bool {
+    a.replace('_', "-") == b.replace('_', "-")
+}
+
+/// The part of a resolved dependency graph that comes from the user's own dependencies
+struct UserGraph<'a> {
+    packages: BTreeMap<&'a str, &'a Package>,
+    features: BTreeMap<&'a str, &'a [String]>,
+    root: &'a str,
+    /// the user's direct dependencies
+    direct: Vec<&'a str>,
+    /// every package the user's dependencies pull in, each with a package that depends on it
+    audited: BTreeMap<&'a str, &'a str>,
+}
+
+impl<'a> UserGraph<'a> {
+    fn new(metadata: &'a Metadata) -> Self {
+        let packages = metadata
+            .packages
+            .iter()
+            .map(|package| (package.id.as_str(), package))
+            .collect::<BTreeMap<_, _>>();
+        let dependencies = metadata
+            .resolve
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node.dependencies.as_slice()))
+            .collect::<BTreeMap<_, _>>();
+        let features = metadata
+            .resolve
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node.features.as_slice()))
+            .collect::<BTreeMap<_, _>>();
+        let Some(root) = metadata.resolve.root.as_deref() else {
+            return Self {
+                packages,
+                features,
+                root: "",
+                direct: Vec::new(),
+                audited: BTreeMap::new(),
+            };
+        };
+        let direct = dependencies.get(root).copied().unwrap_or_default();
+
+        // PL/Rust's own dependencies
+        let (trusted_pgrx, user_direct): (Vec<&String>, Vec<&String>) =
+            direct.iter().partition(|id| {
+                packages
+                    .get(id.as_str())
+                    .map_or(false, |p| p.name == "plrust-trusted-pgrx")
+            });
+        let exempt = reachable(
+            &dependencies,
+            trusted_pgrx.iter().map(|id| (id.as_str(), root)),
+        )
+        .into_keys()
+        .collect::<BTreeSet<_>>();
+
+        let mut audited = reachable(
+            &dependencies,
+            user_direct.iter().map(|id| (id.as_str(), root)),
+        );
+        audited.retain(|id, _| {
+            !exempt.contains(id) || user_direct.iter().any(|direct| direct.as_str() == *id)
+        });
+
+        Self {
+            packages,
+            features,
+            root,
+            direct: user_direct.into_iter().map(|id| id.as_str()).collect(),
+            audited,
+        }
+    }
+
+    fn resolved(&self) -> Vec<ResolvedDependency> {
+        let mut resolved = self
+            .audited
+            .keys()
+            .filter_map(|id| self.packages.get(id))
+            .map(|package| ResolvedDependency {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                direct: self.direct.contains(&package.id.as_str()),
+            })
+            .collect::<Vec<_>>();
+        resolved.sort();
+        resolved
+    }
+
+    /// The crates `plrust.strict_dependencies` could apply to.  Path dependencies, like PL/Rust
+    /// libraries, are already compiled with PL/Rust's lints
+    fn strict_crates(&self) -> Vec<StrictCrate> {
+        self.audited
+            .keys()
+            .filter_map(|id| self.packages.get(id))
+            .filter(|package| package.source.is_some())
+            .filter(|package| package.has_lib())
+            .map(|package| StrictCrate {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                features: self
+                    .features
+                    .get(package.id.as_str())
+                    .copied()
+                    .unwrap_or_default()
+                    .iter()
+                    .cloned()
+                    .collect(),
+            })
+            .collect()
+    }
+
+    fn violations(&self, policy: &Policy) -> Vec<String> {
+        let mut violations = Vec::new();
+        for (&id, &required_by) in &self.audited {
+            let Some(package) = self.packages.get(id) else {
+                continue;
+            };
+            let describe = |package: &Package| format!("`{} {}`", package.name, package.version);
+            let crate_ = match self.packages.get(required_by) {
+                Some(parent) if required_by != self.root => {
+                    format!("{}, required by {},", describe(package), describe(parent))
+                }
+                _ => describe(package),
+            };
+
+            if policy
+                .denied
+                .iter()
+                .any(|name| same_crate(name, &package.name))
+            {
+                violations.push(format!("{crate_} is denied by `plrust.denied_crates`"));
+            }
+
+            if let Some(allowed) = &policy.allowed_transitive {
+                let is_direct = self.direct.contains(&id);
+                if !is_direct && !allowed.iter().any(|name| same_crate(name, &package.name)) {
+                    violations.push(format!(
+                        "{crate_} is not in `plrust.allowed_transitive_crates`"
+                    ));
+                }
+            }
+
+            if let Some(allowed) = &policy.allowed_build_scripts {
+                if !allowed.iter().any(|name| same_crate(name, &package.name)) {
+                    if package.has_target("custom-build") {
+                        violations.push(format!(
+                            "{crate_} has a build script, and isn't in `plrust.allowed_build_scripts`"
+                        ));
+                    }
+                    if package.has_target("proc-macro") {
+                        violations.push(format!(
+                            "{crate_} is a procedural macro, and isn't in `plrust.allowed_build_scripts`"
+                        ));
+                    }
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// Every package reachable from `start`, each with a package that depends on it, so a violation
+/// can say how it came to be included
+fn reachable<'a>(
+    dependencies: &BTreeMap<&'a str, &'a [String]>,
+    start: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> BTreeMap<&'a str, &'a str> {
+    let mut found = BTreeMap::new();
+    let mut queue = start.into_iter().collect::<VecDeque<_>>();
+    while let Some((id, required_by)) = queue.pop_front() {
+        if found.contains_key(id) {
+            continue;
+        }
+        found.insert(id, required_by);
+        for dependency in dependencies.get(id).copied().unwrap_or_default() {
+            queue.push_back((dependency.as_str(), id));
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = r#"{
+        "packages": [
+            { "id": "root", "name": "plrust_fn_oid_1_1", "version": "0.0.0", "targets": [ { "kind": [ "cdylib" ] } ] },
+            { "id": "tpgrx", "name": "plrust-trusted-pgrx", "version": "1.2.7", "targets": [ { "kind": [ "lib" ] } ] },
+            { "id": "sys", "name": "pgrx-pg-sys", "version": "0.11.0", "targets": [ { "kind": [ "lib" ] }, { "kind": [ "custom-build" ] } ] },
+            { "id": "serde", "name": "serde", "version": "1.0.192", "targets": [ { "kind": [ "lib" ] } ] },
+            { "id": "rand", "name": "rand", "version": "0.8.5", "targets": [ { "kind": [ "lib" ] } ] },
+            { "id": "getrandom", "name": "getrandom", "version": "0.2.11", "targets": [ { "kind": [ "lib" ] }, { "kind": [ "custom-build" ] } ] },
+            { "id": "derive", "name": "sneaky_derive", "version": "0.1.0", "targets": [ { "kind": [ "proc-macro" ] } ] }
+        ],
+        "resolve": {
+            "root": "root",
+            "nodes": [
+                { "id": "root", "dependencies": [ "tpgrx", "rand" ] },
+                { "id": "tpgrx", "dependencies": [ "sys", "serde" ] },
+                { "id": "sys", "dependencies": [] },
+                { "id": "serde", "dependencies": [] },
+                { "id": "rand", "dependencies": [ "getrandom", "serde", "derive" ] },
+                { "id": "getrandom", "dependencies": [] },
+                { "id": "derive", "dependencies": [] }
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn policy_violations() {
+        let metadata: Metadata = serde_json::from_str(METADATA).unwrap();
+
+        let policy = Policy {
+            denied: vec!["sneaky-derive".into(), "pgrx-pg-sys".into()],
+            allowed_transitive: Some(vec!["getrandom".into()]),
+            allowed_build_scripts: Some(vec!["getrandom".into()]),
+        };
+        let graph = UserGraph::new(&metadata);
+        assert_eq!(
+            graph.violations(&policy),
+            vec![
+                "`sneaky_derive 0.1.0`, required by `rand 0.8.5`, is denied by `plrust.denied_crates`",
+                "`sneaky_derive 0.1.0`, required by `rand 0.8.5`, is not in `plrust.allowed_transitive_crates`",
+                "`sneaky_derive 0.1.0`, required by `rand 0.8.5`, is a procedural macro, and isn't in `plrust.allowed_build_scripts`",
+            ]
+        );
+
+        assert!(graph.violations(&Policy::default()).is_empty());
+    }
+
+    #[test]
+    fn resolved_dependencies() {
+        let metadata: Metadata = serde_json::from_str(METADATA).unwrap();
+        let resolved = |name: &str, version: &str, direct: bool| ResolvedDependency {
+            name: name.into(),
+            version: version.into(),
+            direct,
+        };
+
+        // `serde` is also a dependency of `plrust-trusted-pgrx`, so it's exempt
+        assert_eq!(
+            UserGraph::new(&metadata).resolved(),
+            vec![
+                resolved("getrandom", "0.2.11", false),
+                resolved("rand", "0.8.5", true),
+                resolved("sneaky_derive", "0.1.0", false),
+            ]
+        );
+
+        // ...unless the user asked for it
+        let metadata: Metadata = serde_json::from_str(&METADATA.replace(
+            r#"{ "id": "root", "dependencies": [ "tpgrx", "rand" ] }"#,
+            r#"{ "id": "root", "dependencies": [ "tpgrx", "rand", "serde" ] }"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            UserGraph::new(&metadata).resolved(),
+            vec![
+                resolved("getrandom", "0.2.11", false),
+                resolved("rand", "0.8.5", true),
+                resolved("serde", "1.0.192", true),
+                resolved("sneaky_derive", "0.1.0", false),
+            ]
+        );
+    }
+}
@@ -16,6 +16,7 @@ use eyre::{eyre, WrapErr};
 use pgrx::pg_sys;
 
 use crate::target::{CompilationTarget, CrossCompilationTarget};
+use crate::user_crate::audit::ResolvedDependency;
 use crate::user_crate::cargo::cargo;
 use crate::user_crate::lint::LintSet;
 use crate::{
@@ -35,6 +36,7 @@ pub(crate) struct FnBuild {
     fn_oid: pg_sys::Oid,
     crate_dir: PathBuf,
     lints: LintSet,
+    dependencies: Vec<ResolvedDependency>,
 }
 
 impl CrateState for FnBuild {}
@@ -48,6 +50,7 @@ impl FnBuild {
         crate_name: String,
         crate_dir: PathBuf,
         lints: LintSet,
+        dependencies: Vec<ResolvedDependency>,
     ) -> Self {
         Self {
             generation_number,
@@ -55,9 +58,15 @@ impl FnBuild {
             fn_oid,
             crate_dir,
             lints,
+            dependencies,
         }
     }
 
+    /// The crates the function's dependencies resolved to
+    pub(crate) fn dependencies(&self) -> &[ResolvedDependency] {
+        &self.dependencies
+    }
+
     fn user_crate_name(&self) -> String {
         crate::plrust::crate_name(self.db_oid, self.fn_oid, self.generation_number)
     }
@@ -85,6 +94,29 @@ impl FnBuild {
         Ok(results)
     }
 
+    /// Build only for the specified cross compilation `targets`, and not for this host.  This is
+    /// used to compile an existing function for targets added to `plrust.compilation_targets`
+    /// after it was created.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            db_oid = %self.db_oid,
+            fn_oid = %self.fn_oid,
+            crate_dir = %self.crate_dir.display(),
+            target_dir = tracing::field::display(target_dir.display()),
+        ))]
+    pub(crate) fn build_for(
+        self,
+        target_dir: &Path,
+        targets: impl IntoIterator<Item = CrossCompilationTarget>,
+    ) -> eyre::Result<Vec<(FnLoad, Output)>> {
+        targets
+            .into_iter()
+            .map(|target| self.build_internal(target_dir, target.target(), Some(target)))
+            .collect()
+    }
+
     #[tracing::instrument(
         level = "debug",
         skip_all,
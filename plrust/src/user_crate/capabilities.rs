@@ -7,6 +7,7 @@ Use of this source code is governed by the PostgreSQL license that can be found
 */
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
 
 /// The capabilities that influence how PL/Rust generates wrapper code for a user function
 // NB:  Make sure to add new ones down below to [`FunctionCapabilitySet::default()`]
@@ -17,6 +18,14 @@ pub(crate) enum FunctionCapability {
     ZeroCopyArrays,
 }
 
+impl Display for FunctionCapability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FunctionCapability::ZeroCopyArrays => write!(f, "ZeroCopyArrays"),
+        }
+    }
+}
+
 /// A set of [`FunctionCapability`] which is stored as metadata in the system catalogs
 #[derive(Debug, Clone, Serialize, Deserialize, Ord, PartialOrd, PartialEq, Eq, Hash)]
 pub(crate) struct FunctionCapabilitySet(BTreeSet<FunctionCapability>);
@@ -45,4 +54,10 @@ impl FunctionCapabilitySet {
     pub fn has_zero_copy_arrays(&self) -> bool {
         self.0.contains(&FunctionCapability::ZeroCopyArrays)
     }
+
+    /// Iterate the [`FunctionCapability`]s in the set
+    #[inline]
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &FunctionCapability> {
+        self.0.iter()
+    }
 }
@@ -13,6 +13,7 @@ use std::process::Command;
 
 use pgrx::{pg_sys, PgMemoryContexts};
 
+use crate::crate_source;
 use crate::gucs::PLRUST_PATH_OVERRIDE;
 use crate::target::CrossCompilationTarget;
 
@@ -27,6 +28,7 @@ pub(crate) fn cargo(
     configure_rustc(&mut command);
     configure_pg_config(&mut command, cross_compilation_target);
     sanitize_env(&mut command);
+    crate_source::configure_cargo(&mut command);
 
     command.env("CARGO_TARGET_DIR", &cargo_target_dir);
     if cfg!(target_os = "macos") {
@@ -40,6 +42,16 @@ pub(crate) fn cargo(
     Ok(command)
 }
 
+/// Builds a `Command::new("plrustc")`, finding `plrustc` on the same `PATH` as [`cargo()`] does
+pub(crate) fn plrustc() -> eyre::Result<Command> {
+    let mut command = Command::new("plrustc");
+
+    configure_path(&mut command)?;
+    sanitize_env(&mut command);
+
+    Ok(command)
+}
+
 /// `cargo` needs a PATH in order to find its tools and we have some rules about setting that up...
 ///
 /// If the `plrust.PATH_override` GUC is set, we just blindly use it.  Otherwise, if PATH is set,
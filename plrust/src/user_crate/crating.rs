@@ -12,11 +12,15 @@ use eyre::WrapErr;
 use pgrx::{pg_sys, PgOid};
 use quote::quote;
 
-use crate::gucs::get_trusted_pgrx_version;
+use crate::gucs::{self, get_trusted_pgrx_version};
+use crate::library::Library;
 use crate::pgproc::{PgProc, ProArgMode};
+use crate::user_crate::interrupts::insert_interrupt_checks;
+use crate::user_crate::line_map::{user_line_map, USER_FN};
 use crate::user_crate::lint::{compile_lints, LintSet};
+use crate::user_crate::profile::release_profile;
 use crate::{
-    user_crate::{parse_source_and_deps, CrateState, CrateVariant, FnVerify},
+    user_crate::{parse_source_and_deps, CrateState, CrateVariant, FnVerify, Parsed},
     PlRustError,
 };
 
@@ -32,8 +36,13 @@ pub(crate) struct FnCrating {
     db_oid: pg_sys::Oid,
     fn_oid: pg_sys::Oid,
     user_dependencies: toml::value::Table,
-    user_code: syn::Block,
+    user_profile: toml::value::Table,
+    /// The user's code, as it was parsed into `user_code`
+    user_source: String,
+    user_code: Parsed<syn::Block>,
     variant: CrateVariant,
+    /// The PL/Rust libraries the function depends on
+    libraries: Vec<Library>,
 }
 
 impl FnCrating {
@@ -51,8 +60,11 @@ impl FnCrating {
             db_oid,
             fn_oid,
             user_dependencies: user_deps.into(),
-            user_code,
+            user_profile: Default::default(),
+            user_source: quote!(#user_code).to_string(),
+            user_code: Parsed::quoted(user_code),
             variant,
+            libraries: Vec::new(),
         }
     }
 
@@ -62,16 +74,54 @@ impl FnCrating {
         fn_oid: pg_sys::Oid,
     ) -> eyre::Result<Self> {
         let meta = PgProc::new(fn_oid)?;
-        let generation_number = meta.generation_number();
-        let (user_code, user_dependencies, capabilities) = parse_source_and_deps(&meta.prosrc())?;
+        let is_trigger = meta.prorettype() == pg_sys::TRIGGEROID;
+        let (argnames, argtypes, argmodes) = match is_trigger {
+            true => (vec![], vec![], vec![]),
+            false => (
+                meta.proargnames(),
+                meta.proallargtypes(),
+                meta.proargmodes(),
+            ),
+        };
+
+        Self::try_from_parts(
+            meta.generation_number(),
+            db_oid,
+            fn_oid,
+            &meta.prosrc(),
+            meta.proowner(),
+            argnames,
+            argtypes,
+            argmodes,
+            meta.prorettype(),
+            meta.proretset(),
+            meta.proisstrict(),
+        )
+    }
 
-        let variant = match meta.prorettype() == pg_sys::TRIGGEROID {
+    /// Create a [`FnCrating`] from the individual pieces of a function's definition, as they'd be
+    /// found in `pg_catalog.pg_proc`.  The function needn't actually exist.
+    #[tracing::instrument(level = "debug", skip(prosrc))]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn try_from_parts(
+        generation_number: u64,
+        db_oid: pg_sys::Oid,
+        fn_oid: pg_sys::Oid,
+        prosrc: &str,
+        owner: pg_sys::Oid,
+        argnames: Vec<syn::Ident>,
+        argtypes: Vec<pg_sys::Oid>,
+        argmodes: Vec<ProArgMode>,
+        return_type: pg_sys::Oid,
+        return_set: bool,
+        is_strict: bool,
+    ) -> eyre::Result<Self> {
+        let (user_source, user_code, user_dependencies, user_profile, capabilities, libraries) =
+            parse_source_and_deps(prosrc, owner)?;
+
+        let variant = match return_type == pg_sys::TRIGGEROID {
             true => CrateVariant::trigger(),
             false => {
-                let argnames = meta.proargnames();
-                let argtypes = meta.proallargtypes();
-                let argmodes = meta.proargmodes();
-
                 // quick fix for issue #197 (and likely related problems) -- we don't yet support these things
                 if argmodes.contains(&ProArgMode::Out) || argmodes.contains(&ProArgMode::InOut) {
                     panic!("PL/Rust does not support functions with `OUT` or `INOUT` arguments")
@@ -86,9 +136,9 @@ impl FnCrating {
                     argnames,
                     argtypes,
                     argmodes,
-                    PgOid::from(meta.prorettype()),
-                    meta.proretset(),
-                    meta.proisstrict(),
+                    PgOid::from(return_type),
+                    return_set,
+                    is_strict,
                     capabilities,
                 )?
             }
@@ -98,11 +148,15 @@ impl FnCrating {
             generation_number,
             db_oid,
             fn_oid,
+            user_source,
             user_code,
             user_dependencies,
+            user_profile,
             variant,
+            libraries,
         })
     }
+
     pub(crate) fn crate_name(&self) -> String {
         crate::plrust::crate_name(self.db_oid, self.fn_oid, self.generation_number)
     }
@@ -113,7 +167,10 @@ impl FnCrating {
         let symbol_ident = proc_macro2::Ident::new(&symbol_name, proc_macro2::Span::call_site());
         tracing::trace!(symbol_name = %symbol_name, "Generating `lib.rs` for validation step");
 
-        let user_code = &self.user_code;
+        let mut user_code = (*self.user_code).clone();
+        if gucs::check_for_interrupts() && has_runtime_support(&get_trusted_pgrx_version()) {
+            insert_interrupt_checks(&mut user_code);
+        }
         let user_fn: syn::ItemFn = match &self.variant {
             CrateVariant::Function {
                 ref arguments,
@@ -154,7 +211,7 @@ impl FnCrating {
             "Generating `Cargo.toml`"
         );
 
-        let mut cargo_manifest = cargo_toml_template(&crate_name, &version_feature);
+        let mut cargo_manifest = cargo_toml_template(&crate_name, &version_feature)?;
         // We have to add the user deps now before we return it.
         match cargo_manifest.entry("dependencies") {
             toml::map::Entry::Occupied(ref mut occupied) => match occupied.get_mut() {
@@ -174,9 +231,44 @@ impl FnCrating {
             }
         };
 
+        // and then whatever `[profile]` settings the user function is allowed to override
+        if let Some(toml::Value::Table(profile)) = cargo_manifest.get_mut("profile") {
+            if let Some(toml::Value::Table(release)) = profile.get_mut("release") {
+                release.extend(self.user_profile.clone());
+            }
+        }
+
         Ok(cargo_manifest)
     }
 
+    /// The generated `lib.rs` and `Cargo.toml`, exactly as they're written to disk by
+    /// [`FnCrating::provision`], along with the lints applied to `lib.rs`.  Unlike [`FnCrating::lib_rs`],
+    /// `lib.rs` ends with `PLRUST_USER_LINES`, which can only be known once the rest is formatted
+    pub(crate) fn generated_source(&self) -> eyre::Result<(String, String, LintSet)> {
+        let (lib_rs, lints) = self.lib_rs()?;
+        let mut lib_rs = prettyplease::unparse(&lib_rs);
+        let cargo_toml =
+            toml::to_string(&self.cargo_toml()?).wrap_err("Stringifying generated `Cargo.toml`")?;
+
+        if !has_runtime_support(&get_trusted_pgrx_version()) {
+            // nothing would read the line map
+            return Ok((lib_rs, cargo_toml, lints));
+        }
+
+        let (generated_lines, user_lines): (Vec<_>, Vec<_>) =
+            user_line_map(&self.user_source, &lib_rs)?
+                .into_iter()
+                .unzip();
+        let line_map: syn::File = syn::parse2(quote! {
+            static PLRUST_USER_LINES: &[(u32, u32)] = &[ #( (#generated_lines, #user_lines) ),* ];
+        })
+        .wrap_err("Generating user line map")?;
+        lib_rs.push('\n');
+        lib_rs.push_str(&prettyplease::unparse(&line_map));
+
+        Ok((lib_rs, cargo_toml, lints))
+    }
+
     /// Provision into a given folder and return the crate directory.
     #[tracing::instrument(level = "debug", skip_all, fields(db_oid = %self.db_oid, fn_oid = %self.fn_oid, parent_dir = %parent_dir.display()))]
     pub(crate) fn provision(&self, parent_dir: &Path) -> eyre::Result<FnVerify> {
@@ -187,18 +279,16 @@ impl FnCrating {
             "Could not create crate directory in configured `plrust.work_dir` location",
         )?;
 
-        let (lib_rs, lints) = self.lib_rs()?;
+        let (lib_rs, cargo_toml, lints) = self.generated_source()?;
         let lib_rs_path = src_dir.join("lib.rs");
-        std::fs::write(&lib_rs_path, &prettyplease::unparse(&lib_rs))
-            .wrap_err("Writing generated `lib.rs`")?;
+        std::fs::write(&lib_rs_path, &lib_rs).wrap_err("Writing generated `lib.rs`")?;
 
-        let cargo_toml = self.cargo_toml()?;
         let cargo_toml_path = crate_dir.join("Cargo.toml");
-        std::fs::write(
-            &cargo_toml_path,
-            &toml::to_string(&cargo_toml).wrap_err("Stringifying generated `Cargo.toml`")?,
-        )
-        .wrap_err("Writing generated `Cargo.toml`")?;
+        std::fs::write(&cargo_toml_path, &cargo_toml).wrap_err("Writing generated `Cargo.toml`")?;
+
+        for library in &self.libraries {
+            library.provision(&crate_dir, &lints)?;
+        }
 
         Ok(FnVerify::new(
             self.generation_number,
@@ -234,8 +324,12 @@ pub(crate) fn shared_imports() -> syn::ItemUse {
     )
 }
 
-pub(crate) fn cargo_toml_template(crate_name: &str, version_feature: &str) -> toml::Table {
+pub(crate) fn cargo_toml_template(
+    crate_name: &str,
+    version_feature: &str,
+) -> eyre::Result<toml::Table> {
     let trusted_pgrx_version = get_trusted_pgrx_version();
+    let release_profile = release_profile()?;
     let mut toml = toml::toml! {
         [package]
         edition = "2021"
@@ -253,9 +347,8 @@ pub(crate) fn cargo_toml_template(crate_name: &str, version_feature: &str) -> to
 
         /* User deps added here */
 
-        [profile.release]
-        opt-level = 3_usize
-        panic = "unwind"
+        [profile]
+        release = release_profile
     };
 
     // if the `PLRUST_TRUSTED_PGRX_OVERRIDE` environment variable is set at compile time
@@ -279,11 +372,16 @@ pub(crate) fn cargo_toml_template(crate_name: &str, version_feature: &str) -> to
         }
     }
 
-    toml
+    Ok(toml)
 }
 
-fn unsafe_mod(mut called_fn: syn::ItemFn, variant: &CrateVariant) -> eyre::Result<syn::ItemMod> {
+fn unsafe_mod(user_fn: syn::ItemFn, variant: &CrateVariant) -> eyre::Result<syn::ItemMod> {
     let imports = shared_imports();
+    let mut called_fn = if has_runtime_support(&get_trusted_pgrx_version()) {
+        report_pg_errors(user_fn)?
+    } else {
+        user_fn
+    };
 
     match variant {
         CrateVariant::Function { .. } => {
@@ -310,6 +408,54 @@ fn unsafe_mod(mut called_fn: syn::ItemFn, variant: &CrateVariant) -> eyre::Resul
     .wrap_err("Could not create opened module")
 }
 
+/// The newest release of `plrust-trusted-pgrx` without `check_for_interrupts()`, `error_report`,
+/// `panics`, and the allocator.  Every release after it is made from a tree that has them
+const LAST_RELEASE_WITHOUT_RUNTIME_SUPPORT: (u64, u64, u64) = (1, 2, 8);
+
+/// Whether user crates compiled against the `plrust-trusted-pgrx` version `trusted_pgrx_version`,
+/// as `plrust.trusted_pgrx_version` names it, can call its runtime support:  interrupt checks,
+/// `PgError` reporting, panic locations, and the memory limits
+pub(crate) fn has_runtime_support(trusted_pgrx_version: &str) -> bool {
+    if option_env!("PLRUST_TRUSTED_PGRX_OVERRIDE").is_some() {
+        // the override is a checkout of `plrust-trusted-pgrx` made alongside PL/Rust
+        return true;
+    }
+
+    match semver::Version::parse(trusted_pgrx_version.trim_start_matches('=')) {
+        Ok(version) => {
+            (version.major, version.minor, version.patch) > LAST_RELEASE_WITHOUT_RUNTIME_SUPPORT
+        }
+        // cargo will refuse it anyway, with a better error than we'd have
+        Err(_) => true,
+    }
+}
+
+/// Wrap the user's function in one that has `::pgrx::error_report::report()` look at what it
+/// returns, so a `PgError` is raised with its SQLSTATE, DETAIL, and HINT rather than as a plain
+/// message, and that has panics report where they happened in the user's code
+fn report_pg_errors(user_fn: syn::ItemFn) -> eyre::Result<syn::ItemFn> {
+    // user functions are never methods, so every argument is a typed pattern
+    let args = user_fn.sig.inputs.iter().filter_map(|arg| match arg {
+        syn::FnArg::Typed(syn::PatType { pat, .. }) => Some(pat),
+        syn::FnArg::Receiver(_) => None,
+    });
+
+    let mut inner_fn = user_fn.clone();
+    inner_fn.sig.ident = proc_macro2::Ident::new(USER_FN, proc_macro2::Span::call_site());
+    let inner_ident = &inner_fn.sig.ident;
+    let sig = &user_fn.sig;
+
+    syn::parse2(quote! {
+        #sig {
+            #[allow(unused_lifetimes)]
+            #inner_fn
+            ::pgrx::panics::locate_panics(crate::PLRUST_USER_LINES);
+            ::pgrx::error_report::report(#inner_ident( #( #args ),* ))
+        }
+    })
+    .wrap_err("Wrapping user function")
+}
+
 fn safe_mod(bare_fn: syn::ItemFn) -> eyre::Result<(syn::ItemMod, LintSet)> {
     let imports = shared_imports();
     let lints = compile_lints();
@@ -384,9 +530,21 @@ mod tests {
             let imports = shared_imports();
             let bare_fn: syn::ItemFn = syn::parse2(quote! {
                 fn #symbol_ident<'a>(arg0: &'a str) -> ::std::result::Result<Option<String>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                    ::pgrx::check_for_interrupts();
                     Some(arg0.to_string())
                 }
             })?;
+            let opened_fn: syn::ItemFn = syn::parse2(quote! {
+                fn #symbol_ident<'a>(arg0: &'a str) -> ::std::result::Result<Option<String>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                    #[allow(unused_lifetimes)]
+                    fn user_fn<'a>(arg0: &'a str) -> ::std::result::Result<Option<String>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                        ::pgrx::check_for_interrupts();
+                        Some(arg0.to_string())
+                    }
+                    ::pgrx::panics::locate_panics(crate::PLRUST_USER_LINES);
+                    ::pgrx::error_report::report(user_fn(arg0))
+                }
+            })?;
             let fixture_lib_rs = parse_quote! {
                 #![deny(unsafe_op_in_unsafe_fn)]
                 pub mod opened {
@@ -394,7 +552,7 @@ mod tests {
 
                     #[allow(unused_lifetimes)]
                     #[pg_extern]
-                    #bare_fn
+                    #opened_fn
                 }
 
                 #[deny(unknown_lints)]
@@ -462,9 +620,21 @@ mod tests {
             let imports = shared_imports();
             let bare_fn: syn::ItemFn = syn::parse2(quote! {
                 fn #symbol_ident<'a>(val: Option<i32>) -> ::std::result::Result<Option<i64>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                    ::pgrx::check_for_interrupts();
                     val.map(|v| v as i64)
                 }
             })?;
+            let opened_fn: syn::ItemFn = syn::parse2(quote! {
+                fn #symbol_ident<'a>(val: Option<i32>) -> ::std::result::Result<Option<i64>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                    #[allow(unused_lifetimes)]
+                    fn user_fn<'a>(val: Option<i32>) -> ::std::result::Result<Option<i64>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                        ::pgrx::check_for_interrupts();
+                        val.map(|v| v as i64)
+                    }
+                    ::pgrx::panics::locate_panics(crate::PLRUST_USER_LINES);
+                    ::pgrx::error_report::report(user_fn(val))
+                }
+            })?;
             let fixture_lib_rs = parse_quote! {
                 #![deny(unsafe_op_in_unsafe_fn)]
                 pub mod opened {
@@ -472,7 +642,7 @@ mod tests {
 
                     #[allow(unused_lifetimes)]
                     #[pg_extern]
-                    #bare_fn
+                    #opened_fn
                 }
 
                 #[deny(unknown_lints)]
@@ -540,9 +710,21 @@ mod tests {
             let imports = shared_imports();
             let bare_fn: syn::ItemFn = syn::parse2(quote! {
                 fn #symbol_ident<'a>(val: &'a str) -> ::std::result::Result<Option<::pgrx::iter::SetOfIterator<'a, Option<String>>>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                    ::pgrx::check_for_interrupts();
                     Ok(Some(std::iter::repeat(val).take(5)))
                 }
             })?;
+            let opened_fn: syn::ItemFn = syn::parse2(quote! {
+                fn #symbol_ident<'a>(val: &'a str) -> ::std::result::Result<Option<::pgrx::iter::SetOfIterator<'a, Option<String>>>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                    #[allow(unused_lifetimes)]
+                    fn user_fn<'a>(val: &'a str) -> ::std::result::Result<Option<::pgrx::iter::SetOfIterator<'a, Option<String>>>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                        ::pgrx::check_for_interrupts();
+                        Ok(Some(std::iter::repeat(val).take(5)))
+                    }
+                    ::pgrx::panics::locate_panics(crate::PLRUST_USER_LINES);
+                    ::pgrx::error_report::report(user_fn(val))
+                }
+            })?;
             let fixture_lib_rs = parse_quote! {
                 #![deny(unsafe_op_in_unsafe_fn)]
                 pub mod opened {
@@ -550,7 +732,7 @@ mod tests {
 
                     #[allow(unused_lifetimes)]
                     #[pg_extern]
-                    #bare_fn
+                    #opened_fn
                 }
 
                 #[deny(unknown_lints)]
@@ -607,9 +789,31 @@ mod tests {
                     Option<::pgrx::heap_tuple::PgHeapTuple<'a, impl ::pgrx::WhoAllocated>>,
                     Box<dyn std::error::Error>,
                 > {
+                    ::pgrx::check_for_interrupts();
                     Ok(trigger.current().unwrap().into_owned())
                 }
             })?;
+            let opened_fn: syn::ItemFn = syn::parse2(quote! {
+                fn #symbol_ident<'a>(
+                    trigger: &'a ::pgrx::PgTrigger<'a>,
+                ) -> ::core::result::Result<
+                    Option<::pgrx::heap_tuple::PgHeapTuple<'a, impl ::pgrx::WhoAllocated>>,
+                    Box<dyn std::error::Error>,
+                > {
+                    #[allow(unused_lifetimes)]
+                    fn user_fn<'a>(
+                        trigger: &'a ::pgrx::PgTrigger<'a>,
+                    ) -> ::core::result::Result<
+                        Option<::pgrx::heap_tuple::PgHeapTuple<'a, impl ::pgrx::WhoAllocated>>,
+                        Box<dyn std::error::Error>,
+                    > {
+                        ::pgrx::check_for_interrupts();
+                        Ok(trigger.current().unwrap().into_owned())
+                    }
+                    ::pgrx::panics::locate_panics(crate::PLRUST_USER_LINES);
+                    ::pgrx::error_report::report(user_fn(trigger))
+                }
+            })?;
             let fixture_lib_rs = parse_quote! {
                 #![deny(unsafe_op_in_unsafe_fn)]
                 pub mod opened {
@@ -617,7 +821,7 @@ mod tests {
 
                     #[allow(unused_lifetimes)]
                     #[pg_trigger]
-                    #bare_fn
+                    #opened_fn
                 }
 
                 #[deny(unknown_lints)]
@@ -0,0 +1,146 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+/*!
+Make user code responsive to query cancellation and `statement_timeout`
+
+Postgres only acts on a cancel request when the backend checks for interrupts, which user code
+otherwise never does.  So that a runaway function can be cancelled without terminating its backend,
+we insert a call to `::pgrx::check_for_interrupts()` at the start of the user's function, of every
+function and method defined within it, and of every loop body, which covers each loop's back-edge.
+*/
+
+use syn::visit_mut::{self, VisitMut};
+
+/// Insert interrupt checks into the body of a user function
+pub(crate) fn insert_interrupt_checks(user_code: &mut syn::Block) {
+    InterruptChecks.visit_block_mut(user_code);
+    prepend_check(user_code);
+}
+
+fn prepend_check(block: &mut syn::Block) {
+    block.stmts.insert(
+        0,
+        syn::parse_quote! {
+            ::pgrx::check_for_interrupts();
+        },
+    );
+}
+
+struct InterruptChecks;
+
+impl VisitMut for InterruptChecks {
+    fn visit_expr_loop_mut(&mut self, node: &mut syn::ExprLoop) {
+        visit_mut::visit_expr_loop_mut(self, node);
+        prepend_check(&mut node.body);
+    }
+
+    fn visit_expr_while_mut(&mut self, node: &mut syn::ExprWhile) {
+        visit_mut::visit_expr_while_mut(self, node);
+        prepend_check(&mut node.body);
+    }
+
+    fn visit_expr_for_loop_mut(&mut self, node: &mut syn::ExprForLoop) {
+        visit_mut::visit_expr_for_loop_mut(self, node);
+        prepend_check(&mut node.body);
+    }
+
+    fn visit_item_fn_mut(&mut self, node: &mut syn::ItemFn) {
+        if node.sig.constness.is_none() {
+            visit_mut::visit_item_fn_mut(self, node);
+            prepend_check(&mut node.block);
+        }
+    }
+
+    fn visit_impl_item_fn_mut(&mut self, node: &mut syn::ImplItemFn) {
+        if node.sig.constness.is_none() {
+            visit_mut::visit_impl_item_fn_mut(self, node);
+            prepend_check(&mut node.block);
+        }
+    }
+
+    fn visit_trait_item_fn_mut(&mut self, node: &mut syn::TraitItemFn) {
+        if node.sig.constness.is_none() {
+            visit_mut::visit_trait_item_fn_mut(self, node);
+            if let Some(block) = &mut node.default {
+                prepend_check(block);
+            }
+        }
+    }
+
+    // what follows is evaluated at compile time, where we can't call `check_for_interrupts()`
+
+    fn visit_item_const_mut(&mut self, _node: &mut syn::ItemConst) {}
+
+    fn visit_item_static_mut(&mut self, _node: &mut syn::ItemStatic) {}
+
+    fn visit_impl_item_const_mut(&mut self, _node: &mut syn::ImplItemConst) {}
+
+    fn visit_trait_item_const_mut(&mut self, _node: &mut syn::TraitItemConst) {}
+
+    fn visit_expr_const_mut(&mut self, _node: &mut syn::ExprConst) {}
+
+    fn visit_expr_repeat_mut(&mut self, node: &mut syn::ExprRepeat) {
+        self.visit_expr_mut(&mut node.expr);
+    }
+
+    fn visit_type_array_mut(&mut self, node: &mut syn::TypeArray) {
+        self.visit_type_mut(&mut node.elem);
+    }
+
+    fn visit_variant_mut(&mut self, node: &mut syn::Variant) {
+        self.visit_fields_mut(&mut node.fields);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quote::quote;
+
+    use super::*;
+
+    #[test]
+    fn loops_and_functions() {
+        let mut user_code: syn::Block = syn::parse_quote! {{
+            const LIMIT: usize = loop { break 10 };
+            fn helper() -> usize { LIMIT }
+            const fn constant() -> usize { 1 }
+            let mut total = [0usize; { constant() }].len();
+            for i in 0..helper() {
+                while total < i {
+                    total += 1;
+                }
+            }
+            Ok(Some(total as i64))
+        }};
+        insert_interrupt_checks(&mut user_code);
+
+        let expected: syn::Block = syn::parse_quote! {{
+            ::pgrx::check_for_interrupts();
+            const LIMIT: usize = loop { break 10 };
+            fn helper() -> usize {
+                ::pgrx::check_for_interrupts();
+                LIMIT
+            }
+            const fn constant() -> usize { 1 }
+            let mut total = [0usize; { constant() }].len();
+            for i in 0..helper() {
+                ::pgrx::check_for_interrupts();
+                while total < i {
+                    ::pgrx::check_for_interrupts();
+                    total += 1;
+                }
+            }
+            Ok(Some(total as i64))
+        }};
+        assert_eq!(
+            quote!(#user_code).to_string(),
+            quote!(#expected).to_string()
+        );
+    }
+}
@@ -0,0 +1,229 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+/*!
+Map lines of the generated `lib.rs` back to lines of the user's code
+
+A panic reports where it happened in the generated `lib.rs`, which users never see.  To report the
+line of their own code instead, we pair up the tokens of the user's code with those of the function
+it became in the `opened` module, which tells us the line of the user's code each line of `lib.rs`
+came from.  The generated function has tokens the user's code doesn't, such as interrupt checks,
+and `prettyplease` may drop a trailing comma, so tokens that don't pair up are skipped.
+
+The map is written into `lib.rs` as `PLRUST_USER_LINES`, after everything else so it doesn't
+shift the lines it describes, and `plrust-trusted-pgrx` consults it when a user function panics.
+*/
+
+use std::cell::Cell;
+use std::ops::Deref;
+
+use eyre::{eyre, WrapErr};
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use quote::ToTokens;
+
+use crate::PlRustError;
+
+/// The name of the user's function, nested within the `#[pg_extern]` or `#[pg_trigger]` function
+/// in the `opened` module
+pub(crate) const USER_FN: &str = "user_fn";
+
+/// Pairs of (`lib.rs` line, user code line), ordered by `lib.rs` line.  `user_source` is the user's
+/// code block, as parsed, and `lib_rs` the generated source it became
+pub(crate) fn user_line_map(user_source: &str, lib_rs: &str) -> eyre::Result<Vec<(u32, u32)>> {
+    let user_tokens = {
+        let user_code =
+            parse_source::<syn::Block>(user_source).map_err(PlRustError::ParsingCodeBlock)?;
+        let mut tokens = Vec::new();
+        for stmt in &user_code.stmts {
+            flatten(stmt.to_token_stream(), &mut tokens);
+        }
+        tokens
+    };
+    let generated_tokens = {
+        let lib_rs = parse_source::<syn::File>(lib_rs).wrap_err("Parsing generated `lib.rs`")?;
+        let user_fn = find_user_fn(&lib_rs)
+            .ok_or_else(|| eyre!("Generated `lib.rs` has no `{USER_FN}` in its `opened` module"))?;
+        let mut tokens = Vec::new();
+        for stmt in &user_fn.block.stmts {
+            flatten(stmt.to_token_stream(), &mut tokens);
+        }
+        tokens
+    };
+
+    Ok(align(&user_tokens, &generated_tokens))
+}
+
+thread_local! {
+    /// How many [`Parsed`] syntax trees are alive on this thread
+    static LIVE_TREES: Cell<usize> = Cell::new(0);
+}
+
+/// Parse Rust source code.  Knowing where tokens are means `proc-macro2` keeps the source text of
+/// everything this thread parses, for as long as the backend lives, so everything we parse from a
+/// string goes through here.  Once the last tree it returned is dropped, the source text is
+/// released, which invalidates the spans of everything parsed, including tokens copied out of the
+/// trees.  Nothing asks those where they are, as only [`user_line_map`] needs to know, and it keeps
+/// its trees until it's done
+pub(crate) fn parse_source<T: syn::parse::Parse>(source: &str) -> syn::Result<Parsed<T>> {
+    // counted first, so that the source text is released even when it doesn't parse
+    let live = LiveTree::new();
+    Ok(Parsed {
+        tree: syn::parse_str(source)?,
+        _live: live,
+    })
+}
+
+/// A syntax tree parsed by [`parse_source`]
+pub(crate) struct Parsed<T> {
+    tree: T,
+    /// Declared after `tree`, so it's dropped after it
+    _live: LiveTree,
+}
+
+impl<T> Parsed<T> {
+    /// A tree that wasn't parsed from source code, such as one made with `quote!`, to use where a
+    /// parsed one is expected
+    #[cfg(any(test, feature = "pg_test"))]
+    pub(crate) fn quoted(tree: T) -> Self {
+        Self {
+            tree,
+            _live: LiveTree::new(),
+        }
+    }
+}
+
+impl<T> Deref for Parsed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.tree
+    }
+}
+
+struct LiveTree(());
+
+impl LiveTree {
+    fn new() -> Self {
+        LIVE_TREES.with(|live| live.set(live.get() + 1));
+        Self(())
+    }
+}
+
+impl Drop for LiveTree {
+    fn drop(&mut self) {
+        let remaining = LIVE_TREES.with(|live| {
+            live.set(live.get() - 1);
+            live.get()
+        });
+        if remaining == 0 {
+            proc_macro2::extra::invalidate_current_thread_spans();
+        }
+    }
+}
+
+fn find_user_fn(lib_rs: &syn::File) -> Option<&syn::ItemFn> {
+    let opened = lib_rs.items.iter().find_map(|item| match item {
+        syn::Item::Mod(module) if module.ident == "opened" => module.content.as_ref(),
+        _ => None,
+    })?;
+    opened.1.iter().find_map(|item| match item {
+        syn::Item::Fn(wrapper) => wrapper.block.stmts.iter().find_map(|stmt| match stmt {
+            syn::Stmt::Item(syn::Item::Fn(user_fn)) if user_fn.sig.ident == USER_FN => {
+                Some(user_fn)
+            }
+            _ => None,
+        }),
+        _ => None,
+    })
+}
+
+/// Each token, with the line it's on
+fn flatten(tokens: TokenStream, flattened: &mut Vec<(String, u32)>) {
+    for token in tokens {
+        match token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                flattened.push((open.into(), group.span_open().start().line as u32));
+                flatten(group.stream(), flattened);
+                flattened.push((close.into(), group.span_close().start().line as u32));
+            }
+            TokenTree::Ident(ident) => {
+                flattened.push((ident.to_string(), ident.span().start().line as u32))
+            }
+            TokenTree::Punct(punct) => {
+                flattened.push((punct.to_string(), punct.span().start().line as u32))
+            }
+            TokenTree::Literal(literal) => {
+                flattened.push((literal.to_string(), literal.span().start().line as u32))
+            }
+        }
+    }
+}
+
+fn align(user_tokens: &[(String, u32)], generated_tokens: &[(String, u32)]) -> Vec<(u32, u32)> {
+    let mut map: Vec<(u32, u32)> = Vec::new();
+    let mut next = 0;
+    for (token, generated_line) in generated_tokens {
+        // a comma or semicolon the user wrote that `prettyplease` didn't
+        if next + 1 < user_tokens.len()
+            && matches!(user_tokens[next].0.as_str(), "," | ";")
+            && user_tokens[next].0 != *token
+            && user_tokens[next + 1].0 == *token
+        {
+            next += 1;
+        }
+
+        if next < user_tokens.len() && user_tokens[next].0 == *token {
+            if map.last().map_or(true, |(line, _)| line != generated_line) {
+                map.push((*generated_line, user_tokens[next].1));
+            }
+            next += 1;
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_lines_to_user_lines() {
+        let user_source = "{ let values = vec![1, 2, 3,];
+            // a comment, which isn't in the generated code
+            for value in values { if value > 2 { panic!(\"too big\") } }
+            Ok(Some(1))
+        \n}";
+        let lib_rs = "pub mod opened {
+    fn plrust_fn_oid_1_1<'a>() -> Result<Option<i32>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        fn user_fn<'a>() -> Result<Option<i32>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+            ::pgrx::check_for_interrupts();
+            let values = vec![1, 2, 3];
+            for value in values {
+                ::pgrx::check_for_interrupts();
+                if value > 2 {
+                    panic!(\"too big\")
+                }
+            }
+            Ok(Some(1))
+        }
+        ::pgrx::error_report::report(user_fn())
+    }
+}
+";
+        assert_eq!(
+            user_line_map(user_source, lib_rs).unwrap(),
+            vec![(5, 1), (6, 3), (8, 3), (9, 3), (10, 3), (11, 3), (12, 4)]
+        );
+    }
+}
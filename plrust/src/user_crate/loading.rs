@@ -10,7 +10,7 @@ use pgrx::pg_sys;
 
 use crate::target::CompilationTarget;
 use crate::user_crate::lint::LintSet;
-use crate::user_crate::{CrateState, FnValidate};
+use crate::user_crate::{CrateState, FnValidate, SharedObject};
 
 /// Available and ready-to-load PL/Rust function
 ///
@@ -62,7 +62,7 @@ impl FnLoad {
             self.db_oid,
             self.fn_oid,
             self.symbol,
-            self.shared_object,
+            SharedObject::Bytes(self.shared_object),
             self.lints,
         )
     }
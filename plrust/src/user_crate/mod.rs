@@ -25,28 +25,39 @@ use proc_macro2::TokenStream;
 use quote::quote;
 
 use crate::allow_list::{load_allowlist, AllowList, Error};
+use crate::cache::CachedLibrary;
+use crate::library::{take_libraries, Library};
+use crate::memory::LibraryMemory;
+pub(crate) use audit::ResolvedDependency;
 pub(crate) use build::FnBuild;
 use crate_variant::CrateVariant;
 pub(crate) use crating::FnCrating;
+pub(crate) use line_map::{parse_source, Parsed};
 pub(crate) use loading::FnLoad;
 pub(crate) use ready::FnReady;
 pub(crate) use validate::FnValidate;
 pub(crate) use verify::FnVerify;
 
+use crate::pgproc::ProArgMode;
 use crate::prosrc::extract_source_and_capabilities_from_json;
-use crate::target::CompilationTarget;
+use crate::target::{CompilationTarget, CrossCompilationTarget};
 use crate::user_crate::capabilities::FunctionCapabilitySet;
 use crate::user_crate::lint::LintSet;
 use crate::PlRustError;
 
+mod audit;
 mod build;
 pub(crate) mod capabilities;
 mod cargo;
 mod crate_variant;
-mod crating;
+pub(crate) mod crating;
+mod interrupts;
+mod line_map;
 pub(crate) mod lint;
 mod loading;
+pub(crate) mod profile;
 mod ready;
+mod strict;
 mod validate;
 mod verify;
 
@@ -80,6 +91,14 @@ premature abstraction would be unwise.
 */
 pub(crate) trait CrateState {}
 
+/// A compiled user function shared library that's ready to be dlopen()'d
+pub(crate) enum SharedObject {
+    /// The library's bytes, which must first be written somewhere they can be dlopen()'d
+    Bytes(Vec<u8>),
+    /// The library, as found in the [`crate::cache`]
+    Cached(CachedLibrary),
+}
+
 impl UserCrate<FnCrating> {
     #[cfg(any(test, feature = "pg_test"))]
     #[tracing::instrument(level = "debug", skip_all)]
@@ -105,6 +124,36 @@ impl UserCrate<FnCrating> {
         unsafe { FnCrating::try_from_fn_oid(db_oid, fn_oid).map(Self) }
     }
     #[tracing::instrument(level = "debug", skip_all)]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn try_from_parts(
+        generation_number: u64,
+        db_oid: pg_sys::Oid,
+        fn_oid: pg_sys::Oid,
+        prosrc: &str,
+        owner: pg_sys::Oid,
+        argnames: Vec<syn::Ident>,
+        argtypes: Vec<pg_sys::Oid>,
+        argmodes: Vec<ProArgMode>,
+        return_type: pg_sys::Oid,
+        return_set: bool,
+        is_strict: bool,
+    ) -> eyre::Result<Self> {
+        FnCrating::try_from_parts(
+            generation_number,
+            db_oid,
+            fn_oid,
+            prosrc,
+            owner,
+            argnames,
+            argtypes,
+            argmodes,
+            return_type,
+            return_set,
+            is_strict,
+        )
+        .map(Self)
+    }
+    #[tracing::instrument(level = "debug", skip_all)]
     #[allow(unused)] // used in tests
     pub fn lib_rs(&self) -> eyre::Result<(syn::File, LintSet)> {
         self.0.lib_rs()
@@ -114,6 +163,11 @@ impl UserCrate<FnCrating> {
     pub fn cargo_toml(&self) -> eyre::Result<toml::value::Table> {
         self.0.cargo_toml()
     }
+    /// The generated `lib.rs` and `Cargo.toml`, as they'd be written by [`UserCrate::provision`]
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub(crate) fn generated_source(&self) -> eyre::Result<(String, String, LintSet)> {
+        self.0.generated_source()
+    }
     /// Provision into a given folder and return the crate directory.
     #[tracing::instrument(level = "debug", skip_all)]
     pub fn provision(&self, parent_dir: &Path) -> eyre::Result<UserCrate<FnVerify>> {
@@ -160,6 +214,32 @@ impl UserCrate<FnBuild> {
             .map(|(state, output)| (UserCrate(state), output))
             .collect())
     }
+
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            db_oid = %self.0.db_oid(),
+            fn_oid = %self.0.fn_oid(),
+            crate_dir = %self.0.crate_dir().display(),
+            target_dir = tracing::field::display(target_dir.display()),
+        ))]
+    pub fn build_for(
+        self,
+        target_dir: &Path,
+        targets: impl IntoIterator<Item = CrossCompilationTarget>,
+    ) -> eyre::Result<Vec<(UserCrate<FnLoad>, Output)>> {
+        Ok(self
+            .0
+            .build_for(target_dir, targets)?
+            .into_iter()
+            .map(|(state, output)| (UserCrate(state), output))
+            .collect())
+    }
+
+    pub(crate) fn dependencies(&self) -> &[ResolvedDependency] {
+        self.0.dependencies()
+    }
 }
 
 impl UserCrate<FnLoad> {
@@ -196,14 +276,37 @@ impl UserCrate<FnLoad> {
 }
 
 impl UserCrate<FnValidate> {
+    /// Skip straight to validating a shared library found in the [`crate::cache`]
+    #[tracing::instrument(level = "debug")]
+    pub(crate) fn cached(
+        generation_number: u64,
+        db_oid: pg_sys::Oid,
+        fn_oid: pg_sys::Oid,
+        library: CachedLibrary,
+        symbol: Option<String>,
+        lints: LintSet,
+    ) -> eyre::Result<Self> {
+        FnValidate::new(
+            generation_number,
+            db_oid,
+            fn_oid,
+            symbol,
+            SharedObject::Cached(library),
+            lints,
+        )
+        .map(UserCrate)
+    }
+
+    /// Load the library, which was compiled with `plrust-trusted-pgrx`'s runtime support if
+    /// `runtime_support` is true
     #[tracing::instrument(level = "debug", skip_all)]
-    pub(crate) unsafe fn load(self) -> eyre::Result<UserCrate<FnReady>> {
-        unsafe { self.0.load().map(UserCrate) }
+    pub(crate) unsafe fn load(self, runtime_support: bool) -> eyre::Result<UserCrate<FnReady>> {
+        unsafe { self.0.load(runtime_support).map(UserCrate) }
     }
 }
 
 impl UserCrate<FnReady> {
-    #[tracing::instrument(level = "debug", skip_all)]
+    #[inline]
     pub unsafe fn evaluate(&self, fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
         unsafe { self.0.evaluate(fcinfo) }
     }
@@ -220,6 +323,19 @@ impl UserCrate<FnReady> {
     pub(crate) fn generation_number(&self) -> u64 {
         self.0.generation_number()
     }
+
+    /// Whether the function was compiled with `plrust-trusted-pgrx`'s runtime support, so it
+    /// reports its panics and errors to us, and can be held to `plrust.max_call_memory` and
+    /// `plrust.max_backend_memory`
+    #[inline]
+    pub(crate) fn runtime_support(&self) -> bool {
+        self.0.runtime_support()
+    }
+
+    #[inline]
+    pub(crate) fn memory(&self) -> &LibraryMemory {
+        self.0.memory()
+    }
 }
 
 #[tracing::instrument(level = "debug", skip_all, fields(type_oid = %type_oid.value()))]
@@ -305,15 +421,56 @@ pub(crate) fn oid_to_syn_type(
         .map_err(|e| PlRustError::ParsingRustMapping(type_oid.value(), rust_type.to_string(), e))
 }
 
-#[tracing::instrument(level = "debug", skip_all)]
-fn parse_source_and_deps(
-    code_and_deps: &str,
-) -> eyre::Result<(syn::Block, toml::value::Table, FunctionCapabilitySet)> {
+/// The sections of user-provided `LANGUAGE plrust` source code
+#[derive(Debug, Default)]
+pub(crate) struct SourceSections {
+    /// the `[code]` section, which is also any code outside of a section
+    pub(crate) code: String,
+    /// the `[dependencies]` section
+    pub(crate) dependencies: String,
+    /// the `[profile]` section
+    pub(crate) profile: String,
+}
+
+/// Split user-provided `LANGUAGE plrust` source into its [`SourceSections`]
+pub(crate) fn split_source_sections(code_and_deps: &str) -> SourceSections {
     enum Parse {
         Code,
         Deps,
+        Profile,
     }
 
+    let mut sections = SourceSections::default();
+    let mut parse = Parse::Code;
+
+    for line in code_and_deps.trim().split_inclusive('\n') {
+        match line.trim() {
+            "[dependencies]" => parse = Parse::Deps,
+            "[profile]" => parse = Parse::Profile,
+            "[code]" => parse = Parse::Code,
+            _ => match parse {
+                Parse::Code => sections.code.push_str(line),
+                Parse::Deps => sections.dependencies.push_str(line),
+                Parse::Profile => sections.profile.push_str(line),
+            },
+        }
+    }
+
+    sections
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+fn parse_source_and_deps(
+    code_and_deps: &str,
+    owner: pg_sys::Oid,
+) -> eyre::Result<(
+    String,
+    Parsed<syn::Block>,
+    toml::value::Table,
+    toml::value::Table,
+    FunctionCapabilitySet,
+    Vec<Library>,
+)> {
     // it's possible, especially via a `pg_restore` operation, that "code_and_deps" is actually
     // our JSON structure stored in `pg_proc.prosrc`.  We'll pass it to [`extract_source_and_capabilities_from_json`]
     // and let it figure out what to do.
@@ -322,43 +479,54 @@ fn parse_source_and_deps(
     // [`maybe_extract_source_from_json`] returns anyways.  We ignore everything else that was there
     // and ultimately do a full compilation based on the current state of the Postgres database,
     // taking into account current GUC values and other parameters that may impact compilation.
+    // (Unless `plrust.reuse_compiled_functions` is on, in which case the validator may decide to
+    // keep the compiled artifact after all -- see [`crate::plrust::reuse_compiled_function`])
     //
     // It's also possible "code_and_deps" is exactly that, given to us via a user-written
     // "CREATE OR REPLACE FUNCTION" statement.
     let (code_and_deps, capabilities) = extract_source_and_capabilities_from_json(code_and_deps);
 
-    let mut deps_block = String::new();
-    let mut code_block = String::from("{ ");
-    let mut parse = Parse::Code;
-
-    for line in code_and_deps.trim().split_inclusive('\n') {
-        match line.trim() {
-            "[dependencies]" => parse = Parse::Deps,
-            "[code]" => parse = Parse::Code,
-            _ => match parse {
-                Parse::Code => code_block.push_str(line),
-                Parse::Deps => deps_block.push_str(line),
-            },
-        }
-    }
-
-    code_block.push_str("\n}");
+    let sections = split_source_sections(&code_and_deps);
+    let code_block = format!("{{ {}\n}}", sections.code);
 
-    let mut user_dependencies = validate_user_dependencies(deps_block)?;
+    let mut user_dependencies = validate_user_dependencies(sections.dependencies)?;
+    let mut libraries = take_libraries(&mut user_dependencies)?;
+    let user_profile = toml::from_str(&sections.profile)
+        .wrap_err("Parsing `[profile]` section")
+        .and_then(profile::restrict_profile)?;
 
-    if crate::gucs::PLRUST_ALLOWED_DEPENDENCIES.get().is_some() {
-        let allowlist = load_allowlist().wrap_err("Error loading dependency allow-list")?;
+    if crate::allow_list::is_configured() {
+        let allowlist = load_allowlist(owner).wrap_err("Error loading dependency allow-list")?;
         user_dependencies = restrict_dependencies(user_dependencies, &allowlist)?;
+
+        // what a library depends on must be allowed for the function's owner, too
+        for library in &mut libraries {
+            library.dependencies =
+                restrict_dependencies(std::mem::take(&mut library.dependencies), &allowlist)
+                    .wrap_err_with(|| format!("Dependencies of library `{}`", library.name))?;
+        }
     }
 
-    let user_code: syn::Block =
-        syn::parse_str(&code_block).map_err(PlRustError::ParsingCodeBlock)?;
+    // libraries are written next to the function's crate, and depended on by path
+    for library in &libraries {
+        user_dependencies.insert(library.name.clone(), library.dependency_entry());
+    }
 
-    Ok((user_code, user_dependencies, capabilities))
+    let user_code =
+        parse_source::<syn::Block>(&code_block).map_err(PlRustError::ParsingCodeBlock)?;
+
+    Ok((
+        code_block,
+        user_code,
+        user_dependencies,
+        user_profile,
+        capabilities,
+        libraries,
+    ))
 }
 
 #[tracing::instrument(level = "debug", skip_all)]
-fn validate_user_dependencies(user_deps: String) -> eyre::Result<toml::value::Table> {
+pub(crate) fn validate_user_dependencies(user_deps: String) -> eyre::Result<toml::value::Table> {
     let user_dependencies: toml::value::Table = toml::from_str(&user_deps)?;
 
     //
@@ -398,8 +566,14 @@ enum RestrictionError {
     VersionMissing(String),
     #[error("`{0}`'s version is not a String type")]
     NotAString(String),
-    #[error("When specified, the dependency properties of `{1}` for `{0}` must be the same as the restricted set of `{2}`")]
-    DependencyDeclarationMismatch(String, String, String),
+    #[error("Feature `{1}` of `{0}` is not permitted.  The allow-list permits: [{2}]")]
+    FeatureNotPermitted(String, String, String),
+    #[error("The allow-list does not permit the default features of `{0}`")]
+    DefaultFeaturesNotPermitted(String),
+    #[error("`{0}`'s `{1}` property is malformed")]
+    MalformedProperty(String, String),
+    #[error("`{0}`'s `{1}` property is not supported.  Only `version`, `features`, and `default-features` may be specified")]
+    UnsupportedProperty(String, String),
     #[error("Dependency Error: {0}")]
     DependencyError(crate::allow_list::Error),
 }
@@ -438,26 +612,16 @@ fn restrict_dependencies(
         let wanted_version = extract_version(wanted_dep, wanted_value)?;
         let used = allowed_dep.get_dependency_entry(wanted_version)?;
 
-        // validate that the other properties that the user might have specified exactly match what
-        // is in the allow-list
-        if let Some(wanted_table) = wanted_value.as_table() {
-            if wanted_table.len() > 1 {
-                let mut wanted = wanted_table.clone();
-                let mut used = used.as_table().unwrap().clone();
-
-                // don't compare the version values
-                wanted.remove("version");
-                used.remove("version");
-
-                if used != wanted {
-                    return Err(RestrictionError::DependencyDeclarationMismatch(
-                        wanted_dep.clone(),
-                        toml::to_string(&wanted).unwrap().trim().replace('\n', ", "),
-                        toml::to_string(&used).unwrap().trim().replace('\n', ", "),
-                    ));
-                }
+        // the allow-list entry's `features` are the features the user is permitted to use and its
+        // `default-features` is whether or not they may use the crate's default features.  When the
+        // user doesn't say otherwise they get everything the entry permits, but they can also ask
+        // for less, in which case we emit exactly what they asked for
+        let used = match wanted_value.as_table() {
+            Some(wanted_table) => {
+                restrict_properties(wanted_dep, wanted_table, used.as_table().unwrap().clone())?
             }
-        }
+            None => used,
+        };
 
         actual.insert(wanted_dep.clone(), used);
     }
@@ -465,6 +629,85 @@ fn restrict_dependencies(
     Ok(actual)
 }
 
+/// Narrow the allow-list's `permitted` dependency table down to the `features` and `default-features`
+/// the user specified in `wanted`, provided they're within what's permitted
+fn restrict_properties(
+    depname: &str,
+    wanted: &toml::value::Table,
+    mut permitted: toml::value::Table,
+) -> Result<toml::value::Value, RestrictionError> {
+    let malformed = |property: &str| {
+        RestrictionError::MalformedProperty(depname.to_string(), property.to_string())
+    };
+
+    for (property, value) in wanted {
+        match property.as_str() {
+            // already resolved against the allow-list
+            "version" => {}
+
+            "features" => {
+                let permitted_features = permitted
+                    .get("features")
+                    .and_then(|features| features.as_array())
+                    .map(|features| {
+                        features
+                            .iter()
+                            .filter_map(|f| f.as_str())
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+
+                let wanted_features = value.as_array().ok_or_else(|| malformed(property))?;
+                for feature in wanted_features {
+                    let feature = feature.as_str().ok_or_else(|| malformed(property))?;
+                    if !permitted_features.contains(&feature) {
+                        return Err(RestrictionError::FeatureNotPermitted(
+                            depname.to_string(),
+                            feature.to_string(),
+                            permitted_features
+                                .iter()
+                                .map(|f| format!("\"{f}\""))
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        ));
+                    }
+                }
+
+                if wanted_features.is_empty() {
+                    permitted.remove("features");
+                } else {
+                    permitted.insert("features".to_string(), value.clone());
+                }
+            }
+
+            "default-features" => {
+                let wanted_default = value.as_bool().ok_or_else(|| malformed(property))?;
+                let permitted_default = permitted
+                    .get("default-features")
+                    .and_then(|default_features| default_features.as_bool())
+                    .unwrap_or(true);
+
+                if wanted_default && !permitted_default {
+                    return Err(RestrictionError::DefaultFeaturesNotPermitted(
+                        depname.to_string(),
+                    ));
+                } else if !wanted_default {
+                    permitted.insert("default-features".to_string(), value.clone());
+                }
+            }
+
+            _ => {
+                return Err(RestrictionError::UnsupportedProperty(
+                    depname.to_string(),
+                    property.clone(),
+                ))
+            }
+        }
+    }
+
+    Ok(toml::value::Value::Table(permitted))
+}
+
 #[cfg(any(test, feature = "pg_test"))]
 #[pgrx::pg_schema]
 mod tests {
@@ -490,6 +733,8 @@ b = "*"
 c = "=1.2.3"
 d = { version = "=3.4.5", features = [ "x", "y", "z" ] }
 e = ">=0.8, <0.9"
+f = "^1.2"
+g = { version = "=1.0.0", features = [ "rt", "net" ], default-features = false }
     "#;
 
         let allowed = parse_allowlist(TOML)?;
@@ -498,9 +743,37 @@ e = ">=0.8, <0.9"
         assert_eq!(toml! { a = { version = "=3.0" } }, restricted);
 
         let restricted = restrict_dependencies(toml! { a = { version = "2.4.5", features = [ "q", "r", "p" ], default-features = false } }, &allowed);
-        if !matches!(restricted, Err(RestrictionError::DependencyDeclarationMismatch(..))) {
-            panic!("got valid restricted table when we shouldn't have");
-        }
+        assert_eq!(restricted, Err(RestrictionError::FeatureNotPermitted("a".to_string(), "q".to_string(), r#""x", "y", "z""#.to_string())));
+
+        let restricted = restrict_dependencies(toml! { a = { version = "2.4.5", features = [ "y" ] } }, &allowed)?;
+        assert_eq!(toml! { a = { version = "=2.4.5", features = [ "y" ] } }, restricted);
+
+        let restricted = restrict_dependencies(toml! { a = { version = "2.4.5", features = [ "z", "x" ], default-features = false } }, &allowed)?;
+        assert_eq!(toml! { a = { version = "=2.4.5", features = [ "z", "x" ], default-features = false } }, restricted);
+
+        let restricted = restrict_dependencies(toml! { a = { version = "2.4.5", features = [] } }, &allowed)?;
+        assert_eq!(toml! { a = { version = "=2.4.5" } }, restricted);
+
+        let restricted = restrict_dependencies(toml! { a = { version = "3.0", features = [ "x" ] } }, &allowed);
+        assert_eq!(restricted, Err(RestrictionError::FeatureNotPermitted("a".to_string(), "x".to_string(), "".to_string())));
+
+        let restricted = restrict_dependencies(toml! { a = { version = "2.4.5", features = "x" } }, &allowed);
+        assert_eq!(restricted, Err(RestrictionError::MalformedProperty("a".to_string(), "features".to_string())));
+
+        let restricted = restrict_dependencies(toml! { a = { version = "2.4.5", path = "/tmp/a" } }, &allowed);
+        assert_eq!(restricted, Err(RestrictionError::UnsupportedProperty("a".to_string(), "path".to_string())));
+
+        let restricted = restrict_dependencies(toml! { g = { version = "1.0.0", features = [ "rt" ] } }, &allowed)?;
+        assert_eq!(toml! { g = { version = "=1.0.0", features = [ "rt" ], default-features = false } }, restricted);
+
+        let restricted = restrict_dependencies(toml! { g = { version = "1.0.0", default-features = true } }, &allowed);
+        assert_eq!(restricted, Err(RestrictionError::DefaultFeaturesNotPermitted("g".to_string())));
+
+        let restricted = restrict_dependencies(toml! { g = { version = "1.0.0", default-features = false } }, &allowed)?;
+        assert_eq!(toml! { g = { version = "=1.0.0", features = [ "rt", "net" ], default-features = false } }, restricted);
+
+        let restricted = restrict_dependencies(toml! { d = { version = "3.4.5", default-features = true } }, &allowed)?;
+        assert_eq!(toml! { d = { version = "=3.4.5", features = [ "x", "y", "z" ] } }, restricted);
 
         let restricted = restrict_dependencies(toml! { a = { version = "2.4.5", features = [ "x", "y", "z" ] } }, &allowed)?;
         assert_eq!(toml! { a = { version = "=2.4.5", features = [ "x", "y", "z" ] } }, restricted);
@@ -541,6 +814,15 @@ e = ">=0.8, <0.9"
         let restricted = restrict_dependencies(toml! { e = "0.9" } , &allowed);
         assert_eq!(restricted, Err(RestrictionError::DependencyError(Error::VersionNotPermitted("0.9".to_string()))));
 
+        let restricted = restrict_dependencies(toml! { f = "1.4.0" } , &allowed)?;
+        assert_eq!(toml! { f = { version = "=1.4.0" } }, restricted);
+
+        let restricted = restrict_dependencies(toml! { f = "^1.3" } , &allowed)?;
+        assert_eq!(toml! { f = { version = ">=1.2.0, <2.0.0" } }, restricted);
+
+        let restricted = restrict_dependencies(toml! { f = "2.0" } , &allowed);
+        assert_eq!(restricted, Err(RestrictionError::DependencyError(Error::VersionNotPermitted("2.0".to_string()))));
+
         Ok(())
     }
 
@@ -590,9 +872,21 @@ e = ">=0.8, <0.9"
             let imports = crate::user_crate::crating::shared_imports();
             let bare_fn: syn::ItemFn = syn::parse2(quote! {
                 fn #symbol_ident<'a>(arg0: &'a str) -> ::std::result::Result<Option<String>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                    ::pgrx::check_for_interrupts();
                     Ok(Some(arg0.to_string()))
                 }
             })?;
+            let opened_fn: syn::ItemFn = syn::parse2(quote! {
+                fn #symbol_ident<'a>(arg0: &'a str) -> ::std::result::Result<Option<String>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                    #[allow(unused_lifetimes)]
+                    fn user_fn<'a>(arg0: &'a str) -> ::std::result::Result<Option<String>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+                        ::pgrx::check_for_interrupts();
+                        Ok(Some(arg0.to_string()))
+                    }
+                    ::pgrx::panics::locate_panics(crate::PLRUST_USER_LINES);
+                    ::pgrx::error_report::report(user_fn(arg0))
+                }
+            })?;
             let fixture_lib_rs = parse_quote! {
                 #![deny(unsafe_op_in_unsafe_fn)]
                 pub mod opened {
@@ -600,7 +894,7 @@ e = ">=0.8, <0.9"
 
                     #[allow(unused_lifetimes)]
                     #[pg_extern]
-                    #bare_fn
+                    #opened_fn
                 }
 
                 #[deny(unknown_lints)]
@@ -621,7 +915,7 @@ e = ">=0.8, <0.9"
             let generated_cargo_toml = generated.cargo_toml()?;
             let version_feature = format!("pgrx/pg{}", pgrx::pg_sys::get_pg_major_version_num());
             let crate_name = crate::plrust::crate_name(db_oid, fn_oid, generation_number);
-            let fixture_cargo_toml = cargo_toml_template(&crate_name, &version_feature);
+            let fixture_cargo_toml = cargo_toml_template(&crate_name, &version_feature)?;
 
             assert_eq!(
                 toml::to_string(&generated_cargo_toml)?,
@@ -636,7 +930,9 @@ e = ">=0.8, <0.9"
             for (built, _output) in validated.build(&target_dir)? {
                 // Without an fcinfo, we can't call this.
                 let validated = unsafe { built.validate()? };
-                let _loaded = unsafe { validated.load()? };
+                let runtime_support =
+                    crating::has_runtime_support(&crate::gucs::get_trusted_pgrx_version());
+                let _loaded = unsafe { validated.load(runtime_support)? };
             }
 
             Ok(())
@@ -0,0 +1,300 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+/*!
+The `[profile.release]` section of a user function's generated `Cargo.toml`
+
+Administrators configure the profile server-wide with the `plrust.profile_*` GUCs, and can allow
+functions to override some of those settings, within bounds, via `plrust.allowed_profile_overrides`.
+A function does so with a `[profile]` section in its source code.
+*/
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::gucs;
+
+/// The `[profile.release]` settings that can be configured for user functions.  Notably, `panic`
+/// is not one of them -- PL/Rust requires that it always be `"unwind"`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum ProfileSetting {
+    OptLevel,
+    Lto,
+    CodegenUnits,
+    Debug,
+    Strip,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub(crate) enum ProfileError {
+    #[error("`{0}` is not a supported `[profile]` setting")]
+    UnknownSetting(String),
+    #[error("`{0}` may not be overridden by a function.  See `plrust.allowed_profile_overrides`")]
+    NotAllowed(ProfileSetting),
+    #[error("`{1}` is not a valid value for `{0}`")]
+    InvalidValue(ProfileSetting, toml::Value),
+    #[error("`{1}` is not an allowed value for `{0}`.  Allowed values are: {2}")]
+    ValueNotAllowed(ProfileSetting, toml::Value, String),
+}
+
+impl Display for ProfileSetting {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.key())
+    }
+}
+
+impl TryFrom<&str> for ProfileSetting {
+    type Error = ProfileError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "opt-level" => Ok(ProfileSetting::OptLevel),
+            "lto" => Ok(ProfileSetting::Lto),
+            "codegen-units" => Ok(ProfileSetting::CodegenUnits),
+            "debug" => Ok(ProfileSetting::Debug),
+            "strip" => Ok(ProfileSetting::Strip),
+            _ => Err(ProfileError::UnknownSetting(value.to_string())),
+        }
+    }
+}
+
+impl ProfileSetting {
+    /// The setting's key in a `Cargo.toml` `[profile]` section
+    pub(crate) fn key(&self) -> &'static str {
+        match self {
+            ProfileSetting::OptLevel => "opt-level",
+            ProfileSetting::Lto => "lto",
+            ProfileSetting::CodegenUnits => "codegen-units",
+            ProfileSetting::Debug => "debug",
+            ProfileSetting::Strip => "strip",
+        }
+    }
+
+    /// Validate `value` and convert it into the single form we give to `cargo` for this setting.
+    /// Values as they'd be written in a `Cargo.toml` are accepted, as are their string forms so
+    /// that the GUCs can express them.
+    pub(crate) fn normalize(&self, value: &toml::Value) -> Result<toml::Value, ProfileError> {
+        use toml::Value;
+
+        let normalized = match (self, value) {
+            (ProfileSetting::OptLevel, Value::Integer(i @ 0..=3)) => Some(Value::Integer(*i)),
+            (ProfileSetting::OptLevel, Value::String(s)) => match s.as_str() {
+                "0" | "1" | "2" | "3" => Some(Value::Integer(s.parse().unwrap())),
+                "s" | "z" => Some(Value::String(s.clone())),
+                _ => None,
+            },
+
+            (ProfileSetting::Lto, Value::Boolean(false)) => Some("off".into()),
+            (ProfileSetting::Lto, Value::Boolean(true)) => Some("fat".into()),
+            (ProfileSetting::Lto, Value::String(s)) => match s.as_str() {
+                "off" | "false" => Some("off".into()),
+                "fat" | "true" => Some("fat".into()),
+                "thin" => Some("thin".into()),
+                _ => None,
+            },
+
+            (ProfileSetting::CodegenUnits, Value::Integer(i @ 1..=256)) => Some(Value::Integer(*i)),
+            (ProfileSetting::CodegenUnits, Value::String(s)) => match s.parse::<i64>() {
+                Ok(i @ 1..=256) => Some(Value::Integer(i)),
+                _ => None,
+            },
+
+            (ProfileSetting::Debug, Value::Boolean(false) | Value::Integer(0)) => {
+                Some("none".into())
+            }
+            (ProfileSetting::Debug, Value::Integer(1)) => Some("limited".into()),
+            (ProfileSetting::Debug, Value::Boolean(true) | Value::Integer(2)) => {
+                Some("full".into())
+            }
+            (ProfileSetting::Debug, Value::String(s)) => match s.as_str() {
+                "none" | "false" | "0" => Some("none".into()),
+                "line-directives-only" | "line-tables-only" | "limited" => Some(s.clone().into()),
+                "1" => Some("limited".into()),
+                "full" | "true" | "2" => Some("full".into()),
+                _ => None,
+            },
+
+            (ProfileSetting::Strip, Value::Boolean(false)) => Some("none".into()),
+            (ProfileSetting::Strip, Value::Boolean(true)) => Some("symbols".into()),
+            (ProfileSetting::Strip, Value::String(s)) => match s.as_str() {
+                "none" | "false" => Some("none".into()),
+                "debuginfo" => Some("debuginfo".into()),
+                "symbols" | "true" => Some("symbols".into()),
+                _ => None,
+            },
+
+            _ => None,
+        };
+
+        normalized.ok_or_else(|| ProfileError::InvalidValue(*self, value.clone()))
+    }
+}
+
+/// The settings a function may override, as configured by `plrust.allowed_profile_overrides`.
+/// A setting mapped to `None` may be overridden with any valid value, otherwise only with one of
+/// the listed values.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct AllowedOverrides(BTreeMap<ProfileSetting, Option<Vec<toml::Value>>>);
+
+impl TryFrom<&str> for AllowedOverrides {
+    type Error = ProfileError;
+
+    /// Parses the `plrust.allowed_profile_overrides` format, which is a comma-separated list of
+    /// settings, each optionally followed by `=` and a `|`-separated list of allowed values.  ie:
+    ///
+    /// ```text
+    /// opt-level, lto = off|thin, strip
+    /// ```
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut allowed = BTreeMap::new();
+        for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (key, values) = match entry.split_once('=') {
+                Some((key, values)) => (key.trim(), Some(values)),
+                None => (entry, None),
+            };
+            let setting = ProfileSetting::try_from(key)?;
+            let values = values
+                .map(|values| {
+                    values
+                        .split('|')
+                        .map(|v| setting.normalize(&toml::Value::String(v.trim().to_string())))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?;
+            allowed.insert(setting, values);
+        }
+        Ok(AllowedOverrides(allowed))
+    }
+}
+
+impl AllowedOverrides {
+    /// Validate a function's `[profile]` section against these allowed overrides, returning the
+    /// settings it overrides in the form we give to `cargo`
+    pub(crate) fn restrict(&self, profile: toml::Table) -> Result<toml::Table, ProfileError> {
+        let mut restricted = toml::Table::new();
+        for (key, value) in profile {
+            let setting = ProfileSetting::try_from(key.as_str())?;
+            let value = setting.normalize(&value)?;
+            match self.0.get(&setting) {
+                None => return Err(ProfileError::NotAllowed(setting)),
+                Some(Some(values)) if !values.contains(&value) => {
+                    let values = values
+                        .iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    return Err(ProfileError::ValueNotAllowed(setting, value, values));
+                }
+                Some(_) => {}
+            }
+            restricted.insert(setting.key().to_string(), value);
+        }
+        Ok(restricted)
+    }
+}
+
+/// The `[profile.release]` table configured by the `plrust.profile_*` GUCs
+pub(crate) fn release_profile() -> eyre::Result<toml::Table> {
+    let mut profile = toml::Table::new();
+    for (setting, value) in gucs::release_profile_settings() {
+        let value = setting
+            .normalize(&toml::Value::String(value))
+            .map_err(|e| eyre::eyre!("{e}, as configured by `plrust.profile_*`"))?;
+        profile.insert(setting.key().to_string(), value);
+    }
+
+    // PL/Rust relies on catching panics from user functions
+    profile.insert("panic".to_string(), "unwind".into());
+    Ok(profile)
+}
+
+/// Validate a function's `[profile]` section against `plrust.allowed_profile_overrides`
+pub(crate) fn restrict_profile(profile: toml::Table) -> eyre::Result<toml::Table> {
+    if profile.is_empty() {
+        return Ok(profile);
+    }
+
+    let allowed = AllowedOverrides::try_from(gucs::allowed_profile_overrides().as_str())
+        .map_err(|e| eyre::eyre!("{e}, in `plrust.allowed_profile_overrides`"))?;
+    Ok(allowed.restrict(profile)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        let s = |s: &str| toml::Value::String(s.to_string());
+
+        assert_eq!(
+            ProfileSetting::OptLevel.normalize(&s("3")),
+            Ok(toml::Value::Integer(3))
+        );
+        assert_eq!(ProfileSetting::OptLevel.normalize(&s("z")), Ok(s("z")));
+        assert_eq!(
+            ProfileSetting::OptLevel.normalize(&toml::Value::Integer(4)),
+            Err(ProfileError::InvalidValue(
+                ProfileSetting::OptLevel,
+                toml::Value::Integer(4)
+            ))
+        );
+        assert_eq!(
+            ProfileSetting::Lto.normalize(&toml::Value::Boolean(true)),
+            Ok(s("fat"))
+        );
+        assert_eq!(ProfileSetting::Lto.normalize(&s("thin")), Ok(s("thin")));
+        assert_eq!(
+            ProfileSetting::CodegenUnits.normalize(&s("1")),
+            Ok(toml::Value::Integer(1))
+        );
+        assert!(ProfileSetting::CodegenUnits
+            .normalize(&toml::Value::Integer(0))
+            .is_err());
+        assert_eq!(
+            ProfileSetting::Debug.normalize(&toml::Value::Integer(1)),
+            Ok(s("limited"))
+        );
+        assert_eq!(
+            ProfileSetting::Strip.normalize(&toml::Value::Boolean(true)),
+            Ok(s("symbols"))
+        );
+    }
+
+    #[test]
+    fn allowed_overrides() {
+        let allowed = AllowedOverrides::try_from("opt-level, lto = off|thin").unwrap();
+
+        let profile: toml::Table = toml::from_str("opt-level = \"s\"\nlto = true").unwrap();
+        assert_eq!(
+            allowed.restrict(profile),
+            Err(ProfileError::ValueNotAllowed(
+                ProfileSetting::Lto,
+                "fat".into(),
+                "\"off\", \"thin\"".into()
+            ))
+        );
+
+        let profile: toml::Table = toml::from_str("opt-level = \"s\"\nlto = \"thin\"").unwrap();
+        let restricted = allowed.restrict(profile).unwrap();
+        assert_eq!(restricted.get("opt-level"), Some(&"s".into()));
+        assert_eq!(restricted.get("lto"), Some(&"thin".into()));
+
+        let profile: toml::Table = toml::from_str("strip = true").unwrap();
+        assert_eq!(
+            allowed.restrict(profile),
+            Err(ProfileError::NotAllowed(ProfileSetting::Strip))
+        );
+
+        let profile: toml::Table = toml::from_str("panic = \"abort\"").unwrap();
+        assert_eq!(
+            allowed.restrict(profile),
+            Err(ProfileError::UnknownSetting("panic".into()))
+        );
+    }
+}
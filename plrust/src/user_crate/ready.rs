@@ -9,7 +9,8 @@ Use of this source code is governed by the PostgreSQL license that can be found
 use libloading::os::unix::{Library, Symbol};
 use pgrx::pg_sys;
 
-use crate::user_crate::CrateState;
+use crate::memory::LibraryMemory;
+use crate::user_crate::{CrateState, SharedObject};
 
 impl CrateState for FnReady {}
 
@@ -24,6 +25,12 @@ pub(crate) struct FnReady {
     #[allow(dead_code)] // We must hold this handle for `symbol`
     library: Library,
     symbol: Symbol<unsafe extern "C" fn(pg_sys::FunctionCallInfo) -> pg_sys::Datum>,
+    /// Whether the library was compiled with `plrust-trusted-pgrx`'s runtime support, without which
+    /// its memory can't be limited
+    runtime_support: bool,
+    /// Declared after `library`, so that what its functions still hold is only forgotten once it's
+    /// closed
+    memory: LibraryMemory,
 
     // used to hang onto the thing where the "shared object bytes" were written
     // mainly, this is to hold the `Memfd` instance on Linux so that we can support
@@ -31,8 +38,10 @@ pub(crate) struct FnReady {
     // of what it dlopen()'d based on the file descriptor number
     //
     // and it's different based on platform!
+    //
+    // when the library was loaded from the shared library cache, it's the cached file we hold
     #[cfg(target_os = "linux")]
-    _file_holder: memfd::Memfd,
+    _file_holder: Option<std::fs::File>,
     #[cfg(not(target_os = "linux"))]
     _file_holder: (),
 }
@@ -44,8 +53,44 @@ impl FnReady {
         db_oid: pg_sys::Oid,
         fn_oid: pg_sys::Oid,
         symbol: Option<String>,
-        shared_object: Vec<u8>,
+        shared_object: SharedObject,
+        runtime_support: bool,
     ) -> eyre::Result<Self> {
+        let shared_object = match shared_object {
+            SharedObject::Bytes(shared_object) => shared_object,
+            SharedObject::Cached(cached) => {
+                tracing::trace!("loading `{}` from the cache", cached.path.display());
+
+                // on Linux, we dlopen() the very file the cache hashed, through its file
+                // descriptor, so it can't have been swapped for another since
+                #[cfg(target_os = "linux")]
+                let (file_holder, library) = {
+                    use std::os::unix::io::AsRawFd;
+
+                    let filename = format!("/proc/self/fd/{}", cached.file.as_raw_fd());
+                    let library = unsafe { Library::new(&filename)? };
+                    (Some(cached.file), library)
+                };
+
+                // elsewhere, the cache never modifies a library once it's been written, so we can
+                // dlopen() it right where it is
+                #[cfg(not(target_os = "linux"))]
+                let (file_holder, library) = ((), unsafe { Library::new(&cached.path)? });
+
+                return unsafe {
+                    Self::new(
+                        generation_number,
+                        db_oid,
+                        fn_oid,
+                        symbol,
+                        library,
+                        file_holder,
+                        runtime_support,
+                    )
+                };
+            }
+        };
+
         #[cfg(target_os = "linux")]
         let (file_holder, library) = {
             // for Linux we write the `shared_object` bytes to an anonymous file of exactly the
@@ -81,7 +126,7 @@ impl FnReady {
             // we need to also return the `Memfd` instance as well as if it gets dropped
             // Linux might re-use its filedescriptor and dlopen() won't open the new library
             // behind it
-            (mfd, library)
+            (Some(mfd.into_file()), library)
         };
 
         #[cfg(not(target_os = "linux"))]
@@ -102,6 +147,30 @@ impl FnReady {
             ((), library)
         };
 
+        unsafe {
+            Self::new(
+                generation_number,
+                db_oid,
+                fn_oid,
+                symbol,
+                library,
+                file_holder,
+                runtime_support,
+            )
+        }
+    }
+
+    /// Finish loading the function by finding its symbol in the now-dlopen()'d `library`
+    unsafe fn new(
+        generation_number: u64,
+        db_oid: pg_sys::Oid,
+        fn_oid: pg_sys::Oid,
+        symbol: Option<String>,
+        library: Library,
+        #[cfg(target_os = "linux")] file_holder: Option<std::fs::File>,
+        #[cfg(not(target_os = "linux"))] file_holder: (),
+        runtime_support: bool,
+    ) -> eyre::Result<Self> {
         let symbol_name = symbol.unwrap_or_else(|| crate::plrust::symbol_name(db_oid, fn_oid));
         let symbol_name = symbol_name + "_wrapper"; // + "_wrapper" b/c pgrx' `#[pg_extern]` adds that
 
@@ -113,11 +182,13 @@ impl FnReady {
             symbol_name,
             library,
             symbol,
+            runtime_support,
+            memory: LibraryMemory::default(),
             _file_holder: file_holder,
         })
     }
 
-    #[tracing::instrument(level = "debug", skip_all, fields(?fcinfo))]
+    #[inline]
     pub(crate) unsafe fn evaluate(&self, fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
         // SAFETY:  First off, `self.symbol` is some function in the dlopened shared library, so
         // FFI into that is inherently unsafe.  Secondly, it's an FFI function, so we need to protect
@@ -138,9 +209,12 @@ impl FnReady {
             library,
             symbol: _,
             symbol_name: _,
+            runtime_support: _,
+            memory,
             _file_holder: _,
         } = self;
         library.close()?;
+        drop(memory);
         Ok(())
     }
 
@@ -148,6 +222,14 @@ impl FnReady {
         &self.symbol_name
     }
 
+    pub(crate) fn runtime_support(&self) -> bool {
+        self.runtime_support
+    }
+
+    pub(crate) fn memory(&self) -> &LibraryMemory {
+        &self.memory
+    }
+
     #[inline]
     pub(crate) fn generation_number(&self) -> u64 {
         self.generation_number
@@ -0,0 +1,383 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+/*!
+Holding allow-listed dependencies to PL/Rust's lints
+
+`cargo` caps the lints of dependencies from a registry at `allow`, so ordinarily a dependency is
+trusted only because an administrator put it on the allow-list.  Crates named in
+`plrust.strict_dependencies` (or all of them, with `*`) are instead compiled on their own by
+`plrustc`, which lifts that cap and forbids `plrust.compile_lints`.  A crate that doesn't pass can't
+be used by any function.
+
+Checking a crate means compiling it, so the outcome is remembered, in `plrust.work_dir`, for each
+version of a crate and the features and lints it was checked with, by the `plrustc` that checked it.
+A new `plrustc` may lint differently, so outcomes from any other are checked again.
+*/
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use color_eyre::{Section, SectionExt};
+use eyre::{eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::cache;
+use crate::error::PlRustError;
+use crate::gucs;
+use crate::user_crate::cargo::{cargo, plrustc};
+use crate::user_crate::lint::{compile_lints, LintSet};
+
+/// A crate in a function's dependency graph that `plrust.strict_dependencies` might apply to
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct StrictCrate {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    /// the features the function's dependency graph enables for the crate
+    pub(crate) features: BTreeSet<String>,
+}
+
+/// The outcome of checking one version of a crate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CheckResult {
+    features: BTreeSet<String>,
+    lints: LintSet,
+    /// the compiler's output, if the crate didn't pass
+    violations: Option<String>,
+}
+
+/// Which crates are held to PL/Rust's lints
+#[derive(Debug)]
+struct StrictPolicy {
+    all: bool,
+    crates: Vec<String>,
+    /// `name` or `name@version`
+    exempt: Vec<String>,
+}
+
+impl StrictPolicy {
+    fn configured() -> Option<Self> {
+        let crates = gucs::strict_dependencies()?;
+        if crates.is_empty() {
+            return None;
+        }
+        Some(Self {
+            all: crates.iter().any(|name| name == "*"),
+            crates,
+            exempt: gucs::strict_dependencies_exempt().unwrap_or_default(),
+        })
+    }
+
+    fn applies_to(&self, name: &str, version: &str) -> bool {
+        let exempt = self.exempt.iter().any(|entry| match entry.split_once('@') {
+            Some((exempt_name, exempt_version)) => {
+                same_crate(exempt_name, name) && exempt_version.trim() == version
+            }
+            None => same_crate(entry, name),
+        });
+        !exempt && (self.all || self.crates.iter().any(|entry| same_crate(entry, name)))
+    }
+}
+
+/// `cargo` considers `-` and `_` to be the same in crate names
+fn same_crate(a: &str, b: &str) -> bool {
+    a.trim().replace('-', "_") == b.replace('-', "_")
+}
+
+/// Check those of `crates` named in `plrust.strict_dependencies`, failing if any of them don't pass
+/// `plrust.compile_lints`
+pub(crate) fn check_strict_dependencies(crates: Vec<StrictCrate>) -> eyre::Result<()> {
+    let Some(policy) = StrictPolicy::configured() else {
+        return Ok(());
+    };
+    let lints = compile_lints();
+    let results_dir = strict_dir().join("results").join(plrustc_version()?);
+
+    let mut violations = Vec::new();
+    for krate in crates
+        .iter()
+        .filter(|krate| policy.applies_to(&krate.name, &krate.version))
+    {
+        if let Some(output) = check(krate, &lints, &results_dir)? {
+            violations.push(format!("`{} {}`:\n{output}", krate.name, krate.version));
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(PlRustError::StrictDependencyViolation)
+            .section(violations.join("\n\n").header("Violations:"))
+            .suggestion(
+                "an administrator can exempt a crate with `plrust.strict_dependencies_exempt`",
+            ))
+    }
+}
+
+/// Check one crate, reusing an earlier outcome, from `results_dir`, for the same features and lints
+fn check(krate: &StrictCrate, lints: &LintSet, results_dir: &Path) -> eyre::Result<Option<String>> {
+    let results_path = results_dir.join(format!("{}-{}.json", krate.name, krate.version));
+    let mut results: Vec<CheckResult> = std::fs::read(&results_path)
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default();
+
+    if let Some(result) = results
+        .iter()
+        .find(|result| result.features == krate.features && &result.lints == lints)
+    {
+        return Ok(result.violations.clone());
+    }
+
+    let violations = compile(krate, lints)?;
+    results.push(CheckResult {
+        features: krate.features.clone(),
+        lints: lints.clone(),
+        violations: violations.clone(),
+    });
+    write_results(&results_path, &results)?;
+    Ok(violations)
+}
+
+/// Identifies the `plrustc` that checks crates, as a hash of what `plrustc -vV` reports, which is
+/// safe to use as a directory name
+fn plrustc_version() -> eyre::Result<String> {
+    let output = plrustc()?
+        .arg("-vV")
+        .output()
+        .wrap_err("`plrustc -vV` execution failure")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return Err(eyre!("`plrustc -vV` failed").section(stderr.header("Stderr:")));
+    }
+    Ok(cache::sha256(&output.stdout))
+}
+
+fn strict_dir() -> PathBuf {
+    gucs::work_dir().join("strict-dependencies")
+}
+
+/// Compile the crate as the sole dependency of an empty library, having `plrustc` forbid `lints`
+/// while compiling it.
+///
+/// Each check gets a target directory of its own, which is removed with the rest of the check
+/// crate.  `cargo` only fingerprints registry crates by their package and features, not by what
+/// `plrustc` was asked to do with them, so a crate built elsewhere with its lints capped would be
+/// considered fresh, and pass without being linted at all
+fn compile(krate: &StrictCrate, lints: &LintSet) -> eyre::Result<Option<String>> {
+    let crate_dir = strict_dir().join(format!(
+        "check-{}-{}-{}",
+        krate.name,
+        krate.version,
+        std::process::id()
+    ));
+    let src_dir = crate_dir.join("src");
+    std::fs::create_dir_all(&src_dir).wrap_err("Could not create strict check directory")?;
+
+    let result = (|| -> eyre::Result<Option<String>> {
+        std::fs::write(src_dir.join("lib.rs"), "").wrap_err("Writing strict check `lib.rs`")?;
+        let cargo_toml = toml::to_string(&check_cargo_toml(krate))
+            .wrap_err("Stringifying strict check `Cargo.toml`")?;
+        std::fs::write(crate_dir.join("Cargo.toml"), cargo_toml)
+            .wrap_err("Writing strict check `Cargo.toml`")?;
+
+        let mut command = cargo(&crate_dir.join("target"), None)?;
+        command.current_dir(&crate_dir);
+        command.arg("check");
+        command.arg("--message-format=json");
+        command.env(
+            "PLRUSTC_STRICT_CRATE",
+            format!("{}@{}", krate.name, krate.version),
+        );
+        command.env(
+            "PLRUSTC_STRICT_LINTS",
+            lints.iter().collect::<Vec<String>>().join(","),
+        );
+
+        let output = command
+            .output()
+            .wrap_err("`cargo check` execution failure")?;
+        if output.status.success() {
+            return Ok(None);
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let errors = crate_errors(krate, &stdout);
+        if errors.is_empty() {
+            // something other than the crate itself failed, so there's no outcome to remember
+            let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+            Err(
+                eyre!("`cargo check` of `{} {}` failed", krate.name, krate.version)
+                    .section(stderr.header("Stderr:")),
+            )
+        } else {
+            Ok(Some(errors.join("\n")))
+        }
+    })();
+
+    std::fs::remove_dir_all(&crate_dir).ok();
+    result
+}
+
+/// A line of `cargo`'s `--message-format=json` output
+#[derive(Debug, Deserialize)]
+struct CargoMessage {
+    reason: String,
+    #[serde(default)]
+    package_id: String,
+    message: Option<CompilerMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompilerMessage {
+    level: String,
+    rendered: Option<String>,
+}
+
+/// The errors the compiler reported for `krate` itself in `cargo`'s JSON `output`
+fn crate_errors(krate: &StrictCrate, output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<CargoMessage>(line).ok())
+        .filter(|message| {
+            message.reason == "compiler-message" && is_package(krate, &message.package_id)
+        })
+        .filter_map(|message| message.message)
+        .filter(|message| message.level == "error")
+        .filter_map(|message| message.rendered)
+        .collect()
+}
+
+/// Whether `package_id` names `krate`, in either the `name version (source)` form of older
+/// versions of `cargo` or the `source#name@version` form of newer ones
+fn is_package(krate: &StrictCrate, package_id: &str) -> bool {
+    package_id.starts_with(&format!("{} {} ", krate.name, krate.version))
+        || package_id.ends_with(&format!("#{}@{}", krate.name, krate.version))
+}
+
+fn check_cargo_toml(krate: &StrictCrate) -> toml::value::Table {
+    let version = format!("={}", krate.version);
+    let features = krate.features.iter().cloned().collect::<Vec<_>>();
+    let mut dependency = toml::toml! {
+        version = version
+        default-features = false
+        features = features
+    };
+    if krate.features.is_empty() {
+        dependency.remove("features");
+    }
+
+    let mut cargo_toml = toml::toml! {
+        [package]
+        edition = "2021"
+        name = "plrust_strict_check"
+        version = "0.0.0"
+        publish = false
+    };
+    let mut dependencies = toml::value::Table::new();
+    dependencies.insert(krate.name.clone(), toml::Value::Table(dependency));
+    cargo_toml.insert("dependencies".into(), toml::Value::Table(dependencies));
+    cargo_toml
+}
+
+/// Write through a temporary file, so that concurrent backends never see a partial file
+fn write_results(path: &Path, results: &[CheckResult]) -> eyre::Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| eyre!("strict check results path has no parent directory"))?;
+    std::fs::create_dir_all(dir).wrap_err("Could not create strict check results directory")?;
+    let tmp = path.with_extension(format!("json.{}", std::process::id()));
+    std::fs::write(
+        &tmp,
+        serde_json::to_vec(results).wrap_err("Serializing strict check results")?,
+    )
+    .wrap_err("Writing strict check results")?;
+    std::fs::rename(&tmp, path).wrap_err("Writing strict check results")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(crates: &[&str], exempt: &[&str]) -> StrictPolicy {
+        StrictPolicy {
+            all: crates.contains(&"*"),
+            crates: crates.iter().map(|s| s.to_string()).collect(),
+            exempt: exempt.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn strict_policy() {
+        let named = policy(&["owo-colors", "rand"], &["rand@0.8.4"]);
+        assert!(named.applies_to("owo-colors", "3.5.0"));
+        assert!(named.applies_to("owo_colors", "3.5.0"));
+        assert!(named.applies_to("rand", "0.8.5"));
+        assert!(!named.applies_to("rand", "0.8.4"));
+        assert!(!named.applies_to("serde", "1.0.0"));
+
+        let all = policy(&["*"], &["serde", "rand@0.8.5"]);
+        assert!(all.applies_to("owo-colors", "3.5.0"));
+        assert!(all.applies_to("rand", "0.8.4"));
+        assert!(!all.applies_to("rand", "0.8.5"));
+        assert!(!all.applies_to("serde", "1.0.0"));
+    }
+
+    #[test]
+    fn crate_errors_from_cargo() {
+        let krate = StrictCrate {
+            name: "leaky".into(),
+            version: "0.1.0".into(),
+            features: BTreeSet::new(),
+        };
+        let output = [
+            r#"{"reason":"compiler-artifact","package_id":"libc 0.2.150 (registry+https://github.com/rust-lang/crates.io-index)"}"#,
+            r#"{"reason":"compiler-message","package_id":"leaky 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)","message":{"level":"error","rendered":"error: usage of an `unsafe` block"}}"#,
+            r#"{"reason":"compiler-message","package_id":"registry+https://github.com/rust-lang/crates.io-index#leaky@0.1.0","message":{"level":"warning","rendered":"warning: unused import"}}"#,
+            r#"{"reason":"compiler-message","package_id":"registry+https://github.com/rust-lang/crates.io-index#leaky@0.1.0","message":{"level":"error","rendered":"error: aborting due to previous error"}}"#,
+            r#"{"reason":"compiler-message","package_id":"leaky-sys 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)","message":{"level":"error","rendered":"error: linking failed"}}"#,
+            r#"{"reason":"build-finished","success":false}"#,
+        ]
+        .join("\n");
+        assert_eq!(
+            crate_errors(&krate, &output),
+            vec![
+                "error: usage of an `unsafe` block",
+                "error: aborting due to previous error"
+            ]
+        );
+        assert!(crate_errors(&krate, r#"{"reason":"build-finished","success":false}"#).is_empty());
+    }
+
+    #[test]
+    fn check_crate_manifest() {
+        let krate = StrictCrate {
+            name: "tokio".into(),
+            version: "1.19.2".into(),
+            features: ["net".to_string(), "rt".to_string()].into(),
+        };
+        let cargo_toml = check_cargo_toml(&krate);
+        let dependency = &cargo_toml["dependencies"]["tokio"];
+        assert_eq!(dependency["version"].as_str(), Some("=1.19.2"));
+        assert_eq!(dependency["default-features"].as_bool(), Some(false));
+        assert_eq!(
+            dependency["features"],
+            toml::Value::Array(vec!["net".into(), "rt".into()])
+        );
+
+        let krate = StrictCrate {
+            features: BTreeSet::new(),
+            ..krate
+        };
+        let cargo_toml = check_cargo_toml(&krate);
+        assert!(cargo_toml["dependencies"]["tokio"]
+            .get("features")
+            .is_none());
+    }
+}
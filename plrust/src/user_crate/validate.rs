@@ -9,14 +9,14 @@ use pgrx::pg_sys;
 
 use crate::error::PlRustError;
 use crate::user_crate::lint::{required_lints, LintSet};
-use crate::user_crate::{CrateState, FnReady};
+use crate::user_crate::{CrateState, FnReady, SharedObject};
 
 pub(crate) struct FnValidate {
     generation_number: u64,
     db_oid: pg_sys::Oid,
     fn_oid: pg_sys::Oid,
     symbol: Option<String>,
-    shared_object: Vec<u8>,
+    shared_object: SharedObject,
 }
 
 impl CrateState for FnValidate {}
@@ -28,7 +28,7 @@ impl FnValidate {
         db_oid: pg_sys::Oid,
         fn_oid: pg_sys::Oid,
         symbol: Option<String>,
-        shared_object: Vec<u8>,
+        shared_object: SharedObject,
         lints: LintSet,
     ) -> eyre::Result<Self> {
         // if the set of lints we're validating don't include every required lint, we raise an error
@@ -50,7 +50,7 @@ impl FnValidate {
         })
     }
 
-    pub(crate) unsafe fn load(self) -> eyre::Result<FnReady> {
+    pub(crate) unsafe fn load(self, runtime_support: bool) -> eyre::Result<FnReady> {
         unsafe {
             // SAFETY:  Caller is responsible for ensuring self.shared_object points to the proper
             // shared library to be loaded
@@ -60,6 +60,7 @@ impl FnValidate {
                 self.fn_oid,
                 self.symbol,
                 self.shared_object,
+                runtime_support,
             )
         }
     }
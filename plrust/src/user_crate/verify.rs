@@ -32,6 +32,7 @@ use std::{
 use eyre::{eyre, WrapErr};
 use pgrx::pg_sys;
 
+use crate::user_crate::audit::audit_dependencies;
 use crate::user_crate::cargo::cargo;
 use crate::user_crate::lint::LintSet;
 use crate::user_crate::{CrateState, FnBuild, PlRustError};
@@ -93,6 +94,9 @@ impl FnVerify {
         let output = command.output().wrap_err("verification failure")?;
 
         if output.status.success() {
+            // the dependencies the user asked for are allowed, but what they depend on may not be
+            let dependencies = audit_dependencies(&self.crate_dir, cargo_target_dir)?;
+
             Ok((
                 FnBuild::new(
                     self.generation_number,
@@ -101,6 +105,7 @@ impl FnVerify {
                     self.crate_name,
                     self.crate_dir,
                     self.lints,
+                    dependencies,
                 ),
                 output,
             ))
@@ -22,7 +22,7 @@ extern crate rustc_span;
 use rustc_driver::Callbacks;
 use rustc_error_messages::DiagnosticMessage;
 use rustc_interface::interface;
-use rustc_session::config::ErrorOutputType;
+use rustc_session::config::{ErrorOutputType, OomStrategy};
 use rustc_session::parse::ParseSess;
 use rustc_session::EarlyErrorHandler;
 use rustc_span::source_map::FileLoader;
@@ -31,6 +31,8 @@ use std::path::Path;
 
 const PLRUSTC_USER_CRATE_NAME: &str = "PLRUSTC_USER_CRATE_NAME";
 const PLRUSTC_USER_CRATE_ALLOWED_SOURCE_PATHS: &str = "PLRUSTC_USER_CRATE_ALLOWED_SOURCE_PATHS";
+const PLRUSTC_STRICT_CRATE: &str = "PLRUSTC_STRICT_CRATE";
+const PLRUSTC_STRICT_LINTS: &str = "PLRUSTC_STRICT_LINTS";
 
 mod lints;
 
@@ -45,6 +47,12 @@ impl Callbacks for PlrustcCallbacks {
         config.parse_sess_created = Some(Box::new(move |parse_sess| {
             cfg.track(parse_sess);
         }));
+        if self.config.compiling_user_crate() {
+            // `plrust` limits how much memory user functions may allocate.  When it refuses an
+            // allocation, the user function must panic, which becomes a Postgres ERROR, rather than
+            // abort the entire backend
+            config.opts.unstable_opts.oom = OomStrategy::Panic;
+        }
         if self.lints_enabled {
             let previous = config.register_lints.take();
             config.register_lints = Some(Box::new(move |sess, lint_store| {
@@ -62,9 +70,12 @@ fn main() {
     let handler = &EarlyErrorHandler::new(ErrorOutputType::default());
     rustc_driver::init_rustc_env_logger(handler);
     std::process::exit(rustc_driver::catch_with_exit_code(move || {
-        let args =
+        let mut args =
             rustc_driver::args::arg_expand_all(handler, &std::env::args().collect::<Vec<_>>());
         let config = PlrustcConfig::from_env_and_args(&args);
+        if config.compiling_strict_crate() {
+            config.apply_strict_lints(&mut args);
+        }
         run_compiler(
             args,
             &mut PlrustcCallbacks {
@@ -84,6 +95,12 @@ struct PlrustcConfig {
     plrust_user_crate_name: Option<String>,
     // PLRUSTC_USER_CRATE_ALLOWED_SOURCE_PATHS
     plrust_user_crate_allowed_source_paths: Option<String>,
+    // CARGO_PKG_NAME and CARGO_PKG_VERSION, which cargo sets to the package being compiled
+    cargo_pkg: Option<String>,
+    // PLRUSTC_STRICT_CRATE, as `name@version`
+    plrust_strict_crate: Option<String>,
+    // PLRUSTC_STRICT_LINTS
+    plrust_strict_lints: Option<String>,
 }
 
 impl PlrustcConfig {
@@ -95,6 +112,46 @@ impl PlrustcConfig {
                 PLRUSTC_USER_CRATE_ALLOWED_SOURCE_PATHS,
             )
             .ok(),
+            cargo_pkg: std::env::var("CARGO_PKG_NAME")
+                .ok()
+                .zip(std::env::var("CARGO_PKG_VERSION").ok())
+                .map(|(name, version)| format!("{name}@{version}")),
+            plrust_strict_crate: std::env::var(PLRUSTC_STRICT_CRATE).ok(),
+            plrust_strict_lints: std::env::var(PLRUSTC_STRICT_LINTS).ok(),
+        }
+    }
+
+    /// Whether this is the library of a dependency that `plrust` wants held to its lints.  Its
+    /// build script, if it has one, is compiled from the same package but is a different crate
+    fn compiling_strict_crate(&self) -> bool {
+        if let (Some(current), Some(package), Some(strict)) = (
+            self.crate_name_arg.as_deref(),
+            self.cargo_pkg.as_deref(),
+            self.plrust_strict_crate.as_deref(),
+        ) {
+            package == strict && !current.starts_with("build_script_")
+        } else {
+            false
+        }
+    }
+
+    /// `cargo` caps the lints of dependencies from a registry at `allow`, so lift that, then forbid
+    /// the lints `plrust` asked for
+    fn apply_strict_lints(&self, args: &mut Vec<String>) {
+        let mut i = 0;
+        while i < args.len() {
+            if args[i] == "--cap-lints" {
+                args.drain(i..(i + 2).min(args.len()));
+            } else if args[i].starts_with("--cap-lints=") {
+                args.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        let lints = self.plrust_strict_lints.as_deref().unwrap_or_default();
+        for lint in lints.split(',').map(str::trim).filter(|l| !l.is_empty()) {
+            args.push(format!("-F{lint}"));
         }
     }
 
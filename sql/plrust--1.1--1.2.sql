@@ -0,0 +1,213 @@
+/*
+Portions Copyright 2021-2025 Technology Concepts & Design, Inc.
+
+All rights reserved.
+
+Use of this source code is governed by the PostgreSQL license that can be found in the LICENSE.md file.
+*/
+
+-- plrust::compile_missing_targets
+CREATE FUNCTION compile_missing_targets("func" regprocedure DEFAULT NULL) RETURNS TABLE (
+    "fn_oid" oid,
+    "target" TEXT
+) LANGUAGE c AS 'MODULE_PATHNAME', 'compile_missing_targets_wrapper';
+
+-- plrust::function_info
+CREATE FUNCTION function_info("func" regprocedure) RETURNS TABLE (
+    "source" TEXT,
+    "dependencies" TEXT,
+    "trusted_pgrx_version" TEXT,
+    "pg_major_version" INT,
+    "capabilities" TEXT[],
+    "target" TEXT,
+    "symbol" TEXT,
+    "binary_size" BIGINT,
+    "stored_size" BIGINT,
+    "lints" TEXT[],
+    "error" TEXT
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'function_info_wrapper';
+
+-- functions_view
+CREATE VIEW functions AS
+SELECT p.oid::regprocedure AS "function",
+       pg_catalog.pg_get_userbyid(p.proowner) AS "owner",
+       info.*
+  FROM pg_catalog.pg_proc p
+  INNER JOIN pg_catalog.pg_language l ON p.prolang = l.oid
+  CROSS JOIN LATERAL function_info(p.oid::regprocedure) info
+ WHERE l.lanname = 'plrust';
+
+-- plrust::generated_crate_for_function
+CREATE FUNCTION generated_crate("func" regprocedure) RETURNS TABLE (
+    "lib_rs" TEXT,
+    "cargo_toml" TEXT
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'generated_crate_for_function_wrapper';
+
+-- plrust::generated_crate_for_source
+CREATE FUNCTION generated_crate(
+    "source" TEXT,
+    "argument_names" TEXT[] DEFAULT '{}',
+    "argument_types" regtype[] DEFAULT '{}',
+    "return_type" regtype DEFAULT 'void',
+    "returns_set" bool DEFAULT false,
+    "strict" bool DEFAULT false
+) RETURNS TABLE (
+    "lib_rs" TEXT,
+    "cargo_toml" TEXT
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'generated_crate_for_source_wrapper';
+
+-- plrust::clear_shared_library_cache
+CREATE FUNCTION clear_shared_library_cache() RETURNS BIGINT
+    STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'clear_shared_library_cache_wrapper';
+
+-- plrust::function_stats
+CREATE FUNCTION function_stats() RETURNS TABLE (
+    "funcid" oid,
+    "calls" BIGINT,
+    "errors" BIGINT,
+    "total_time" DOUBLE PRECISION,
+    "max_time" DOUBLE PRECISION,
+    "loads" BIGINT,
+    "load_time" DOUBLE PRECISION,
+    "compiles" BIGINT,
+    "compile_time" DOUBLE PRECISION
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'function_stats_wrapper';
+
+-- stat_functions_view
+CREATE VIEW stat_functions AS
+SELECT s.funcid,
+       n.nspname AS schemaname,
+       p.proname AS funcname,
+       s.calls,
+       s.errors,
+       s.total_time,
+       s.max_time,
+       s.loads,
+       s.load_time,
+       s.compiles,
+       s.compile_time
+  FROM function_stats() s
+  INNER JOIN pg_catalog.pg_proc p ON p.oid = s.funcid
+  INNER JOIN pg_catalog.pg_namespace n ON p.pronamespace = n.oid;
+
+-- plrust::stat_functions_reset
+CREATE FUNCTION stat_functions_reset("func" regprocedure DEFAULT NULL) RETURNS void
+    LANGUAGE c AS 'MODULE_PATHNAME', 'stat_functions_reset_wrapper';
+
+-- plrust::loaded_functions
+CREATE FUNCTION loaded_functions() RETURNS TABLE (
+    "function" regprocedure,
+    "symbol" TEXT,
+    "calls" BIGINT,
+    "loaded_at" timestamptz
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'loaded_functions_wrapper';
+
+-- plrust::unload_functions
+CREATE FUNCTION unload_functions("func" regprocedure DEFAULT NULL) RETURNS bigint
+    LANGUAGE c AS 'MODULE_PATHNAME', 'unload_functions_wrapper';
+
+-- plrust::preload_functions
+CREATE FUNCTION preload_functions("functions" TEXT DEFAULT NULL) RETURNS BIGINT
+    LANGUAGE c AS 'MODULE_PATHNAME', 'preload_functions_wrapper';
+
+-- dependency_allowlist_table
+CREATE TABLE dependency_allowlist (
+    "name" TEXT NOT NULL,
+    "version_req" TEXT NOT NULL,
+    "features" TEXT[] NOT NULL DEFAULT '{}',
+    "default_features" bool NOT NULL DEFAULT true,
+    "role" regrole NOT NULL DEFAULT '0',
+    PRIMARY KEY ("name", "version_req", "role")
+);
+COMMENT ON TABLE dependency_allowlist IS 'The dependency allow-list, when `plrust.allowed_dependencies_table` is on';
+COMMENT ON COLUMN dependency_allowlist.role IS 'The role whose members may use the entry, or `-` for every role';
+
+-- functions are compiled as their owner, who must be able to read the allow-list
+GRANT SELECT ON dependency_allowlist TO PUBLIC;
+
+SELECT pg_catalog.pg_extension_config_dump('dependency_allowlist', '');
+
+-- plrust::allow_dependency
+CREATE FUNCTION allow_dependency(
+    "name" TEXT,
+    "version_req" TEXT,
+    "features" TEXT[] DEFAULT '{}',
+    "default_features" bool DEFAULT true,
+    "role" TEXT DEFAULT 'public'
+) RETURNS void STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'allow_dependency_wrapper';
+
+-- plrust::revoke_dependency
+CREATE FUNCTION revoke_dependency(
+    "name" TEXT,
+    "version_req" TEXT DEFAULT NULL,
+    "role" TEXT DEFAULT NULL
+) RETURNS bigint LANGUAGE c AS 'MODULE_PATHNAME', 'revoke_dependency_wrapper';
+
+-- plrust::validate_allowlist
+CREATE FUNCTION validate_allowlist() RETURNS TABLE (
+    "name" TEXT,
+    "entry" TEXT,
+    "valid" bool,
+    "version_req" TEXT,
+    "error" TEXT
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'validate_allowlist_wrapper';
+
+-- plrust::check_local_crate_source
+CREATE FUNCTION check_local_crate_source() RETURNS TABLE (
+    "name" TEXT,
+    "version_req" TEXT
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'check_local_crate_source_wrapper';
+
+-- plrust::dependency_impact
+CREATE FUNCTION dependency_impact(
+    "name" TEXT,
+    "version_req" TEXT DEFAULT NULL,
+    "narrowed_to" TEXT DEFAULT NULL
+) RETURNS TABLE (
+    "function" regprocedure,
+    "owner" regrole,
+    "version" TEXT
+) LANGUAGE c AS 'MODULE_PATHNAME', 'dependency_impact_wrapper';
+
+-- plrust::resolved_dependencies
+CREATE FUNCTION resolved_dependencies("func" regprocedure) RETURNS TABLE (
+    "name" TEXT,
+    "version" TEXT,
+    "direct" bool
+) STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'resolved_dependencies_wrapper';
+
+-- function_dependencies_view
+CREATE VIEW function_dependencies AS
+SELECT p.oid::regprocedure AS "function",
+       pg_catalog.pg_get_userbyid(p.proowner) AS "owner",
+       d.*
+  FROM pg_catalog.pg_proc p
+  INNER JOIN pg_catalog.pg_language l ON p.prolang = l.oid
+  CROSS JOIN LATERAL resolved_dependencies(p.oid::regprocedure) d
+ WHERE l.lanname = 'plrust';
+
+-- libraries_table
+CREATE TABLE libraries (
+    "name" TEXT NOT NULL PRIMARY KEY,
+    "version" TEXT NOT NULL,
+    "source" TEXT NOT NULL,
+    "dependencies" TEXT NOT NULL DEFAULT ''
+);
+COMMENT ON TABLE libraries IS 'Library crates PL/Rust functions may declare as dependencies';
+
+-- functions are compiled as their owner, who must be able to read the libraries they use
+GRANT SELECT ON libraries TO PUBLIC;
+
+SELECT pg_catalog.pg_extension_config_dump('libraries', '');
+
+-- plrust::register_library
+CREATE FUNCTION register_library(
+    "name" TEXT,
+    "source" TEXT,
+    "version" TEXT DEFAULT '0.1.0',
+    "dependencies" TEXT DEFAULT NULL
+) RETURNS void LANGUAGE c AS 'MODULE_PATHNAME', 'register_library_wrapper';
+
+-- plrust::unregister_library
+CREATE FUNCTION unregister_library("name" TEXT) RETURNS bool
+    STRICT LANGUAGE c AS 'MODULE_PATHNAME', 'unregister_library_wrapper';